@@ -1,9 +1,11 @@
 extern crate chrono;
 
+use std::collections::HashMap;
+
 use chrono::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AkkaSettings {
     pub tree_address: String,
     pub status_address: String,
@@ -13,14 +15,32 @@ pub struct AkkaSettings {
     pub dead_letters_window: u64,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct ActorTreeNode {
     pub name: String,
     pub parent: Option<usize>,
     pub id: usize,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+impl ActorTreeNode {
+    /// Paths of actors by id, e.g. `/user/orders/worker-1`.
+    pub fn paths(actors: &[ActorTreeNode]) -> HashMap<usize, String> {
+        let by_id: HashMap<usize, &ActorTreeNode> = actors.iter().map(|a| (a.id, a)).collect();
+        actors.iter().map(|a| {
+            let mut names = vec![a.name.as_str()];
+            let mut parent = a.parent.and_then(|p| by_id.get(&p));
+            // the length bound guards against cycles in malformed input
+            while let (Some(p), true) = (parent, names.len() <= actors.len()) {
+                names.push(p.name.as_str());
+                parent = p.parent.and_then(|p| by_id.get(&p));
+            }
+            names.reverse();
+            (a.id, format!("/{}", names.join("/")))
+        }).collect()
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct DeadLettersSnapshot {
     #[serde(rename = "deadLetters")]
     pub dead_letters: Vec<Timestamped<DeadLettersMessage>>,
@@ -28,20 +48,20 @@ pub struct DeadLettersSnapshot {
     pub dropped: Vec<Timestamped<DroppedMessage>>,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct Timestamped<T> {
     pub value: T,
     pub timestamp: u64,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct DeadLettersMessage {
     pub message: String,
     pub sender: String,
     pub recipient: String,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct DroppedMessage {
     pub message: String,
     pub sender: String,
@@ -49,14 +69,14 @@ pub struct DroppedMessage {
     pub reason: String,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct UnhandledMessage {
     pub message: String,
     pub sender: String,
     pub recipient: String,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct DeadLettersWindow {
     #[serde(rename = "withinMillis")]
     pub within_millis: u64,
@@ -66,14 +86,14 @@ pub struct DeadLettersWindow {
     pub dropped: DeadLettersWindowData,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct DeadLettersWindowData {
     pub count: u32,
     #[serde(rename = "isMinimumEstimate")]
     pub is_min_estimate: bool,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct DeadLettersMetrics {
     pub snapshot: DeadLettersSnapshot,
     pub window: DeadLettersWindow,
@@ -88,23 +108,23 @@ pub struct DeadLettersUIMessage {
     pub reason: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ActorSystemStatus {
-    #[serde(rename(deserialize = "actorCount"))]
+    #[serde(rename = "actorCount")]
     pub actor_count: u64,
     pub uptime: u64,
-    #[serde(rename(deserialize = "startTime"))]
+    #[serde(rename = "startTime")]
     pub start_time: u64,
 }
 
 
 impl DeadLettersWindow {
     pub fn max(&self) -> u32 {
-        vec![self.dead_letters.count, self.unhandled.count, self.dropped.count].iter().max().map(|x| x.to_owned()).unwrap_or(0)
+        [self.dead_letters.count, self.unhandled.count, self.dropped.count].iter().max().map(|x| x.to_owned()).unwrap_or(0)
     }
 
     pub fn total(&self) -> u32 {
-        vec![self.dead_letters.count, self.unhandled.count, self.dropped.count].iter().sum()
+        [self.dead_letters.count, self.unhandled.count, self.dropped.count].iter().sum()
     }
 }
 
@@ -145,6 +165,22 @@ impl DroppedMessage {
 }
 
 impl DeadLettersUIMessage {
+    /// Whether an actor ref, e.g. `Actor[akka://orders/user/worker#1234]`, points at the actor with the given path.
+    /// The path has to follow the address of the actor system, e.g. `akka.tcp://orders@host:2552`,
+    /// and children of the actor don't count.
+    pub fn refers_to(actor_ref: &str, path: &str) -> bool {
+        let address = match actor_ref.find("://") {
+            Some(n) => &actor_ref[n + 3..],
+            None => return false,
+        };
+        let actor_path = match address.find('/') {
+            Some(n) => &address[n..],
+            None => return false,
+        };
+        actor_path.strip_prefix(path)
+            .is_some_and(|rest| rest.chars().next().is_none_or(|c| matches!(c, '#' | ']' | ' ')))
+    }
+
     pub fn summary(&self) -> String {
         let now = Local::now().timestamp();
         let diff = now - (self.timestamp / 1000) as i64;
@@ -0,0 +1,522 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use chrono::prelude::*;
+use serde::Deserialize;
+
+use crate::app::TargetState;
+use crate::fetcher::FetcherRequest;
+
+/// Alert rules and notification settings, from the `alerts` section of the config file.
+///
+/// Example (TOML):
+///
+///   ```toml
+///   [alerts]
+///   bell = true
+///
+///   [[alerts.rules]]
+///   name = "threads waiting for connections"
+///   metric = "hikari-waiting"
+///   above = 0
+///   for = 10
+///   ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AlertsConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    /// Rings the terminal bell when an alert fires.
+    #[serde(default)]
+    pub bell: bool,
+    /// Shell command run when an alert fires, with alert details in `PANOPTICON_ALERT*` environment variables.
+    pub command: Option<String>,
+}
+
+/// Fires when a condition on a metric holds for a while.
+/// Exactly one of `above`, `above_percent_of_max` and `growing` has to be set.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    pub metric: AlertMetric,
+    pub above: Option<f64>,
+    /// Percentage of the configured maximum of the metric, e.g. of max queue size for `slick-queue-size`.
+    pub above_percent_of_max: Option<f64>,
+    /// Whether the metric keeps growing, i.e. doesn't drop between samples and ends up higher than it started.
+    #[serde(default)]
+    pub growing: bool,
+    /// Seconds the condition has to hold before the alert fires.
+    #[serde(rename = "for", default)]
+    pub for_secs: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlertMetric {
+    SlickActiveThreads,
+    SlickQueueSize,
+    HikariTotal,
+    HikariActive,
+    HikariIdle,
+    HikariWaiting,
+    /// Fibers that aren't done yet.
+    Fibers,
+    FibersRunning,
+    FibersSuspended,
+    Actors,
+    /// Dead letters within the dead letters window.
+    DeadLetters,
+    Unhandled,
+    Dropped,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Condition {
+    Above(f64),
+    AbovePercentOfMax(f64),
+    Growing,
+}
+
+/// Latest value of a metric. Database metrics have a sample per pool.
+struct Sample {
+    pool: Option<String>,
+    value: f64,
+    max: Option<f64>,
+}
+
+impl AlertMetric {
+    /// Request that brings new samples of the metric.
+    fn request(self) -> FetcherRequest {
+        match self {
+            AlertMetric::SlickActiveThreads | AlertMetric::SlickQueueSize => FetcherRequest::SlickMetrics,
+            AlertMetric::HikariTotal | AlertMetric::HikariActive | AlertMetric::HikariIdle | AlertMetric::HikariWaiting =>
+                FetcherRequest::HikariMetrics,
+            AlertMetric::Fibers | AlertMetric::FibersRunning | AlertMetric::FibersSuspended => FetcherRequest::FiberCount,
+            AlertMetric::Actors => FetcherRequest::ActorSystemStatus,
+            AlertMetric::DeadLetters | AlertMetric::Unhandled | AlertMetric::Dropped => FetcherRequest::DeadLetters,
+        }
+    }
+
+    fn has_max(self) -> bool {
+        self.request() == FetcherRequest::SlickMetrics || self.request() == FetcherRequest::HikariMetrics
+    }
+
+    fn samples(self, target: &TargetState) -> Vec<Sample> {
+        let single = |value: Option<f64>| value.map(|value| Sample { pool: None, value, max: None }).into_iter().collect();
+        // limits are 0 until configs are loaded
+        let max = |m: i32| Some(m as f64).filter(|m| *m > 0.0);
+        let pools = target.slick.iter().flat_map(|s| s.pool_metrics.iter());
+        match self {
+            AlertMetric::SlickActiveThreads | AlertMetric::SlickQueueSize => pools
+                .filter(|(_, db)| db.has_slick)
+                .filter_map(|(pool, db)| db.slick_metrics.back().map(|m| {
+                    let (value, limit) = match self {
+                        AlertMetric::SlickActiveThreads => (m.active_threads, db.slick_config.max_threads),
+                        _ => (m.queue_size, db.slick_config.max_queue_size),
+                    };
+                    Sample { pool: Some(pool.clone()), value: value as f64, max: max(limit) }
+                }))
+                .collect(),
+            AlertMetric::HikariTotal | AlertMetric::HikariActive | AlertMetric::HikariIdle | AlertMetric::HikariWaiting => pools
+                .filter(|(_, db)| db.has_hikari)
+                .filter_map(|(pool, db)| db.hikari_metrics.back().map(|m| {
+                    let value = match self {
+                        AlertMetric::HikariTotal => m.total,
+                        AlertMetric::HikariActive => m.active,
+                        AlertMetric::HikariIdle => m.idle,
+                        _ => m.waiting,
+                    };
+                    let limit = db.hikari_config.as_ref().map_or(0, |c| c.maximum_pool_size);
+                    Sample { pool: Some(pool.clone()), value: value as f64, max: max(limit) }
+                }))
+                .collect(),
+            AlertMetric::Fibers | AlertMetric::FibersRunning | AlertMetric::FibersSuspended =>
+                single(target.zmx.as_ref().and_then(|z| z.fiber_counts.back()).map(|c| match self {
+                    AlertMetric::Fibers => c.running + c.suspended + c.finishing,
+                    AlertMetric::FibersRunning => c.running,
+                    _ => c.suspended,
+                } as f64)),
+            AlertMetric::Actors =>
+                single(target.akka.as_ref().and_then(|a| a.actor_counts.back()).map(|c| *c as f64)),
+            AlertMetric::DeadLetters | AlertMetric::Unhandled | AlertMetric::Dropped =>
+                single(target.akka.as_ref().and_then(|a| a.dead_letters_windows.back()).map(|w| match self {
+                    AlertMetric::DeadLetters => w.dead_letters.count,
+                    AlertMetric::Unhandled => w.unhandled.count,
+                    _ => w.dropped.count,
+                } as f64)),
+        }
+    }
+}
+
+impl fmt::Display for AlertMetric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AlertMetric::SlickActiveThreads => "slick-active-threads",
+            AlertMetric::SlickQueueSize => "slick-queue-size",
+            AlertMetric::HikariTotal => "hikari-total",
+            AlertMetric::HikariActive => "hikari-active",
+            AlertMetric::HikariIdle => "hikari-idle",
+            AlertMetric::HikariWaiting => "hikari-waiting",
+            AlertMetric::Fibers => "fibers",
+            AlertMetric::FibersRunning => "fibers-running",
+            AlertMetric::FibersSuspended => "fibers-suspended",
+            AlertMetric::Actors => "actors",
+            AlertMetric::DeadLetters => "dead-letters",
+            AlertMetric::Unhandled => "unhandled",
+            AlertMetric::Dropped => "dropped",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl AlertRule {
+    fn condition(&self) -> Result<Condition, String> {
+        match (self.above, self.above_percent_of_max, self.growing) {
+            (Some(v), None, false) => Ok(Condition::Above(v)),
+            (None, Some(p), false) if self.metric.has_max() => Ok(Condition::AbovePercentOfMax(p)),
+            (None, Some(_), false) => Err(format!("Alert rule {}: {} has no maximum to compare with", self.name, self.metric)),
+            (None, None, true) => Ok(Condition::Growing),
+            _ => Err(format!("Alert rule {} needs exactly one of above, above-percent-of-max or growing", self.name)),
+        }
+    }
+
+    fn describe(&self, condition: Condition) -> String {
+        let condition = match condition {
+            Condition::Above(v) => format!("above {}", v),
+            Condition::AbovePercentOfMax(p) => format!("above {}% of max", p),
+            Condition::Growing => "growing".to_owned(),
+        };
+        match self.for_secs {
+            0 => condition,
+            secs => format!("{} for {}s", condition, secs),
+        }
+    }
+}
+
+/// A rule that fired for a target, and a pool in case of database metrics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    pub target: String,
+    pub pool: Option<String>,
+    pub metric: AlertMetric,
+    pub value: f64,
+    pub condition: String,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pool = self.pool.as_ref().map_or("".to_owned(), |p| format!(", pool {}", p));
+        write!(f, "{} ({}{}): {} is {}, {}", self.rule, self.target, pool, self.metric, self.value, self.condition)
+    }
+}
+
+/// An alert firing or resolving.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertEvent {
+    pub time: DateTime<Local>,
+    pub fired: bool,
+    pub alert: Alert,
+}
+
+#[derive(Default)]
+struct RuleState {
+    previous: Option<f64>,
+    /// When the condition started to hold, with the value at that time.
+    since: Option<(DateTime<Local>, f64)>,
+    firing: Option<Alert>,
+}
+
+/// Evaluates alert rules on new samples and keeps track of firing alerts.
+#[derive(Default)]
+pub struct Alerts {
+    rules: Vec<(AlertRule, Condition)>,
+    bell: bool,
+    command: Option<String>,
+    /// Keyed by target, rule index and pool.
+    states: BTreeMap<(String, usize, Option<String>), RuleState>,
+    /// Most recent events first.
+    pub history: VecDeque<AlertEvent>,
+    /// Fired alerts that haven't been notified about yet.
+    pending: Vec<Alert>,
+}
+
+impl Alerts {
+    pub const MAX_HISTORY: usize = 100;
+
+    pub fn new(config: &AlertsConfig) -> Result<Alerts, String> {
+        let rules = config.rules.iter()
+            .map(|r| r.condition().map(|c| (r.clone(), c)))
+            .collect::<Result<Vec<(AlertRule, Condition)>, String>>()?;
+        Ok(Alerts {
+            rules,
+            bell: config.bell,
+            command: config.command.clone(),
+            states: BTreeMap::new(),
+            history: VecDeque::new(),
+            pending: vec![],
+        })
+    }
+
+    pub fn has_rules(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    pub fn firing(&self) -> Vec<&Alert> {
+        self.states.values().filter_map(|s| s.firing.as_ref()).collect()
+    }
+
+    /// Evaluates rules of metrics that a successful response of `request` brought new samples of.
+    /// Alerts of pools that have no sample anymore are resolved.
+    pub fn on_response(&mut self, target_name: &str, target: &TargetState, request: &FetcherRequest, now: DateTime<Local>) {
+        for (n, (rule, condition)) in self.rules.iter().enumerate() {
+            if rule.metric.request() != *request {
+                continue;
+            }
+            let samples = rule.metric.samples(target);
+            Alerts::resolve(&mut self.states, &mut self.history, now, |(t, r, pool)| {
+                t == target_name && *r == n && samples.iter().all(|s| s.pool != *pool)
+            });
+            for sample in samples {
+                let state = self.states.entry((target_name.to_owned(), n, sample.pool.clone())).or_default();
+                let holds = match *condition {
+                    Condition::Above(v) => sample.value > v,
+                    Condition::AbovePercentOfMax(p) => sample.max.is_some_and(|m| sample.value > m * p / 100.0),
+                    Condition::Growing => state.previous.is_some_and(|p| sample.value >= p),
+                };
+                if !holds {
+                    state.since = None;
+                } else if state.since.is_none() {
+                    state.since = Some((now, state.previous.unwrap_or(sample.value)));
+                }
+                state.previous = Some(sample.value);
+
+                let fires = state.since.is_some_and(|(since, start)| {
+                    now.signed_duration_since(since) >= chrono::Duration::seconds(rule.for_secs as i64)
+                        && (*condition != Condition::Growing || sample.value > start)
+                });
+                let alert = Alert {
+                    rule: rule.name.clone(),
+                    target: target_name.to_owned(),
+                    pool: sample.pool,
+                    metric: rule.metric,
+                    value: sample.value,
+                    condition: rule.describe(*condition),
+                };
+                match (fires, state.firing.is_some()) {
+                    (true, false) => {
+                        self.pending.push(alert.clone());
+                        self.history.push_front(AlertEvent { time: now, fired: true, alert: alert.clone() });
+                        state.firing = Some(alert);
+                    }
+                    (true, true) => state.firing = Some(alert),
+                    (false, true) => {
+                        self.history.push_front(AlertEvent { time: now, fired: false, alert });
+                        state.firing = None;
+                    }
+                    (false, false) => {}
+                }
+            }
+        }
+        self.history.truncate(Alerts::MAX_HISTORY);
+    }
+
+    /// Resolves alerts on metrics of a failed request, as there's nothing to tell whether their conditions still hold.
+    pub fn on_error(&mut self, target_name: &str, request: &FetcherRequest, now: DateTime<Local>) {
+        let rules: Vec<usize> = self.rules.iter().enumerate()
+            .filter(|(_, (rule, _))| rule.metric.request() == *request)
+            .map(|(n, _)| n)
+            .collect();
+        Alerts::resolve(&mut self.states, &mut self.history, now, |(t, r, _)| t == target_name && rules.contains(r));
+        self.history.truncate(Alerts::MAX_HISTORY);
+    }
+
+    /// Forgets states matching `f`, adding an event for those that were firing.
+    fn resolve<F>(states: &mut BTreeMap<(String, usize, Option<String>), RuleState>, history: &mut VecDeque<AlertEvent>, now: DateTime<Local>, f: F)
+        where F: Fn(&(String, usize, Option<String>)) -> bool {
+        let keys: Vec<(String, usize, Option<String>)> = states.keys().filter(|k| f(k)).cloned().collect();
+        for key in keys {
+            if let Some(alert) = states.remove(&key).and_then(|s| s.firing) {
+                history.push_front(AlertEvent { time: now, fired: false, alert });
+            }
+        }
+    }
+
+    /// Forgets samples seen so far, e.g. after seeking in a replayed session. Firing alerts are dropped silently.
+    pub fn reset(&mut self) {
+        self.states.clear();
+        self.pending.clear();
+    }
+
+    /// Rings the bell and runs the command for every alert fired since the last call.
+    pub fn notify(&mut self) {
+        for alert in self.pending.drain(..) {
+            if self.bell {
+                print!("\x07");
+                let _ = io::stdout().flush();
+            }
+            if let Some(command) = &self.command {
+                run_command(command, &alert);
+            }
+        }
+    }
+}
+
+fn run_command(command: &str, alert: &Alert) {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let child = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .env("PANOPTICON_ALERT", &alert.rule)
+        .env("PANOPTICON_ALERT_TARGET", &alert.target)
+        .env("PANOPTICON_ALERT_POOL", alert.pool.as_deref().unwrap_or(""))
+        .env("PANOPTICON_ALERT_MESSAGE", alert.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    // a failing hook shouldn't interrupt monitoring, so its result is only awaited to reap the process
+    if let Ok(mut child) = child {
+        thread::spawn(move || child.wait());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    use crate::alerts::{AlertMetric, AlertRule, Alerts, AlertsConfig};
+    use crate::app::TargetState;
+    use crate::fetcher::{FetcherRequest, FetcherResponse};
+    use crate::jmx::model::{DbPools, JMXConnectionSettings, SlickConfig, SlickMetrics};
+    use crate::target::TargetSettings;
+    use crate::zio::model::FiberCount;
+
+    fn rule(metric: AlertMetric) -> AlertRule {
+        AlertRule { name: "rule".to_owned(), metric, above: None, above_percent_of_max: None, growing: false, for_secs: 0 }
+    }
+
+    fn alerts(rules: Vec<AlertRule>) -> Alerts {
+        Alerts::new(&AlertsConfig { rules, bell: false, command: None }).unwrap()
+    }
+
+    fn target() -> TargetState {
+        TargetState::new(&TargetSettings {
+            name: "orders".to_owned(),
+            zio_zmx: Some("localhost:6789".to_owned()),
+            jmx: Some(JMXConnectionSettings { db_pools: Some(DbPools::Discovered), ..JMXConnectionSettings::for_test("localhost:9010") }),
+            akka: None,
+        })
+    }
+
+    /// Applies a response to the target and evaluates rules `secs` seconds after `start`.
+    fn sample(alerts: &mut Alerts, target: &mut TargetState, response: FetcherResponse, start: DateTime<Local>, secs: i64) {
+        let request = response.request();
+        target.on_fetcher_response(response);
+        alerts.on_response("orders", target, &request, start + Duration::seconds(secs));
+    }
+
+    fn fibers(running: i32) -> FetcherResponse {
+        FetcherResponse::FiberCount(Ok(FiberCount { done: 0, finishing: 0, running, suspended: 0 }))
+    }
+
+    #[test]
+    fn alert_fires_when_condition_holds_long_enough_and_resolves() {
+        let mut alerts = alerts(vec![AlertRule { above: Some(10.0), for_secs: 10, ..rule(AlertMetric::Fibers) }]);
+        let mut target = target();
+        let start = Local::now();
+
+        sample(&mut alerts, &mut target, fibers(20), start, 0);
+        sample(&mut alerts, &mut target, fibers(20), start, 5);
+        assert!(alerts.firing().is_empty());
+
+        sample(&mut alerts, &mut target, fibers(25), start, 10);
+        assert_eq!(alerts.firing().len(), 1);
+        assert_eq!(alerts.firing()[0].to_string(), "rule (orders): fibers is 25, above 10 for 10s");
+        assert_eq!(alerts.pending.len(), 1);
+
+        sample(&mut alerts, &mut target, fibers(5), start, 15);
+        assert!(alerts.firing().is_empty());
+        assert_eq!(alerts.history.iter().map(|e| e.fired).collect::<Vec<bool>>(), vec![false, true]);
+    }
+
+    #[test]
+    fn alert_compares_pools_with_their_limits() {
+        let mut alerts = alerts(vec![AlertRule { above_percent_of_max: Some(80.0), ..rule(AlertMetric::SlickQueueSize) }]);
+        let mut target = target();
+        let start = Local::now();
+        let config = |max_queue_size| SlickConfig { max_threads: 20, max_queue_size };
+        let queue = |queue_size| SlickMetrics { active_threads: 1, queue_size };
+        target.on_fetcher_response(FetcherResponse::SlickConfig(Ok(
+            vec![("read".to_owned(), config(100)), ("write".to_owned(), config(1000))].into_iter().collect()
+        )));
+
+        sample(&mut alerts, &mut target, FetcherResponse::SlickMetrics(Ok(
+            vec![("read".to_owned(), queue(90)), ("write".to_owned(), queue(90))].into_iter().collect()
+        )), start, 0);
+
+        let firing = alerts.firing();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].pool, Some("read".to_owned()));
+    }
+
+    #[test]
+    fn alert_resolves_when_its_sample_is_gone() {
+        let mut alerts = alerts(vec![AlertRule { above: Some(10.0), ..rule(AlertMetric::SlickQueueSize) }]);
+        let mut target = target();
+        let start = Local::now();
+        let queue = |pools: &[&str]| FetcherResponse::SlickMetrics(Ok(
+            pools.iter().map(|p| (p.to_string(), SlickMetrics { active_threads: 1, queue_size: 20 })).collect()
+        ));
+
+        sample(&mut alerts, &mut target, queue(&["read", "write"]), start, 0);
+        assert_eq!(alerts.firing().len(), 2);
+
+        // a pool that's gone can't keep its alert firing
+        sample(&mut alerts, &mut target, queue(&["read"]), start, 5);
+        let firing = alerts.firing();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].pool, Some("read".to_owned()));
+
+        // neither can a source that stopped answering
+        alerts.on_error("orders", &FetcherRequest::SlickMetrics, start + Duration::seconds(10));
+        assert!(alerts.firing().is_empty());
+        assert_eq!(alerts.history.iter().map(|e| e.fired).collect::<Vec<bool>>(), vec![false, false, true, true]);
+    }
+
+    #[test]
+    fn growing_alert_needs_metric_to_grow_without_drops() {
+        let mut alerts = alerts(vec![AlertRule { growing: true, for_secs: 60, ..rule(AlertMetric::FibersRunning) }]);
+        let mut target = target();
+        let start = Local::now();
+
+        for (secs, running) in [(0, 10), (30, 12), (50, 11), (80, 11), (110, 15)].iter() {
+            sample(&mut alerts, &mut target, fibers(*running), start, *secs);
+            assert!(alerts.firing().is_empty());
+        }
+        sample(&mut alerts, &mut target, fibers(16), start, 140);
+        assert_eq!(alerts.firing().len(), 1);
+    }
+
+    #[test]
+    fn alert_rules_need_a_single_condition() {
+        let error = |rule| Alerts::new(&AlertsConfig { rules: vec![rule], bell: false, command: None }).err();
+
+        assert!(error(rule(AlertMetric::Fibers)).is_some());
+        assert!(error(AlertRule { above: Some(1.0), growing: true, ..rule(AlertMetric::Fibers) }).is_some());
+        assert_eq!(
+            error(AlertRule { above_percent_of_max: Some(80.0), ..rule(AlertMetric::DeadLetters) }),
+            Some("Alert rule rule: dead-letters has no maximum to compare with".to_owned())
+        );
+        assert_eq!(error(AlertRule { growing: true, ..rule(AlertMetric::Actors) }), None);
+        // responses of other metrics are ignored
+        let mut alerts = alerts(vec![AlertRule { above: Some(0.0), ..rule(AlertMetric::Actors) }]);
+        alerts.on_response("orders", &target(), &FetcherRequest::FiberCount, Local::now());
+        assert!(alerts.history.is_empty());
+    }
+}
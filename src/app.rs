@@ -1,24 +1,51 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::iter::Iterator;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
+use chrono::prelude::*;
 use tui::text::{Span, Spans};
 use tui::widgets::ListState;
 
-use crate::akka::model::{ActorSystemStatus, ActorTreeNode, AkkaSettings, DeadLettersSnapshot, DeadLettersUIMessage, DeadLettersWindow};
-use crate::jmx::model::{HikariMetrics, JMXConnectionSettings, SlickConfig, SlickMetrics};
-use crate::widgets::tree;
+use crate::alerts::Alerts;
+use crate::akka::model::{ActorSystemStatus, ActorTreeNode, DeadLettersSnapshot, DeadLettersUIMessage, DeadLettersWindow};
+use crate::fetcher::{FetcherRequest, FetcherResponse};
+use crate::health::{HealthStatus, Source, SourceHealth};
+use crate::snapshot::{self, SnapshotFormat, SnapshotKind};
+use crate::jmx::model::{HikariConfig, HikariMetrics, HikariTimings, JvmMetrics, MBean, MBeanAttributeRef, MBeanAttributeValue, MBeanTreeNode, SlickConfig, SlickMetrics, ThreadDump, ThreadInfo, ThreadState};
+use crate::target::TargetSettings;
+use crate::widgets::tree::{self, StatefulTree, Tree, TreeWidgetNode};
+use crate::zio::diff::FiberDiff;
 use crate::zio::model::{Fiber, FiberCount, FiberStatus};
 
 pub struct UIFiber {
+    /// None for rows of fiber groups.
+    pub id: Option<usize>,
     pub label: String,
     pub dump: String,
 }
 
 #[derive(Clone)]
 pub enum AppTabKind {
-    ZMX,
+    Zmx,
     Slick,
+    Jvm,
+    Threads,
     Akka,
+    MBeans,
+}
+
+impl AppTabKind {
+    pub fn source(&self) -> Source {
+        match self {
+            AppTabKind::Zmx => Source::Zmx,
+            AppTabKind::Slick => Source::Jmx,
+            AppTabKind::Jvm => Source::Jmx,
+            AppTabKind::Threads => Source::Jmx,
+            AppTabKind::Akka => Source::Akka,
+            AppTabKind::MBeans => Source::Jmx,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -53,33 +80,108 @@ impl<K> TabsState<K> {
         &self.tabs[self.index]
     }
 
-    pub fn titles(&self) -> Vec<Spans> {
+    pub fn titles(&self) -> Vec<Spans<'_>> {
         self.tabs.iter().map(|x| Spans(vec![Span::raw(&x.title)])).collect()
     }
 }
 
+/// Narrows the fiber tree down by a search query and fiber status.
+#[derive(Clone, Default)]
+pub struct FiberFilter {
+    /// Matched against fiber id, status and dump, ignoring case.
+    pub query: String,
+    pub hide_done: bool,
+    pub only_suspended: bool,
+}
+
+impl FiberFilter {
+    pub fn is_empty(&self) -> bool {
+        self.query.trim().is_empty() && !self.hide_done && !self.only_suspended
+    }
+
+    pub fn matches(&self, fiber: &Fiber) -> bool {
+        if self.hide_done && fiber.status == FiberStatus::Done {
+            return false;
+        }
+        if self.only_suspended && fiber.status != FiberStatus::Suspended {
+            return false;
+        }
+        let query = self.query.trim().to_ascii_lowercase();
+        query.is_empty()
+            || query.trim_start_matches('#') == fiber.id.to_string()
+            || fiber.status.to_string().to_ascii_lowercase().contains(&query)
+            || fiber.dump.to_ascii_lowercase().contains(&query)
+    }
+
+    /// Matching fibers along with all of their ancestors, so that they keep their place in the tree.
+    pub fn apply(&self, fibers: &[Fiber]) -> Vec<Fiber> {
+        let by_id: HashMap<usize, &Fiber> = fibers.iter().map(|f| (f.id, f)).collect();
+        let mut visible: HashSet<usize> = HashSet::new();
+        for f in fibers.iter().filter(|f| self.matches(f)) {
+            let mut next = Some(f);
+            while let Some(f) = next {
+                if !visible.insert(f.id) {
+                    break;
+                }
+                next = f.parent_id.and_then(|id| by_id.get(&id).copied());
+            }
+        }
+        fibers.iter().filter(|f| visible.contains(&f.id)).cloned().collect()
+    }
+}
+
 pub struct ZMXTab {
     pub fibers: StatefulList<String>,
     pub selected_fiber_dump: (String, u16),
     pub fiber_dump_all: Vec<String>,
+    /// Ids of the listed fibers, in the order of `fibers`.
+    pub fiber_ids: Vec<Option<usize>>,
+    /// The last few fiber dumps, the most recent last.
+    pub snapshots: VecDeque<Vec<Fiber>>,
+    /// How many dumps are kept in `snapshots`.
+    pub max_snapshots: usize,
+    /// Whether the most recent dump was auto-refreshed, so the next refresh replaces it.
+    latest_refreshed: bool,
+    pub filter: FiberFilter,
+    /// Whether the oldest fibers are listed first, rather than in the order of the dump.
+    pub sort_by_age: bool,
+    /// Whether fibers are grouped by the place they are suspended at, instead of being shown as a tree.
+    pub group_by_site: bool,
+    /// Whether changes since the previous dump are listed, instead of the last dump.
+    pub show_diff: bool,
+    /// Whether the search query is being typed in.
+    pub searching: bool,
+    /// Collapsed fibers of the tree. Its rows are copied to `fibers`, which keeps the selection.
+    pub tree: Tree<Fiber>,
     pub scroll: u16,
     pub fiber_counts: VecDeque<FiberCount>,
 }
 
 impl ZMXTab {
     pub const MAX_FIBER_COUNT_MEASURES: usize = 100;
+    pub const DEFAULT_FIBER_SNAPSHOTS: usize = 5;
 
     pub fn new() -> ZMXTab {
         ZMXTab {
             fibers: StatefulList::with_items(vec![]),
             selected_fiber_dump: ("".to_string(), 1),
             fiber_dump_all: vec![],
+            fiber_ids: vec![],
+            snapshots: VecDeque::new(),
+            max_snapshots: ZMXTab::DEFAULT_FIBER_SNAPSHOTS,
+            latest_refreshed: false,
+            filter: FiberFilter::default(),
+            sort_by_age: false,
+            group_by_site: false,
+            show_diff: false,
+            searching: false,
+            tree: Tree::new(true),
             scroll: 0,
             fiber_counts: VecDeque::new(),
         }
     }
 
-    fn append_fiber_count(&mut self, c: FiberCount) {
+    pub fn append_fiber_count(&mut self, c: FiberCount) {
         if self.fiber_counts.len() > ZMXTab::MAX_FIBER_COUNT_MEASURES {
             self.fiber_counts.pop_front();
         }
@@ -101,25 +203,252 @@ impl ZMXTab {
     }
 
     pub fn on_fiber_change(&mut self) {
-        let n = self.fibers.state.selected().unwrap_or(0);
-        self.selected_fiber_dump = ZMXTab::prepare_dump(self.fiber_dump_all[n].clone());
+        let dump = self.fibers.state.selected()
+            .and_then(|n| self.fiber_dump_all.get(n))
+            .cloned()
+            .unwrap_or_default();
+        self.selected_fiber_dump = ZMXTab::prepare_dump(dump);
         self.scroll = 0;
     }
 
+    /// Adds a fiber dump, keeping the selected fiber and the scroll position of its details if it's still there.
     pub fn replace_fiber_dump(&mut self, dump: Vec<Fiber>) {
-        let list: Vec<UIFiber> = tree::tree_list_widget(dump, true)
+        self.latest_refreshed = false;
+        while self.snapshots.len() >= self.max_snapshots.max(1) {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(dump);
+        let (selected_id, scroll) = (self.selected_fiber_id(), self.scroll);
+        self.show_fibers(selected_id);
+        if selected_id.is_some() && self.selected_fiber_id() == selected_id {
+            self.scroll = scroll.min(self.selected_fiber_dump.1);
+        }
+    }
+
+    /// Shows an auto-refreshed dump. Only the latest of them is kept, so refreshes don't push
+    /// dumps taken with <Enter> out of the diff history.
+    pub fn refresh_fiber_dump(&mut self, dump: Vec<Fiber>) {
+        if self.latest_refreshed {
+            self.snapshots.pop_back();
+        }
+        self.replace_fiber_dump(dump);
+        self.latest_refreshed = true;
+    }
+
+    pub fn latest_fibers(&self) -> &[Fiber] {
+        self.snapshots.back().map_or(&[], |s| s.as_slice())
+    }
+
+    /// Lists the fibers that pass the filter, selecting the given one if it still matches.
+    fn show_fibers(&mut self, selected_id: Option<usize>) {
+        let (list, matches): (Vec<UIFiber>, HashSet<usize>) = if self.show_diff {
+            let list = FiberDiff::of(&self.snapshots).map_or_else(Vec::new, |d| self.diff_rows(d));
+            let matches = list.iter().filter_map(|f| f.id).collect();
+            (list, matches)
+        } else {
+            let mut fibers = if self.group_by_site {
+                self.latest_fibers().iter().filter(|f| self.filter.matches(f)).cloned().collect()
+            } else {
+                self.filter.apply(self.latest_fibers())
+            };
+            if self.sort_by_age {
+                fibers.sort_by_key(|f| std::cmp::Reverse(f.lifetime_ms));
+            }
+            let matches = fibers.iter().filter(|f| self.filter.matches(f)).map(|f| f.id).collect();
+            (if self.group_by_site { ZMXTab::site_groups(fibers) } else { self.fiber_tree(fibers) }, matches)
+        };
+
+        // parents are listed only to keep the tree in shape, so the first actual match is selected by default
+        let is_match = |f: &UIFiber| f.id.is_some_and(|id| matches.contains(&id));
+        let selected = selected_id
+            .and_then(|id| list.iter().position(|f| f.id == Some(id) && is_match(f)))
+            .or_else(|| list.iter().position(is_match))
+            .or(if list.is_empty() { None } else { Some(0) });
+
+        self.fibers.items = list.iter().map(|f| f.label.clone()).collect();
+        self.fiber_dump_all = list.iter().map(|f| f.dump.clone()).collect();
+        self.fiber_ids = list.iter().map(|f| f.id).collect();
+        self.fibers.state.select(selected);
+        self.on_fiber_change();
+    }
+
+    fn fiber_tree(&mut self, fibers: Vec<Fiber>) -> Vec<UIFiber> {
+        self.tree.replace(fibers);
+        self.tree.rows
             .iter()
-            .map(|(label, fb)| UIFiber { label: label.to_owned(), dump: fb.dump.to_owned() })
+            .map(|(label, fb)| UIFiber { id: Some(fb.id), label: label.to_owned(), dump: fb.details() })
+            .collect()
+    }
+
+    /// A row for every suspension site (or status of fibers that aren't suspended), each followed by its fibers.
+    /// The biggest groups go first.
+    fn site_groups(fibers: Vec<Fiber>) -> Vec<UIFiber> {
+        let mut groups: Vec<(String, Vec<UIFiber>)> = vec![];
+        for f in fibers {
+            let site = f.suspended_at.as_ref().map_or_else(|| f.status.to_string(), |s| s.to_string());
+            let row = ZMXTab::fiber_row(&f);
+            match groups.iter_mut().find(|g| g.0 == site) {
+                Some(g) => g.1.push(row),
+                None => groups.push((site, vec![row])),
+            }
+        }
+        groups.sort_by_key(|g| std::cmp::Reverse(g.1.len()));
+        ZMXTab::group_rows(groups)
+    }
+
+    /// New, finished, changed and long-lived fibers that pass the filter.
+    fn diff_rows(&self, diff: FiberDiff) -> Vec<UIFiber> {
+        let matching = |fibers: &[Fiber]| -> Vec<UIFiber> {
+            fibers.iter().filter(|f| self.filter.matches(f)).map(ZMXTab::fiber_row).collect()
+        };
+        let changed = diff.changed.iter()
+            .filter(|(_, f)| self.filter.matches(f))
+            .map(|(before, f)| {
+                let change = if before.status == f.status { format!("{}, moved", f.status) } else { format!("{} → {}", before.status, f.status) };
+                UIFiber {
+                    id: Some(f.id),
+                    label: format!("#{} {}", f.id, change),
+                    dump: format!("Was: {}\nNow: {}\n\n{}", before.state(), f.state(), f.details()),
+                }
+            })
             .collect();
-        let mut fib_labels: Vec<String> = list.iter().map(|f| f.label.clone()).collect();
-        let mut fib_dumps = list.iter().map(|f| f.dump.to_owned()).collect::<Vec<String>>();
+        ZMXTab::group_rows(vec![
+            ("New".to_owned(), matching(&diff.new)),
+            ("Finished".to_owned(), matching(&diff.finished)),
+            ("Changed".to_owned(), changed),
+            ("In every snapshot".to_owned(), matching(&diff.long_lived)),
+        ])
+    }
+
+    fn fiber_row(f: &Fiber) -> UIFiber {
+        UIFiber { id: Some(f.id), label: format!("#{} {}", f.id, f.label()), dump: f.details() }
+    }
+
+    /// A header row for every non-empty group, followed by the rows of its fibers.
+    fn group_rows(groups: Vec<(String, Vec<UIFiber>)>) -> Vec<UIFiber> {
+        let mut rows = vec![];
+        for (title, fibers) in groups.into_iter().filter(|g| !g.1.is_empty()) {
+            let labels: Vec<&str> = fibers.iter().map(|f| f.label.as_str()).collect();
+            rows.push(UIFiber {
+                id: None,
+                label: format!("{} ({})", title, fibers.len()),
+                dump: format!("{} ({}):\n{}", title, fibers.len(), labels.join("\n")),
+            });
+            let n = fibers.len();
+            for (i, f) in fibers.into_iter().enumerate() {
+                let branch = if i + 1 == n { "└─" } else { "├─" };
+                rows.push(UIFiber { label: format!("{}{}", branch, f.label), ..f });
+            }
+        }
+        rows
+    }
 
-        self.fibers.items.clear();
-        self.fibers.items.append(&mut fib_labels);
-        self.fibers.state.select(Some(0));
-        self.selected_fiber_dump = ZMXTab::prepare_dump(fib_dumps[0].clone());
-        self.fiber_dump_all.clear();
-        self.fiber_dump_all.append(&mut fib_dumps);
+    fn on_filter_change(&mut self) {
+        self.show_fibers(self.selected_fiber_id());
+    }
+
+    fn selected_fiber_id(&self) -> Option<usize> {
+        self.fibers.state.selected().and_then(|n| self.fiber_ids.get(n)).copied().flatten()
+    }
+
+    /// Selects a listed fiber, whether it matches the filter or not.
+    fn select_fiber(&mut self, id: usize) {
+        if let Some(n) = self.fiber_ids.iter().position(|f| *f == Some(id)) {
+            self.fibers.state.select(Some(n));
+            self.on_fiber_change();
+        }
+    }
+
+    /// Whether fibers are shown as a tree, which can be folded.
+    fn shows_tree(&self) -> bool {
+        !self.show_diff && !self.group_by_site
+    }
+
+    /// Collapses or expands the selected fiber of the tree.
+    pub fn toggle_fold(&mut self) {
+        if let (true, Some(id)) = (self.shows_tree(), self.selected_fiber_id()) {
+            self.tree.toggle(id);
+            self.show_fibers(None);
+            self.select_fiber(id);
+        }
+    }
+
+    pub fn expand_all(&mut self) {
+        if let (true, Some(id)) = (self.shows_tree(), self.selected_fiber_id()) {
+            self.tree.expand_all();
+            self.show_fibers(None);
+            self.select_fiber(id);
+        }
+    }
+
+    /// Collapses the tree down to the root fibers, selecting the root of the selected one.
+    pub fn collapse_all(&mut self) {
+        if let (true, Some(id)) = (self.shows_tree(), self.selected_fiber_id()) {
+            self.tree.collapse_all();
+            self.show_fibers(None);
+            self.select_fiber(self.tree.visible_ancestor(id));
+        }
+    }
+
+    /// Selects the fiber that forked the selected one, if it's listed.
+    pub fn select_parent(&mut self) {
+        let parent = self.selected_fiber_id()
+            .and_then(|id| self.latest_fibers().iter().find(|f| f.id == id))
+            .and_then(|f| f.parent_id);
+        if let Some(id) = parent {
+            self.select_fiber(id);
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.searching = true;
+    }
+
+    /// Stops typing, keeping the query.
+    pub fn finish_search(&mut self) {
+        self.searching = false;
+    }
+
+    /// Stops typing and drops the query.
+    pub fn cancel_search(&mut self) {
+        self.searching = false;
+        self.filter.query.clear();
+        self.on_filter_change();
+    }
+
+    pub fn push_search_char(&mut self, c: char) {
+        self.filter.query.push(c);
+        self.on_filter_change();
+    }
+
+    pub fn pop_search_char(&mut self) {
+        self.filter.query.pop();
+        self.on_filter_change();
+    }
+
+    pub fn toggle_hide_done(&mut self) {
+        self.filter.hide_done = !self.filter.hide_done;
+        self.on_filter_change();
+    }
+
+    pub fn toggle_only_suspended(&mut self) {
+        self.filter.only_suspended = !self.filter.only_suspended;
+        self.on_filter_change();
+    }
+
+    pub fn toggle_sort_by_age(&mut self) {
+        self.sort_by_age = !self.sort_by_age;
+        self.on_filter_change();
+    }
+
+    pub fn toggle_group_by_site(&mut self) {
+        self.group_by_site = !self.group_by_site;
+        self.on_filter_change();
+    }
+
+    pub fn toggle_diff(&mut self) {
+        self.show_diff = !self.show_diff;
+        self.on_filter_change();
     }
 
     pub fn scroll_up(&mut self) {
@@ -134,48 +463,37 @@ impl ZMXTab {
         }
     }
 
-    pub fn append_fiber_dump_for_counts(&mut self, dump: Vec<Fiber>) {
-        let mut count = FiberCount { done: 0, suspended: 0, running: 0, finishing: 0 };
-        for f in dump.iter() {
-            match f.status {
-                FiberStatus::Done => { count.done += 1 }
-                FiberStatus::Finishing => { count.finishing += 1 }
-                FiberStatus::Running => { count.running += 1 }
-                FiberStatus::Suspended => { count.suspended += 1 }
-            }
-        }
-        self.append_fiber_count(count);
-    }
-
     fn prepare_dump(s: String) -> (String, u16) {
         (s.clone(), s.lines().collect::<Vec<&str>>().len() as u16)
     }
 }
 
-pub struct SlickTab {
+/// Metrics of a single database connection pool.
+pub struct DbPool {
+    /// Whether the latest Slick response had metrics of this pool.
+    pub has_slick: bool,
     pub has_hikari: bool,
     pub slick_metrics: VecDeque<SlickMetrics>,
     pub slick_config: SlickConfig,
     pub hikari_metrics: VecDeque<HikariMetrics>,
+    pub hikari_config: Option<HikariConfig>,
+    /// Latest timings, if the pool publishes them.
+    pub hikari_timings: Option<HikariTimings>,
 }
 
-impl SlickTab {
-    pub const MAX_SLICK_MEASURES: usize = 25;
-    pub const MAX_HIKARI_MEASURES: usize = 100;
-
-    pub fn new() -> SlickTab {
-        SlickTab {
+impl DbPool {
+    pub fn new() -> DbPool {
+        DbPool {
+            has_slick: false,
             has_hikari: false,
             slick_metrics: VecDeque::new(),
             slick_config: SlickConfig { max_threads: 0, max_queue_size: 0 },
             hikari_metrics: VecDeque::new(),
+            hikari_config: None,
+            hikari_timings: None,
         }
     }
 
-    pub fn replace_slick_config(&mut self, m: SlickConfig) {
-        self.slick_config = m
-    }
-
     pub fn append_slick_metrics(&mut self, m: SlickMetrics) {
         if self.slick_metrics.len() > SlickTab::MAX_SLICK_MEASURES {
             self.slick_metrics.pop_front();
@@ -191,6 +509,268 @@ impl SlickTab {
     }
 }
 
+/// Database connection pools, one of which is shown at a time. Pools are added as they show up in responses.
+pub struct SlickTab {
+    pub pools: TabsState<String>,
+    pub pool_metrics: BTreeMap<String, DbPool>,
+}
+
+impl SlickTab {
+    pub const MAX_SLICK_MEASURES: usize = 25;
+    pub const MAX_HIKARI_MEASURES: usize = 100;
+
+    pub fn new() -> SlickTab {
+        SlickTab {
+            pools: TabsState::new(vec![]),
+            pool_metrics: BTreeMap::new(),
+        }
+    }
+
+    pub fn current_pool(&self) -> Option<&DbPool> {
+        self.pools.tabs.get(self.pools.index).map(|t| &self.pool_metrics[&t.kind])
+    }
+
+    pub fn select_next_pool(&mut self) {
+        if !self.pools.tabs.is_empty() {
+            self.pools.next();
+        }
+    }
+
+    pub fn select_prev_pool(&mut self) {
+        if !self.pools.tabs.is_empty() {
+            self.pools.previous();
+        }
+    }
+
+    pub fn replace_slick_config(&mut self, m: BTreeMap<String, SlickConfig>) {
+        for (pool, config) in m {
+            self.pool_mut(pool).slick_config = config;
+        }
+    }
+
+    /// Pools missing from `m` keep their charts, but are marked as gone.
+    pub fn append_slick_metrics(&mut self, m: BTreeMap<String, SlickMetrics>) {
+        for pool in self.pool_metrics.values_mut() {
+            pool.has_slick = false;
+        }
+        for (pool, metrics) in m {
+            let pool = self.pool_mut(pool);
+            pool.has_slick = true;
+            pool.append_slick_metrics(metrics);
+        }
+    }
+
+    /// Pools missing from `m` are shown without HikariCP metrics.
+    pub fn append_hikari_metrics(&mut self, m: BTreeMap<String, HikariMetrics>) {
+        for pool in self.pool_metrics.values_mut() {
+            pool.has_hikari = false;
+        }
+        for (pool, metrics) in m {
+            let pool = self.pool_mut(pool);
+            pool.has_hikari = true;
+            pool.append_hikari_metrics(metrics);
+        }
+    }
+
+    pub fn replace_hikari_config(&mut self, m: BTreeMap<String, HikariConfig>) {
+        for (pool, config) in m {
+            self.pool_mut(pool).hikari_config = Some(config);
+        }
+    }
+
+    /// Pools missing from `m` are shown without timings.
+    pub fn replace_hikari_timings(&mut self, m: BTreeMap<String, HikariTimings>) {
+        for pool in self.pool_metrics.values_mut() {
+            pool.hikari_timings = None;
+        }
+        for (pool, timings) in m {
+            self.pool_mut(pool).hikari_timings = Some(timings);
+        }
+    }
+
+    /// Returns a pool, adding it to the selector first if it's new. Pools are kept sorted by name.
+    fn pool_mut(&mut self, name: String) -> &mut DbPool {
+        if !self.pool_metrics.contains_key(&name) {
+            let selected = self.pools.tabs.get(self.pools.index).map(|t| t.kind.clone());
+            self.pool_metrics.insert(name.clone(), DbPool::new());
+            self.pools.tabs = self.pool_metrics.keys()
+                .map(|k| Tab { kind: k.clone(), title: k.clone() })
+                .collect();
+            self.pools.index = selected
+                .and_then(|s| self.pools.tabs.iter().position(|t| t.kind == s))
+                .unwrap_or(0);
+        }
+        self.pool_metrics.get_mut(&name).unwrap()
+    }
+}
+
+pub struct JvmTab {
+    pub jvm_metrics: VecDeque<JvmMetrics>,
+}
+
+impl JvmTab {
+    pub const MAX_JVM_MEASURES: usize = 100;
+
+    pub fn new() -> JvmTab {
+        JvmTab { jvm_metrics: VecDeque::new() }
+    }
+
+    pub fn append_jvm_metrics(&mut self, m: JvmMetrics) {
+        if self.jvm_metrics.len() > JvmTab::MAX_JVM_MEASURES {
+            self.jvm_metrics.pop_front();
+        }
+        self.jvm_metrics.push_back(m);
+    }
+
+    /// Collections and collection time (in ms) of every collector since the previous measure.
+    /// Counters that went down, e.g. after the jvm restarted, count as no activity.
+    pub fn recent_gc_activity(&self) -> Vec<(String, i64, i64)> {
+        let mut measures = self.jvm_metrics.iter().rev();
+        let (last, previous) = match (measures.next(), measures.next()) {
+            (Some(l), p) => (l, p),
+            (None, _) => return vec![],
+        };
+        last.garbage_collectors.iter()
+            .map(|gc| {
+                let before = previous.and_then(|p| p.garbage_collectors.iter().find(|x| x.name == gc.name));
+                let count = before.map_or(0, |b| (gc.collection_count - b.collection_count).max(0));
+                let time = before.map_or(0, |b| (gc.collection_time - b.collection_time).max(0));
+                (gc.name.clone(), count, time)
+            })
+            .collect()
+    }
+}
+
+pub struct UIThread {
+    pub label: String,
+    pub dump: String,
+    pub deadlocked: bool,
+}
+
+pub struct ThreadsTab {
+    pub thread_dump: Option<ThreadDump>,
+    /// Whether threads with identical stacks are shown as a single row.
+    pub group_stacks: bool,
+    pub threads: StatefulList<UIThread>,
+    pub scroll: u16,
+}
+
+impl ThreadsTab {
+    pub fn new() -> ThreadsTab {
+        ThreadsTab {
+            thread_dump: None,
+            group_stacks: true,
+            threads: StatefulList::with_items(vec![]),
+            scroll: 0,
+        }
+    }
+
+    pub fn replace_thread_dump(&mut self, dump: ThreadDump) {
+        self.thread_dump = Some(dump);
+        self.reload_threads();
+        self.threads.state.select(if self.threads.items.is_empty() { None } else { Some(0) });
+        self.scroll = 0;
+    }
+
+    pub fn toggle_group_stacks(&mut self) {
+        self.group_stacks = !self.group_stacks;
+        self.reload_threads();
+        self.threads.state.select(if self.threads.items.is_empty() { None } else { Some(0) });
+        self.scroll = 0;
+    }
+
+    pub fn select_prev_thread(&mut self) {
+        if !self.threads.items.is_empty() {
+            self.threads.previous();
+            self.scroll = 0;
+        }
+    }
+
+    pub fn select_next_thread(&mut self) {
+        if !self.threads.items.is_empty() {
+            self.threads.next();
+            self.scroll = 0;
+        }
+    }
+
+    pub fn selected_dump(&self) -> &str {
+        self.threads.selected().map_or("", |t| t.dump.as_str())
+    }
+
+    pub fn deadlocked_count(&self) -> usize {
+        self.thread_dump.as_ref().map_or(0, |d| d.deadlocked.len())
+    }
+
+    pub fn scroll_up(&mut self) {
+        if self.scroll > 0 {
+            self.scroll -= 1;
+        }
+    }
+
+    pub fn scroll_down(&mut self) {
+        if (self.scroll as usize) < self.selected_dump().lines().count() {
+            self.scroll += 1;
+        }
+    }
+
+    /// Rows for every thread state, each followed by its threads.
+    /// With `group_stacks` threads of the same state and stack trace share a row.
+    fn reload_threads(&mut self) {
+        let dump = match &self.thread_dump {
+            Some(d) => d,
+            None => return,
+        };
+        let mut states: Vec<ThreadState> = dump.threads.iter().map(|t| t.thread_state).collect();
+        states.sort();
+        states.dedup();
+
+        let mut rows = vec![];
+        for state in states {
+            let mut groups: Vec<Vec<&ThreadInfo>> = vec![];
+            for t in dump.threads.iter().filter(|t| t.thread_state == state) {
+                match groups.iter_mut().find(|g| self.group_stacks && g[0].stack_trace == t.stack_trace) {
+                    Some(g) => g.push(t),
+                    None => groups.push(vec![t]),
+                }
+            }
+            // the biggest groups are the most likely suspects
+            groups.sort_by_key(|g| std::cmp::Reverse(g.len()));
+
+            let threads: Vec<&ThreadInfo> = groups.iter().flatten().cloned().collect();
+            rows.push(UIThread {
+                label: format!("{} ({})", state, threads.len()),
+                dump: threads.iter().map(|t| t.dump(dump.is_deadlocked(t))).collect::<Vec<String>>().join("\n\n"),
+                deadlocked: threads.iter().any(|t| dump.is_deadlocked(t)),
+            });
+            for (i, group) in groups.iter().enumerate() {
+                let branch = if i + 1 == groups.len() { "└─" } else { "├─" };
+                let label = match group.len() {
+                    1 => format!("{}{}", branch, group[0].thread_name),
+                    n => format!("{}{} (+{} with the same stack)", branch, group[0].thread_name, n - 1),
+                };
+                rows.push(UIThread {
+                    label,
+                    dump: ThreadsTab::group_dump(dump, group),
+                    deadlocked: group.iter().any(|t| dump.is_deadlocked(t)),
+                });
+            }
+        }
+        self.threads = StatefulList::with_items(rows);
+    }
+
+    /// Dump of a single thread, or the names of grouped threads followed by their shared stack.
+    fn group_dump(dump: &ThreadDump, group: &[&ThreadInfo]) -> String {
+        match group {
+            [t] => t.dump(dump.is_deadlocked(t)),
+            _ => {
+                let names: Vec<String> = group.iter().map(|t| format!("\"{}\" #{}", t.thread_name, t.thread_id)).collect();
+                let stack: String = group[0].stack_trace.iter().map(|f| format!("\n    at {}", f)).collect();
+                format!("{} threads with the same stack:\n{}\n{}", group.len(), names.join("\n"), stack)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub enum DeadLettersTabKind {
     DeadLetters,
@@ -199,7 +779,12 @@ pub enum DeadLettersTabKind {
 }
 
 pub struct AkkaTab {
-    pub actors: StatefulList<String>,
+    /// The last fetched actor tree.
+    pub actors: StatefulTree<ActorTreeNode>,
+    /// Ids of actors by path. Fetched ids change whenever the tree does, so they are replaced with these.
+    actor_ids: HashMap<String, usize>,
+    /// Ids are never reused, so that an actor can't inherit the state of a stopped one.
+    next_actor_id: usize,
     pub actor_counts: VecDeque<u64>,
     pub system_status: ActorSystemStatus,
     pub dead_letters_messages: DeadLettersSnapshot,
@@ -214,7 +799,9 @@ impl AkkaTab {
 
     pub fn new() -> AkkaTab {
         AkkaTab {
-            actors: StatefulList::with_items(vec![]),
+            actors: StatefulTree::new(false),
+            actor_ids: HashMap::new(),
+            next_actor_id: 1,
             actor_counts: VecDeque::new(),
             dead_letters_messages: DeadLettersSnapshot {
                 dead_letters: vec![],
@@ -239,14 +826,26 @@ impl AkkaTab {
         }
     }
 
+    /// Replaces the actor tree, keeping the selected and collapsed actors by path.
     pub fn update_actor_tree(&mut self, actors: Vec<ActorTreeNode>) {
-        let mut list: Vec<String> = tree::tree_list_widget(actors, false)
-            .iter()
-            .map(|x| x.0.to_owned())
+        let paths = ActorTreeNode::paths(&actors);
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        for path in actors.iter().filter_map(|a| paths.get(&a.id)) {
+            let id = match self.actor_ids.get(path) {
+                Some(id) => *id,
+                None => {
+                    self.next_actor_id += 1;
+                    self.next_actor_id - 1
+                }
+            };
+            ids.insert(path.to_owned(), id);
+        }
+        let stable_id = |id: usize| paths.get(&id).and_then(|p| ids.get(p)).copied();
+        let actors = actors.iter()
+            .filter_map(|a| stable_id(a.id).map(|id| ActorTreeNode { id, parent: a.parent.and_then(stable_id), ..a.clone() }))
             .collect();
-
-        self.actors.items.clear();
-        self.actors.items.append(&mut list);
+        self.actor_ids = ids;
+        self.actors.replace(actors);
     }
 
     pub fn select_prev_actor(&mut self) {
@@ -273,6 +872,39 @@ impl AkkaTab {
         self.dead_letters_messages = snapshot;
     }
 
+    /// Path and children of the selected actor, along with the dead letters, unhandled and dropped messages
+    /// it sent or was sent, the newest first.
+    pub fn actor_details(&self) -> Option<String> {
+        let actor = self.actors.selected()?;
+        let path = self.actor_ids.iter().find(|(_, id)| **id == actor.id).map(|(p, _)| p.as_str())?;
+        let children = self.actors.nodes().iter().filter(|a| a.parent == Some(actor.id)).count();
+
+        let snapshot = &self.dead_letters_messages;
+        let mut messages: Vec<(&str, DeadLettersUIMessage)> = snapshot.dead_letters.iter()
+            .map(|x| ("Dead letter", x.value.to_ui(x.timestamp)))
+            .chain(snapshot.unhandled.iter().map(|x| ("Unhandled", x.value.to_ui(x.timestamp))))
+            .chain(snapshot.dropped.iter().map(|x| ("Dropped", x.value.to_ui(x.timestamp))))
+            .filter(|(_, m)| DeadLettersUIMessage::refers_to(&m.sender, path) || DeadLettersUIMessage::refers_to(&m.recipient, path))
+            .collect();
+        messages.sort_by_key(|(_, m)| std::cmp::Reverse(m.timestamp));
+
+        let sent = messages.iter().filter(|(_, m)| DeadLettersUIMessage::refers_to(&m.sender, path)).count();
+        let mut lines = vec![
+            format!("Path: {}", path),
+            format!("Children: {}", children),
+            format!("Dead letters: {} sent, {} received", sent, messages.len() - sent),
+        ];
+        for (kind, m) in messages {
+            lines.push(format!("{} {}", kind, m.summary()));
+            if DeadLettersUIMessage::refers_to(&m.sender, path) {
+                lines.push(format!("  to {}", m.recipient));
+            } else {
+                lines.push(format!("  from {}", m.sender));
+            }
+        }
+        Some(lines.join("\n"))
+    }
+
     pub fn reload_dead_letters_log(&mut self) {
         let ui_messages: Vec<DeadLettersUIMessage> = match self.dead_letters_tabs.current().kind {
             DeadLettersTabKind::DeadLetters =>
@@ -287,6 +919,104 @@ impl AkkaTab {
     }
 }
 
+pub struct MBeansTab {
+    pub mbeans: StatefulList<String>,
+    /// ObjectName of every row in `mbeans`, `None` for domain rows.
+    mbean_names: Vec<Option<String>>,
+    pub selected_mbean: Option<MBean>,
+    pub attributes: StatefulList<MBeanAttributeValue>,
+    pub pinned: Vec<MBeanAttributeRef>,
+    pub pinned_values: HashMap<MBeanAttributeRef, VecDeque<f64>>,
+}
+
+impl MBeansTab {
+    pub const MAX_PINNED_MEASURES: usize = 100;
+
+    pub fn new() -> MBeansTab {
+        MBeansTab {
+            mbeans: StatefulList::with_items(vec![]),
+            mbean_names: vec![],
+            selected_mbean: None,
+            attributes: StatefulList::with_items(vec![]),
+            pinned: vec![],
+            pinned_values: HashMap::new(),
+        }
+    }
+
+    pub fn replace_mbean_names(&mut self, names: Vec<String>) {
+        let rows = tree::tree_list_widget(MBeanTreeNode::from_names(&names), false);
+        self.mbeans = StatefulList::with_items(rows.iter().map(|(label, _)| label.to_owned()).collect());
+        self.mbean_names = rows.into_iter().map(|(_, node)| node.name).collect();
+        if !self.mbean_names.is_empty() {
+            self.mbeans.state.select(Some(0));
+        }
+    }
+
+    pub fn replace_mbean(&mut self, mbean: MBean) {
+        let selected = self.attributes.state.selected();
+        let same_mbean = self.selected_mbean.as_ref().is_some_and(|m| m.name == mbean.name);
+        self.attributes = StatefulList::with_items(mbean.attributes.clone());
+        if !self.attributes.items.is_empty() {
+            // keep the cursor when the same MBean is reloaded
+            self.attributes.state.select(if same_mbean { selected } else { Some(0) });
+        }
+        self.selected_mbean = Some(mbean);
+    }
+
+    pub fn selected_mbean_name(&self) -> Option<&String> {
+        self.mbeans.state.selected().and_then(|i| self.mbean_names.get(i)).and_then(|n| n.as_ref())
+    }
+
+    /// Request for <Enter>: attributes of the selected MBean, or the list of MBeans if a domain is selected.
+    pub fn enter_request(&self) -> FetcherRequest {
+        match self.selected_mbean_name() {
+            Some(name) => FetcherRequest::MBean(name.clone()),
+            None => FetcherRequest::MBeanNames,
+        }
+    }
+
+    pub fn is_pinned(&self, attribute: &MBeanAttributeValue) -> bool {
+        self.selected_mbean.as_ref().is_some_and(|m| self.pinned.contains(&MBeanAttributeRef {
+            mbean: m.name.clone(),
+            attribute: attribute.name.clone(),
+        }))
+    }
+
+    /// Pins the selected attribute to the chart, or unpins it if it's already pinned.
+    /// Returns the new set of pinned attributes, or `None` if nothing changed.
+    pub fn toggle_pin(&mut self) -> Option<Vec<MBeanAttributeRef>> {
+        let mbean = self.selected_mbean.as_ref()?;
+        let attribute = self.attributes.selected()?;
+        let pin = MBeanAttributeRef { mbean: mbean.name.clone(), attribute: attribute.name.clone() };
+
+        if let Some(i) = self.pinned.iter().position(|p| p == &pin) {
+            self.pinned.remove(i);
+            self.pinned_values.remove(&pin);
+        } else if attribute.numeric.is_some() {
+            self.pinned.push(pin);
+        } else {
+            return None;
+        }
+        Some(self.pinned.clone())
+    }
+
+    /// Appends values of pinned attributes. The fetcher reports every pinned attribute, so it's the source of truth for pins.
+    pub fn append_pinned_values(&mut self, values: Vec<(MBeanAttributeRef, Option<f64>)>) {
+        self.pinned = values.iter().map(|(a, _)| a.clone()).collect();
+        let pinned = &self.pinned;
+        self.pinned_values.retain(|a, _| pinned.contains(a));
+        for (attribute, value) in values {
+            if let Some(v) = value {
+                let measures = self.pinned_values.entry(attribute).or_default();
+                if measures.len() > MBeansTab::MAX_PINNED_MEASURES {
+                    measures.pop_front();
+                }
+                measures.push_back(v);
+            }
+        }
+    }
+}
+
 pub struct StatefulList<T> {
     pub state: ListState,
     pub items: Vec<T>,
@@ -335,92 +1065,339 @@ impl<T> StatefulList<T> {
     }
 }
 
-pub struct App<'a> {
-    pub title: &'a str,
-    pub should_quit: bool,
-    pub exit_reason: Option<String>,
+/// Tab state of a single monitored target.
+pub struct TargetState {
     pub tabs: TabsState<AppTabKind>,
     pub zmx: Option<ZMXTab>,
     pub slick: Option<SlickTab>,
+    pub jvm: Option<JvmTab>,
+    pub threads: Option<ThreadsTab>,
     pub akka: Option<AkkaTab>,
+    pub mbeans: Option<MBeansTab>,
+    pub health: HashMap<Source, SourceHealth>,
 }
 
-impl<'a> App<'a> {
-    pub fn new(
-        title: &'a str,
-        zio_zmx_addr: Option<String>,
-        jmx: Option<JMXConnectionSettings>,
-        akka: Option<AkkaSettings>) -> App<'a> {
+impl TargetState {
+    pub fn new(settings: &TargetSettings) -> TargetState {
         let mut tabs: Vec<Tab<AppTabKind>> = vec![];
 
-        if let Some(_) = zio_zmx_addr {
-            tabs.push(Tab { kind: AppTabKind::ZMX, title: "ZIO".to_owned() })
+        if settings.zio_zmx.is_some() {
+            tabs.push(Tab { kind: AppTabKind::Zmx, title: "ZIO".to_owned() })
         }
 
-        if let Some(_) = jmx {
+        let has_db_pool = settings.jmx.as_ref().is_some_and(|j| j.db_pools.is_some());
+        if has_db_pool {
             tabs.push(Tab { kind: AppTabKind::Slick, title: "Slick".to_owned() })
         }
 
-        if let Some(_) = akka {
+        if settings.jmx.is_some() {
+            tabs.push(Tab { kind: AppTabKind::Jvm, title: "JVM".to_owned() });
+            tabs.push(Tab { kind: AppTabKind::Threads, title: "Threads".to_owned() })
+        }
+
+        if settings.akka.is_some() {
             tabs.push(Tab { kind: AppTabKind::Akka, title: "Akka".to_owned() })
         }
 
+        if settings.jmx.is_some() {
+            tabs.push(Tab { kind: AppTabKind::MBeans, title: "MBeans".to_owned() })
+        }
+
+        let health = tabs.iter().map(|t| (t.kind.source(), SourceHealth::new())).collect();
+
+        TargetState {
+            tabs: TabsState::new(tabs),
+            zmx: settings.zio_zmx.as_ref().map(|_| ZMXTab::new()),
+            slick: if has_db_pool { Some(SlickTab::new()) } else { None },
+            jvm: settings.jmx.as_ref().map(|_| JvmTab::new()),
+            threads: settings.jmx.as_ref().map(|_| ThreadsTab::new()),
+            akka: settings.akka.as_ref().map(|_| AkkaTab::new()),
+            mbeans: settings.jmx.as_ref().map(|_| MBeansTab::new()),
+            health,
+        }
+    }
+
+    pub fn health(&self, source: Source) -> Option<&SourceHealth> {
+        self.health.get(&source)
+    }
+
+    /// The worst status among all sources of this target.
+    pub fn status(&self) -> HealthStatus {
+        let statuses: Vec<HealthStatus> = self.health.values().map(|h| h.status()).collect();
+        [HealthStatus::Down, HealthStatus::Degraded, HealthStatus::Connecting].iter()
+            .find(|s| statuses.contains(s))
+            .cloned()
+            .unwrap_or(HealthStatus::Connected)
+    }
+
+    /// Data of the current tab that can be saved to a file, as it was fetched.
+    pub fn current_snapshot(&self) -> Option<(SnapshotKind, FetcherResponse)> {
+        match self.tabs.current().kind {
+            AppTabKind::Zmx => self.zmx.as_ref()
+                .filter(|z| !z.snapshots.is_empty())
+                .map(|z| (SnapshotKind::Fibers, FetcherResponse::FiberDump(Ok(z.latest_fibers().to_vec())))),
+            AppTabKind::Threads => self.threads.as_ref()
+                .and_then(|t| t.thread_dump.clone())
+                .map(|d| (SnapshotKind::Threads, FetcherResponse::ThreadDump(Ok(d)))),
+            AppTabKind::Akka => self.akka.as_ref()
+                .filter(|a| !a.actors.nodes().is_empty())
+                .map(|a| (SnapshotKind::Actors, FetcherResponse::ActorTree(Ok(a.actors.nodes().to_vec())))),
+            AppTabKind::Slick | AppTabKind::Jvm | AppTabKind::MBeans => None,
+        }
+    }
+
+    fn update_health(&mut self, response: &FetcherResponse) {
+        let request = response.request();
+        let health = match self.health.get_mut(&request.source()) {
+            Some(h) => h,
+            None => return,
+        };
+        match response {
+            // hikari is optional, so its absence doesn't say anything about jmx health
+            _ if request.is_hikari() && (response.is_err() || matches!(response, FetcherResponse::RetryScheduled(_, _))) => {}
+            FetcherResponse::RetryScheduled(_, retry_in) => health.on_retry_scheduled(&request, *retry_in),
+            _ => match response.error() {
+                Some(e) => health.on_failure(&request, e.to_owned()),
+                None => health.on_success(&request),
+            }
+        }
+    }
+
+    /// Applies a fetcher response to the tab state.
+    /// Failures only change source health, so the data gathered so far is kept.
+    pub fn on_fetcher_response(&mut self, response: FetcherResponse) {
+        self.update_health(&response);
+        self.apply_response(response);
+    }
+
+    /// Applies the response to an auto-refresh of the fiber dump or actor tree shown.
+    pub fn on_refresh_response(&mut self, response: FetcherResponse) {
+        self.update_health(&response);
+        match response {
+            FetcherResponse::FiberDump(Ok(x)) =>
+                self.zmx.as_mut().unwrap().refresh_fiber_dump(x),
+            response => self.apply_response(response),
+        }
+    }
+
+    fn apply_response(&mut self, response: FetcherResponse) {
+        match response {
+            FetcherResponse::FiberDump(Ok(x)) =>
+                self.zmx.as_mut().unwrap().replace_fiber_dump(x),
+            FetcherResponse::FiberCount(Ok(x)) =>
+                self.zmx.as_mut().unwrap().append_fiber_count(x),
+            FetcherResponse::HikariMetrics(d) =>
+                self.slick.as_mut().unwrap().append_hikari_metrics(d.unwrap_or_default()),
+            FetcherResponse::HikariConfig(Ok(x)) =>
+                self.slick.as_mut().unwrap().replace_hikari_config(x),
+            FetcherResponse::HikariTimings(d) =>
+                self.slick.as_mut().unwrap().replace_hikari_timings(d.unwrap_or_default()),
+            FetcherResponse::SlickMetrics(Ok(x)) =>
+                self.slick.as_mut().unwrap().append_slick_metrics(x),
+            FetcherResponse::SlickConfig(Ok(x)) =>
+                self.slick.as_mut().unwrap().replace_slick_config(x),
+            FetcherResponse::JvmMetrics(Ok(x)) =>
+                self.jvm.as_mut().unwrap().append_jvm_metrics(x),
+            FetcherResponse::ThreadDump(Ok(x)) =>
+                self.threads.as_mut().unwrap().replace_thread_dump(x),
+            FetcherResponse::ActorTree(Ok(x)) => {
+                let akka = self.akka.as_mut().unwrap();
+                akka.update_actor_tree(x);
+                akka.reload_dead_letters_log();
+            }
+            FetcherResponse::ActorSystemStatus(Ok(x)) =>
+                self.akka.as_mut().unwrap().append_system_status(x),
+            FetcherResponse::DeadLetters(Ok(x)) =>
+                self.akka.as_mut().unwrap().append_dead_letters(x.0, x.1),
+            FetcherResponse::MBeanNames(Ok(x)) =>
+                self.mbeans.as_mut().unwrap().replace_mbean_names(x),
+            FetcherResponse::MBean(_, Ok(x)) =>
+                self.mbeans.as_mut().unwrap().replace_mbean(x),
+            FetcherResponse::PinnedAttributes(Ok(x)) =>
+                self.mbeans.as_mut().unwrap().append_pinned_values(x),
+            _ => {}
+        }
+    }
+}
+
+pub struct App<'a> {
+    pub title: &'a str,
+    pub should_quit: bool,
+    pub exit_reason: Option<String>,
+    pub targets: TabsState<String>,
+    pub target_states: HashMap<String, TargetState>,
+    /// Playback position and controls, shown in the header when replaying a recorded session.
+    pub replay_status: Option<String>,
+    pub alerts: Alerts,
+    pub show_alert_history: bool,
+    /// Where snapshots saved with `<W>` go.
+    pub save_dir: PathBuf,
+    /// Outcome of the last action that isn't visible otherwise, e.g. the path of a saved snapshot.
+    /// Set with `show_notice`, it's cleared on the first tick after `NOTICE_DURATION`, otherwise it stays.
+    pub notice: Option<String>,
+    notice_shown_at: Option<Instant>,
+    /// Whether the fiber dump or actor tree shown is fetched again every `refresh_interval`.
+    pub auto_refresh: bool,
+    pub refresh_interval: Duration,
+    last_refresh: Option<Instant>,
+    /// Target and request of the auto-refresh that hasn't been answered yet.
+    refreshing: Option<(String, FetcherRequest)>,
+    /// How many fiber dumps every ZIO tab keeps for comparison.
+    fiber_snapshots: usize,
+}
+
+impl<'a> App<'a> {
+    pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+    pub const NOTICE_DURATION: Duration = Duration::from_secs(6);
+
+    pub fn new(title: &'a str, targets: &[TargetSettings]) -> App<'a> {
+        let target_tabs: Vec<Tab<String>> = targets.iter()
+            .map(|t| Tab { kind: t.name.clone(), title: t.name.clone() })
+            .collect();
+        let target_states: HashMap<String, TargetState> = targets.iter()
+            .map(|t| (t.name.clone(), TargetState::new(t)))
+            .collect();
+
         App {
             title,
             should_quit: false,
             exit_reason: None,
-            tabs: TabsState::new(tabs),
-            zmx: zio_zmx_addr.map(|_| ZMXTab::new()),
-            slick: jmx.map(|_| SlickTab::new()),
-            akka: akka.map(|_| AkkaTab::new()),
+            targets: TabsState::new(target_tabs),
+            target_states,
+            replay_status: None,
+            alerts: Alerts::default(),
+            show_alert_history: false,
+            save_dir: PathBuf::from("."),
+            notice: None,
+            notice_shown_at: None,
+            auto_refresh: false,
+            refresh_interval: App::DEFAULT_REFRESH_INTERVAL,
+            last_refresh: None,
+            refreshing: None,
+            fiber_snapshots: ZMXTab::DEFAULT_FIBER_SNAPSHOTS,
+        }
+    }
+
+    /// Drops all fetched data, keeping the selected tab of every target.
+    pub fn reset(&mut self, targets: &[TargetSettings]) {
+        for t in targets {
+            let mut state = TargetState::new(t);
+            if let Some(old) = self.target_states.get(&t.name) {
+                state.tabs.index = old.tabs.index;
+            }
+            self.target_states.insert(t.name.clone(), state);
+        }
+        self.set_fiber_snapshots(self.fiber_snapshots);
+        self.alerts.reset();
+    }
+
+    pub fn set_fiber_snapshots(&mut self, n: usize) {
+        self.fiber_snapshots = n;
+        for zmx in self.target_states.values_mut().filter_map(|t| t.zmx.as_mut()) {
+            zmx.max_snapshots = n;
+        }
+    }
+
+    pub fn current_target(&self) -> &str {
+        &self.targets.current().kind
+    }
+
+    pub fn current(&self) -> &TargetState {
+        &self.target_states[self.current_target()]
+    }
+
+    pub fn current_mut(&mut self) -> &mut TargetState {
+        let name = self.targets.current().kind.clone();
+        self.target_states.get_mut(&name).unwrap()
+    }
+
+    pub fn target_mut(&mut self, name: &str) -> &mut TargetState {
+        self.target_states.get_mut(name).unwrap()
+    }
+
+    /// Applies a response that arrived at `now`, the time it was recorded at in case of a replay.
+    pub fn on_fetcher_response(&mut self, target: &str, response: FetcherResponse, now: DateTime<Local>) {
+        let request = response.request();
+        let failed = response.is_err();
+        let has_samples = !failed && !matches!(response, FetcherResponse::RetryScheduled(_, _));
+        if self.refreshing.as_ref().is_some_and(|(t, r)| t == target && *r == request) {
+            self.refreshing = None;
+            self.target_mut(target).on_refresh_response(response);
+        } else {
+            self.target_mut(target).on_fetcher_response(response);
+        }
+        if has_samples {
+            self.alerts.on_response(target, &self.target_states[target], &request, now);
+        } else if failed {
+            self.alerts.on_error(target, &request, now);
         }
     }
 
     pub fn on_up(&mut self) {
-        match self.tabs.current().kind {
-            AppTabKind::ZMX => self.zmx.as_mut().unwrap().select_prev_fiber(),
-            AppTabKind::Slick => {}
-            AppTabKind::Akka => self.akka.as_mut().unwrap().dead_letters_log.previous()
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().select_prev_fiber(),
+            AppTabKind::Slick | AppTabKind::Jvm => {}
+            AppTabKind::Threads => t.threads.as_mut().unwrap().select_prev_thread(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().dead_letters_log.previous(),
+            AppTabKind::MBeans => t.mbeans.as_mut().unwrap().mbeans.previous(),
         }
     }
 
     pub fn on_down(&mut self) {
-        match self.tabs.current().kind {
-            AppTabKind::ZMX => self.zmx.as_mut().unwrap().select_next_fiber(),
-            AppTabKind::Slick => {}
-            AppTabKind::Akka => self.akka.as_mut().unwrap().dead_letters_log.next()
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().select_next_fiber(),
+            AppTabKind::Slick | AppTabKind::Jvm => {}
+            AppTabKind::Threads => t.threads.as_mut().unwrap().select_next_thread(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().dead_letters_log.next(),
+            AppTabKind::MBeans => t.mbeans.as_mut().unwrap().mbeans.next(),
         }
     }
 
     pub fn on_right(&mut self) {
-        self.tabs.next();
+        self.current_mut().tabs.next();
     }
 
     pub fn on_left(&mut self) {
-        self.tabs.previous();
+        self.current_mut().tabs.previous();
+    }
+
+    pub fn on_next_target(&mut self) {
+        self.targets.next();
+    }
+
+    pub fn on_prev_target(&mut self) {
+        self.targets.previous();
     }
 
     pub fn on_right_alt(&mut self) {
-        match self.tabs.current().kind {
-            AppTabKind::ZMX => {}
-            AppTabKind::Slick => {}
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => {}
+            AppTabKind::Slick => t.slick.as_mut().unwrap().select_next_pool(),
+            AppTabKind::Jvm | AppTabKind::Threads => {}
             AppTabKind::Akka => {
-                let akka = self.akka.as_mut().unwrap();
+                let akka = t.akka.as_mut().unwrap();
                 akka.dead_letters_tabs.next();
                 akka.reload_dead_letters_log();
             }
+            AppTabKind::MBeans => {}
         }
     }
 
     pub fn on_left_alt(&mut self) {
-        match self.tabs.current().kind {
-            AppTabKind::ZMX => {}
-            AppTabKind::Slick => {}
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => {}
+            AppTabKind::Slick => t.slick.as_mut().unwrap().select_prev_pool(),
+            AppTabKind::Jvm | AppTabKind::Threads => {}
             AppTabKind::Akka => {
-                let akka = self.akka.as_mut().unwrap();
+                let akka = t.akka.as_mut().unwrap();
                 akka.dead_letters_tabs.previous();
                 akka.reload_dead_letters_log();
             }
+            AppTabKind::MBeans => {}
         }
     }
 
@@ -429,28 +1406,187 @@ impl<'a> App<'a> {
             'q' => self.quit(None),
             'a' => self.on_left_alt(),
             'd' => self.on_right_alt(),
+            'g' => self.on_group(),
+            'h' => self.show_alert_history = !self.show_alert_history,
+            '/' => self.with_zmx(ZMXTab::start_search),
+            'x' => self.with_zmx(ZMXTab::toggle_hide_done),
+            's' => self.with_zmx(ZMXTab::toggle_only_suspended),
+            'o' => self.with_zmx(ZMXTab::toggle_sort_by_age),
+            'c' => self.with_zmx(ZMXTab::toggle_diff),
+            'f' => self.on_fold(),
+            'e' => self.on_expand_all(),
+            'F' => self.on_collapse_all(),
+            'r' => self.toggle_auto_refresh(),
+            'w' => self.save_snapshot(SnapshotFormat::Text),
+            'W' => self.save_snapshot(SnapshotFormat::Json),
             _ => {}
         }
     }
 
+    pub fn toggle_auto_refresh(&mut self) {
+        self.auto_refresh = !self.auto_refresh;
+        self.last_refresh = None;
+    }
+
+    /// The request to fetch the fiber dump or actor tree shown again, if auto-refresh is on and it's time to.
+    /// Nothing is requested while the previous refresh is still in flight.
+    pub fn refresh_request(&mut self, now: Instant) -> Option<FetcherRequest> {
+        if !self.auto_refresh || self.refreshing.is_some() || self.last_refresh.is_some_and(|t| now.duration_since(t) < self.refresh_interval) {
+            return None;
+        }
+        let request = match self.current().tabs.current().kind {
+            AppTabKind::Zmx => FetcherRequest::FiberDump,
+            AppTabKind::Akka => FetcherRequest::ActorTree,
+            _ => return None,
+        };
+        self.last_refresh = Some(now);
+        self.refreshing = Some((self.current_target().to_owned(), request.clone()));
+        Some(request)
+    }
+
+    pub fn show_notice(&mut self, notice: String, now: Instant) {
+        self.notice = Some(notice);
+        self.notice_shown_at = Some(now);
+    }
+
+    /// Clears the notice once it's been shown for `NOTICE_DURATION`.
+    pub fn on_tick(&mut self, now: Instant) {
+        if self.notice_shown_at.is_some_and(|t| now.duration_since(t) >= App::NOTICE_DURATION) {
+            self.notice = None;
+            self.notice_shown_at = None;
+        }
+    }
+
+    /// Saves the fiber dump, thread dump or actor tree of the current tab to `save_dir`.
+    pub fn save_snapshot(&mut self, format: SnapshotFormat) {
+        let target = self.current_target().to_owned();
+        let notice = match self.current().current_snapshot() {
+            Some((kind, response)) => match snapshot::save(&self.save_dir, &target, kind, response, format) {
+                Ok(path) => format!("Saved {}", path.display()),
+                Err(e) => e,
+            },
+            None => match self.current().tabs.current().kind {
+                AppTabKind::Zmx => "No fiber dump to save yet, take one with <Enter>",
+                AppTabKind::Threads => "No thread dump to save yet, take one with <Enter>",
+                AppTabKind::Akka => "No actor tree to save yet, load it with <Enter>",
+                AppTabKind::Slick | AppTabKind::Jvm | AppTabKind::MBeans =>
+                    "Only fiber dumps, thread dumps and actor trees can be saved, switch to their tab",
+            }.to_owned(),
+        };
+        self.show_notice(notice, Instant::now());
+    }
+
+    /// Applies an action to the ZIO tab, if it's the one shown.
+    fn with_zmx<F>(&mut self, f: F) where F: FnOnce(&mut ZMXTab) {
+        let t = self.current_mut();
+        if let AppTabKind::Zmx = t.tabs.current().kind {
+            f(t.zmx.as_mut().unwrap())
+        }
+    }
+
+    /// Whether typed characters go to the fiber search query, rather than being handled as keys.
+    pub fn is_searching(&self) -> bool {
+        let t = self.current();
+        matches!(t.tabs.current().kind, AppTabKind::Zmx) && t.zmx.as_ref().is_some_and(|z| z.searching)
+    }
+
+    pub fn on_search_char(&mut self, c: char) {
+        self.with_zmx(|z| z.push_search_char(c))
+    }
+
+    pub fn on_search_backspace(&mut self) {
+        self.with_zmx(ZMXTab::pop_search_char)
+    }
+
+    /// Stops typing the search query, keeping it applied or dropping it.
+    pub fn on_search_end(&mut self, keep_query: bool) {
+        if keep_query {
+            self.with_zmx(ZMXTab::finish_search)
+        } else {
+            self.with_zmx(ZMXTab::cancel_search)
+        }
+    }
+
+    pub fn on_group(&mut self) {
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().toggle_group_by_site(),
+            AppTabKind::Threads => t.threads.as_mut().unwrap().toggle_group_stacks(),
+            _ => {}
+        }
+    }
+
+    /// Collapses or expands the selected node of the fiber or actor tree.
+    pub fn on_fold(&mut self) {
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().toggle_fold(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().actors.toggle_selected(),
+            _ => {}
+        }
+    }
+
+    pub fn on_expand_all(&mut self) {
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().expand_all(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().actors.expand_all(),
+            _ => {}
+        }
+    }
+
+    pub fn on_collapse_all(&mut self) {
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().collapse_all(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().actors.collapse_all(),
+            _ => {}
+        }
+    }
+
+    /// Jumps to the parent of the selected fiber or actor.
+    pub fn on_parent(&mut self) {
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().select_parent(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().actors.select_parent(),
+            _ => {}
+        }
+    }
+
+    /// Pins or unpins the selected MBean attribute, returning the new set of pins to be polled.
+    pub fn on_pin(&mut self) -> Option<Vec<MBeanAttributeRef>> {
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::MBeans => t.mbeans.as_mut().unwrap().toggle_pin(),
+            _ => None,
+        }
+    }
+
     pub fn quit(&mut self, error: Option<String>) {
         self.should_quit = true;
         self.exit_reason = error;
     }
 
     pub fn on_page_up(&mut self) {
-        match self.tabs.current().kind {
-            AppTabKind::ZMX => self.zmx.as_mut().unwrap().scroll_up(),
-            AppTabKind::Slick => {}
-            AppTabKind::Akka => self.akka.as_mut().unwrap().select_prev_actor(),
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().scroll_up(),
+            AppTabKind::Slick | AppTabKind::Jvm => {}
+            AppTabKind::Threads => t.threads.as_mut().unwrap().scroll_up(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().select_prev_actor(),
+            AppTabKind::MBeans => t.mbeans.as_mut().unwrap().attributes.previous(),
         }
     }
 
     pub fn on_page_down(&mut self) {
-        match self.tabs.current().kind {
-            AppTabKind::ZMX => self.zmx.as_mut().unwrap().scroll_down(),
-            AppTabKind::Slick => {}
-            AppTabKind::Akka => self.akka.as_mut().unwrap().select_next_actor(),
+        let t = self.current_mut();
+        match t.tabs.current().kind {
+            AppTabKind::Zmx => t.zmx.as_mut().unwrap().scroll_down(),
+            AppTabKind::Slick | AppTabKind::Jvm => {}
+            AppTabKind::Threads => t.threads.as_mut().unwrap().scroll_down(),
+            AppTabKind::Akka => t.akka.as_mut().unwrap().select_next_actor(),
+            AppTabKind::MBeans => t.mbeans.as_mut().unwrap().attributes.next(),
         }
     }
 }
@@ -459,44 +1595,36 @@ impl<'a> App<'a> {
 mod tests {
     use std::collections::VecDeque;
 
-    use crate::app::{StatefulList, ZMXTab};
-    use crate::zio::model::{Fiber, FiberStatus};
-    use crate::zio::zmx::StubZMXClient;
+    use std::time::{Duration, Instant};
+
+    use chrono::Local;
+
+    use crate::akka::model::{ActorTreeNode, DeadLettersMessage, Timestamped};
+    use crate::app::{AkkaTab, App, AppTabKind, DbPool, FiberFilter, MBeansTab, SlickTab, StatefulList, TargetState, ThreadsTab, ZMXTab};
+    use crate::fetcher::{FetcherRequest, FetcherResponse};
+    use crate::health::{HealthStatus, Source};
+    use crate::jmx::model::{GarbageCollectorMetrics, HikariMetrics, JMXConnectionSettings, JvmMetrics, MBean, MBeanAttributeRef, MBeanAttributeValue, MemoryUsage, SlickMetrics, StackFrame, ThreadDump, ThreadInfo, ThreadState};
+    use crate::target::TargetSettings;
+    use crate::zio::model::{Fiber, FiberCount, FiberStatus, TraceElement};
 
     #[test]
     fn zmx_tab_dumps_fibers() {
-        let fiber1 = Fiber {
-            id: 1,
-            parent_id: None,
-            status: FiberStatus::Running,
-            dump: "1".to_owned(),
-        };
-        let fiber2 = Fiber {
-            id: 2,
-            parent_id: Some(1),
-            status: FiberStatus::Suspended,
-            dump: "2".to_owned(),
-        };
-        let fiber4 = Fiber {
-            id: 4,
-            parent_id: None,
-            status: FiberStatus::Done,
-            dump: "4".to_owned(),
-        };
+        let fiber1 = Fiber::new(1, None, FiberStatus::Running, "1".to_owned());
+        let fiber2 = Fiber::new(2, Some(1), FiberStatus::Suspended, "2".to_owned());
+        let fiber4 = Fiber::new(4, None, FiberStatus::Done, "4".to_owned());
 
         let fibers = vec![fiber1, fiber2, fiber4];
 
-        let mut tab = ZMXTab {
-            fibers: StatefulList::with_items(vec!["Fiber #1".to_owned()]),
-            selected_fiber_dump: ("".to_string(), 0),
-            fiber_dump_all: vec![],
-            scroll: 0,
-            fiber_counts: VecDeque::new(),
-        };
+        let mut tab = ZMXTab::new();
+        tab.fibers = StatefulList::with_items(vec!["Fiber #1".to_owned()]);
 
         tab.replace_fiber_dump(fibers);
 
-        assert_eq!(tab.fiber_dump_all, vec!["1", "2", "4"]);
+        assert_eq!(tab.fiber_dump_all, vec![
+            "#1 Running\n\nDump:\n1",
+            "#2 Suspended\n\nDump:\n2",
+            "#4 Done\n\nDump:\n4"
+        ]);
         assert_eq!(tab.fibers.items, vec![
             "├─#1   Running",
             "│ └─#2 Suspended",
@@ -504,4 +1632,560 @@ mod tests {
         ]);
         assert_eq!(tab.fibers.state.selected(), Some(0));
     }
+
+    fn fiber(id: usize, parent_id: Option<usize>, status: FiberStatus, dump: &str) -> Fiber {
+        Fiber::new(id, parent_id, status, format!("#{} (1s)\n{}", id, dump))
+    }
+
+    fn fiber_family() -> Vec<Fiber> {
+        vec![
+            fiber(1, None, FiberStatus::Running, "zio.App.main"),
+            fiber(2, Some(1), FiberStatus::Suspended, "at com.acme.OrderService.place"),
+            fiber(3, Some(2), FiberStatus::Done, "at com.acme.Inventory.reserve"),
+            fiber(4, None, FiberStatus::Done, "zio.Clock.sleep"),
+        ]
+    }
+
+    fn actor(id: usize, parent: Option<usize>, name: &str) -> ActorTreeNode {
+        ActorTreeNode { name: name.to_owned(), parent, id }
+    }
+
+    fn zmx_target(name: &str) -> TargetSettings {
+        TargetSettings {
+            name: name.to_owned(),
+            zio_zmx: Some("localhost:6789".to_owned()),
+            jmx: None,
+            akka: None,
+        }
+    }
+
+    #[test]
+    fn fiber_filter_keeps_parents_of_matches() {
+        let filter = FiberFilter { query: "inventory".to_owned(), ..FiberFilter::default() };
+        let ids: Vec<usize> = filter.apply(&fiber_family()).iter().map(|f| f.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let filter = FiberFilter { query: "#4".to_owned(), ..FiberFilter::default() };
+        let ids: Vec<usize> = filter.apply(&fiber_family()).iter().map(|f| f.id).collect();
+        assert_eq!(ids, vec![4]);
+
+        let filter = FiberFilter { query: "SUSPENDED".to_owned(), ..FiberFilter::default() };
+        let ids: Vec<usize> = filter.apply(&fiber_family()).iter().map(|f| f.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn zmx_tab_lists_fibers_with_missing_parents_as_roots() {
+        // a partial dump, e.g. pasted from logs, where the parent of #7 didn't make it
+        let mut fibers = fiber_family();
+        fibers.push(fiber(7, Some(42), FiberStatus::Suspended, "at com.acme.Payments.charge"));
+        let mut tab = ZMXTab::new();
+        tab.replace_fiber_dump(fibers);
+        assert_eq!(tab.fiber_ids, vec![Some(1), Some(2), Some(3), Some(4), Some(7)]);
+
+        tab.filter = FiberFilter { query: "payments".to_owned(), ..FiberFilter::default() };
+        tab.replace_fiber_dump(tab.latest_fibers().to_vec());
+        assert_eq!(tab.fibers.items, vec!["└─#7 Suspended"]);
+    }
+
+    #[test]
+    fn fiber_filter_toggles_statuses() {
+        let filter = FiberFilter { hide_done: true, ..FiberFilter::default() };
+        let ids: Vec<usize> = filter.apply(&fiber_family()).iter().map(|f| f.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        let filter = FiberFilter { only_suspended: true, query: "acme".to_owned(), ..FiberFilter::default() };
+        let ids: Vec<usize> = filter.apply(&fiber_family()).iter().map(|f| f.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn zmx_tab_search_keeps_selected_fiber() {
+        let mut tab = ZMXTab::new();
+        tab.replace_fiber_dump(fiber_family());
+        tab.select_next_fiber();
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(2));
+
+        tab.start_search();
+        "acme".chars().for_each(|c| tab.push_search_char(c));
+        assert_eq!(tab.fiber_ids, vec![Some(1), Some(2), Some(3)]);
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(2));
+        assert!(tab.selected_fiber_dump.0.contains("OrderService"));
+
+        tab.push_search_char('!');
+        assert!(tab.fibers.items.is_empty());
+        assert_eq!(tab.fibers.state.selected(), None);
+        assert_eq!(tab.selected_fiber_dump.0, "");
+
+        tab.cancel_search();
+        assert!(!tab.searching);
+        assert_eq!(tab.fiber_ids, vec![Some(1), Some(2), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn zmx_tab_sorts_and_groups_fibers() {
+        let promise = TraceElement {
+            class_name: "zio.Promise".to_owned(),
+            method_name: "await".to_owned(),
+            file_name: Some("Promise.scala".to_owned()),
+            line_number: Some(50),
+        };
+        let mut fibers = fiber_family();
+        fibers.push(fiber(5, Some(1), FiberStatus::Suspended, "at com.acme.Payments.charge"));
+        for f in fibers.iter_mut() {
+            f.lifetime_ms = Some(f.id as u64 * 1000);
+            if f.status == FiberStatus::Suspended {
+                f.suspended_at = Some(promise.clone());
+            }
+        }
+        let mut tab = ZMXTab::new();
+        tab.replace_fiber_dump(fibers);
+
+        tab.toggle_sort_by_age();
+        assert_eq!(tab.fibers.items, vec![
+            "├─#4     Done 4s",
+            "└─#1     Running 1s",
+            "  ├─#5   Suspended 5s",
+            "  └─#2   Suspended 2s",
+            "    └─#3 Done 3s",
+        ]);
+
+        tab.toggle_group_by_site();
+        assert_eq!(tab.fibers.items, vec![
+            "zio.Promise.await(Promise.scala:50) (2)",
+            "├─#5 Suspended 5s",
+            "└─#2 Suspended 2s",
+            "Done (2)",
+            "├─#4 Done 4s",
+            "└─#3 Done 3s",
+            "Running (1)",
+            "└─#1 Running 1s",
+        ]);
+        assert_eq!(tab.fiber_ids[0], None);
+        assert!(tab.fiber_dump_all[0].starts_with("zio.Promise.await(Promise.scala:50) (2):\n#5 Suspended 5s"));
+    }
+
+    #[test]
+    fn zmx_tab_keeps_snapshots_for_diff() {
+        let mut tab = ZMXTab::new();
+        tab.toggle_diff();
+        tab.replace_fiber_dump(fiber_family());
+        assert!(tab.fibers.items.is_empty());
+
+        let mut fibers = fiber_family();
+        fibers[1].status = FiberStatus::Running;
+        fibers.remove(2);
+        fibers.push(fiber(5, Some(1), FiberStatus::Suspended, "at com.acme.Payments.charge"));
+        tab.replace_fiber_dump(fibers);
+        assert_eq!(tab.fibers.items, vec![
+            "New (1)",
+            "└─#5 Suspended",
+            "Changed (1)",
+            "└─#2 Suspended → Running",
+            "In every snapshot (1)",
+            "└─#1 Running",
+        ]);
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(5));
+
+        tab.select_next_fiber();
+        tab.select_next_fiber();
+        assert!(tab.selected_fiber_dump.0.starts_with("Was: Suspended\nNow: Running\n\n#2 Running"));
+
+        for _ in 0..ZMXTab::DEFAULT_FIBER_SNAPSHOTS {
+            tab.replace_fiber_dump(vec![]);
+        }
+        assert_eq!(tab.snapshots.len(), ZMXTab::DEFAULT_FIBER_SNAPSHOTS);
+        assert!(tab.fibers.items.is_empty());
+
+        tab.max_snapshots = 2;
+        tab.replace_fiber_dump(vec![]);
+        assert_eq!(tab.snapshots.len(), 2);
+    }
+
+    #[test]
+    fn zmx_tab_folds_fiber_tree() {
+        let mut tab = ZMXTab::new();
+        tab.replace_fiber_dump(fiber_family());
+        tab.select_next_fiber();
+
+        tab.toggle_fold();
+        assert_eq!(tab.fiber_ids, vec![Some(1), Some(2), Some(4)]);
+        assert!(tab.fibers.items[1].ends_with("Suspended [+1]"));
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(2));
+
+        tab.select_parent();
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(1));
+
+        // folding is kept across dumps and filters
+        tab.replace_fiber_dump(fiber_family());
+        tab.toggle_hide_done();
+        assert_eq!(tab.fiber_ids, vec![Some(1), Some(2)]);
+        tab.toggle_hide_done();
+
+        tab.select_next_fiber();
+        tab.collapse_all();
+        assert_eq!(tab.fiber_ids, vec![Some(1), Some(4)]);
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(1));
+
+        tab.expand_all();
+        assert_eq!(tab.fiber_ids, vec![Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(1));
+    }
+
+    #[test]
+    fn zmx_tab_keeps_selected_fiber_across_dumps() {
+        let mut tab = ZMXTab::new();
+        tab.replace_fiber_dump(fiber_family());
+        tab.select_next_fiber();
+        tab.select_next_fiber();
+        tab.scroll_down();
+
+        let mut fibers = fiber_family();
+        fibers.insert(0, fiber(5, None, FiberStatus::Running, "zio.Clock.sleep"));
+        tab.replace_fiber_dump(fibers);
+        assert_eq!(tab.fiber_ids[tab.fibers.state.selected().unwrap()], Some(3));
+        assert_eq!(tab.scroll, 1);
+
+        // the selected fiber is gone
+        tab.replace_fiber_dump(fiber_family().into_iter().filter(|f| f.id != 3).collect());
+        assert_eq!(tab.fibers.state.selected(), Some(0));
+        assert_eq!(tab.scroll, 0);
+    }
+
+    #[test]
+    fn akka_tab_keeps_selected_actor_by_path() {
+        let mut tab = AkkaTab::new();
+        tab.update_actor_tree(vec![
+            actor(1, None, "user"),
+            actor(2, Some(1), "orders"),
+            actor(3, Some(2), "worker"),
+            actor(4, None, "system"),
+        ]);
+        tab.select_next_actor();
+        tab.actors.toggle_selected();
+        tab.select_next_actor();
+        assert_eq!(tab.actors.selected().unwrap().name, "system");
+
+        // ids are assigned in the order of the response, so they change when the tree does
+        tab.update_actor_tree(vec![
+            actor(1, None, "system"),
+            actor(2, Some(1), "log"),
+            actor(3, None, "user"),
+            actor(4, Some(3), "orders"),
+            actor(5, Some(4), "worker"),
+        ]);
+        assert_eq!(tab.actors.selected().unwrap().name, "system");
+        let labels: Vec<&str> = tab.actors.rows().iter().map(|r| r.0.trim_end()).collect();
+        assert_eq!(labels, vec!["├─#   system", "│ └─# log", "└─#   user", "  └─# orders [+1]"]);
+    }
+
+    #[test]
+    fn akka_tab_shows_dead_letters_of_selected_actor() {
+        let message = |message: &str, sender: &str, recipient: &str| DeadLettersMessage {
+            message: message.to_owned(),
+            sender: sender.to_owned(),
+            recipient: recipient.to_owned(),
+        };
+        let mut tab = AkkaTab::new();
+        tab.update_actor_tree(vec![
+            actor(1, None, "user"),
+            actor(2, Some(1), "orders"),
+            actor(3, Some(2), "worker"),
+            actor(4, Some(2), "worker-2"),
+            actor(5, None, "orders"),
+        ]);
+        tab.dead_letters_messages.dead_letters = vec![
+            Timestamped { value: message("Ping", "Actor[akka://shop/user/orders#1]", "Actor[akka://shop/user/gone#2]"), timestamp: 1000 },
+            Timestamped { value: message("Pong", "Actor[akka.tcp://shop@10.0.0.1:2552/user/orders/worker-2#3]", "Actor[akka.tcp://shop@10.0.0.1:2552/user/orders#1]"), timestamp: 2000 },
+            Timestamped { value: message("Tick", "Actor[akka://shop/user/orders/worker#4]", "Actor[akka://shop/deadLetters]"), timestamp: 3000 },
+        ];
+        assert_eq!(tab.actor_details().unwrap().lines().take(1).collect::<Vec<&str>>(), vec!["Path: /user"]);
+
+        tab.select_next_actor();
+        let details = tab.actor_details().unwrap();
+        let lines: Vec<&str> = details.lines().collect();
+        assert_eq!(lines[..3], ["Path: /user/orders", "Children: 2", "Dead letters: 1 sent, 1 received"]);
+        assert!(lines[3].starts_with("Dead letter <") && lines[3].ends_with("> Pong"));
+        assert_eq!(lines[4], "  from Actor[akka.tcp://shop@10.0.0.1:2552/user/orders/worker-2#3]");
+        assert_eq!(lines[6], "  to Actor[akka://shop/user/gone#2]");
+        assert_eq!(lines.len(), 7);
+
+        // a top-level actor with the same name isn't the one under /user
+        tab.actors.select(tab.actor_ids["/orders"]);
+        assert_eq!(tab.actor_details().unwrap().lines().last(), Some("Dead letters: 0 sent, 0 received"));
+    }
+
+    #[test]
+    fn app_refreshes_current_tree_periodically() {
+        let mut app = App::new("test", &[zmx_target("orders")]);
+        let now = Instant::now();
+        assert!(app.refresh_request(now).is_none());
+
+        app.on_fetcher_response("orders", FetcherResponse::FiberDump(Ok(fiber_family())), Local::now());
+        app.on_key('r');
+        assert!(matches!(app.refresh_request(now), Some(FetcherRequest::FiberDump)));
+        assert!(app.refresh_request(now + Duration::from_secs(1)).is_none());
+        // the previous refresh is still in flight
+        assert!(app.refresh_request(now + App::DEFAULT_REFRESH_INTERVAL).is_none());
+
+        app.on_fetcher_response("orders", FetcherResponse::FiberDump(Ok(fiber_family())), Local::now());
+        assert!(app.refresh_request(now + App::DEFAULT_REFRESH_INTERVAL).is_some());
+        app.on_fetcher_response("orders", FetcherResponse::FiberDump(Ok(fiber_family())), Local::now());
+        // refreshed dumps replace each other instead of filling the diff history
+        assert_eq!(app.current().zmx.as_ref().unwrap().snapshots.len(), 2);
+
+        app.on_key('r');
+        assert!(app.refresh_request(now + App::DEFAULT_REFRESH_INTERVAL * 2).is_none());
+    }
+
+    #[test]
+    fn app_saves_snapshot_of_current_tab() {
+        let mut app = App::new("test", &[zmx_target("orders/1")]);
+        app.save_dir = std::env::temp_dir().join("panopticon-save-test");
+
+        app.on_key('W');
+        assert_eq!(app.notice.as_deref(), Some("No fiber dump to save yet, take one with <Enter>"));
+
+        app.current_mut().zmx.as_mut().unwrap().replace_fiber_dump(fiber_family());
+        app.on_key('W');
+        let notice = app.notice.clone().unwrap();
+        let path = notice.strip_prefix("Saved ").unwrap();
+        assert!(path.contains("orders_1-fibers-") && path.ends_with(".json"));
+        let saved: Vec<Fiber> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(saved, fiber_family());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn app_tells_what_can_be_saved_on_tabs_without_snapshots() {
+        let jmx = JMXConnectionSettings::for_test("localhost:9010");
+        let mut app = App::new("test", &[TargetSettings { jmx: Some(jmx), ..zmx_target("orders") }]);
+
+        app.on_key('w');
+        assert_eq!(app.notice.as_deref(), Some("No fiber dump to save yet, take one with <Enter>"));
+
+        app.current_mut().tabs.index = app.current().tabs.tabs.iter().position(|t| matches!(t.kind, AppTabKind::Jvm)).unwrap();
+        app.on_key('w');
+        assert_eq!(app.notice.as_deref(), Some("Only fiber dumps, thread dumps and actor trees can be saved, switch to their tab"));
+    }
+
+    #[test]
+    fn app_clears_notice_after_a_while() {
+        let mut app = App::new("test", &[zmx_target("orders")]);
+        let now = Instant::now();
+        app.show_notice("Saved orders-fibers.txt".to_owned(), now);
+
+        app.on_tick(now + App::NOTICE_DURATION / 2);
+        assert_eq!(app.notice.as_deref(), Some("Saved orders-fibers.txt"));
+        app.on_tick(now + App::NOTICE_DURATION);
+        assert_eq!(app.notice, None);
+    }
+
+    #[test]
+    fn app_routes_search_keys_to_zio_tab() {
+        let mut app = App::new("test", &[zmx_target("orders")]);
+        app.current_mut().zmx.as_mut().unwrap().replace_fiber_dump(fiber_family());
+
+        app.on_key('/');
+        assert!(app.is_searching());
+        app.on_search_char('q');
+        app.on_search_backspace();
+        app.on_search_char('4');
+        app.on_search_end(true);
+        assert!(!app.is_searching());
+
+        let zmx = app.current().zmx.as_ref().unwrap();
+        assert_eq!(zmx.fiber_ids[zmx.fibers.state.selected().unwrap()], Some(4));
+
+        app.on_key('x');
+        let zmx = app.current().zmx.as_ref().unwrap();
+        assert_eq!(zmx.filter.query, "4");
+        assert!(zmx.filter.hide_done);
+        assert!(zmx.fibers.items.is_empty());
+    }
+
+    #[test]
+    fn db_pool_trims_metric_history() {
+        let mut pool = DbPool::new();
+        for n in 0..SlickTab::MAX_SLICK_MEASURES as i32 + 10 {
+            pool.append_slick_metrics(SlickMetrics { active_threads: n, queue_size: 0 });
+        }
+        for n in 0..SlickTab::MAX_HIKARI_MEASURES as i32 + 10 {
+            pool.append_hikari_metrics(HikariMetrics { total: n, active: 0, idle: 0, waiting: 0 });
+        }
+
+        // charts span from 0 to MAX inclusive, so one more measure than MAX is kept
+        assert_eq!(pool.slick_metrics.len(), SlickTab::MAX_SLICK_MEASURES + 1);
+        assert_eq!(pool.slick_metrics.front().map(|m| m.active_threads), Some(9));
+        assert_eq!(pool.slick_metrics.back().map(|m| m.active_threads), Some(SlickTab::MAX_SLICK_MEASURES as i32 + 9));
+        assert_eq!(pool.hikari_metrics.len(), SlickTab::MAX_HIKARI_MEASURES + 1);
+        assert_eq!(pool.hikari_metrics.front().map(|m| m.total), Some(9));
+    }
+
+    #[test]
+    fn slick_tab_keeps_selected_pool_when_pools_show_up() {
+        let slick = |pools: &[&str]| pools.iter()
+            .map(|p| (p.to_string(), SlickMetrics { active_threads: 1, queue_size: 0 }))
+            .collect();
+        let mut tab = SlickTab::new();
+        assert!(tab.current_pool().is_none());
+        tab.select_next_pool();
+
+        tab.append_slick_metrics(slick(&["orders-write"]));
+        tab.append_slick_metrics(slick(&["orders-read", "orders-write"]));
+        assert_eq!(tab.pools.current().kind, "orders-write");
+        assert_eq!(tab.current_pool().unwrap().slick_metrics.len(), 2);
+
+        tab.append_hikari_metrics(vec![("orders-read".to_owned(), HikariMetrics { total: 10, active: 2, idle: 8, waiting: 0 })].into_iter().collect());
+        assert!(!tab.current_pool().unwrap().has_hikari);
+        tab.select_next_pool();
+        assert_eq!(tab.pools.current().kind, "orders-read");
+        assert!(tab.current_pool().unwrap().has_hikari);
+    }
+
+    #[test]
+    fn target_state_keeps_history_when_source_fails() {
+        let mut target = TargetState::new(&zmx_target("orders"));
+        let count = FiberCount { done: 0, finishing: 0, running: 1, suspended: 0 };
+
+        target.on_fetcher_response(FetcherResponse::FiberCount(Ok(count)));
+        assert_eq!(target.health(Source::Zmx).unwrap().status(), HealthStatus::Connected);
+
+        target.on_fetcher_response(FetcherResponse::FiberCount(Err("connection refused".to_owned())));
+        target.on_fetcher_response(FetcherResponse::RetryScheduled(FetcherRequest::FiberCount, Duration::from_secs(4)));
+
+        let health = target.health(Source::Zmx).unwrap();
+        assert_eq!(health.status(), HealthStatus::Degraded);
+        assert_eq!(health.last_error, Some("connection refused".to_owned()));
+        assert!(health.retry_in().is_some());
+        assert_eq!(target.zmx.as_ref().unwrap().fiber_counts.len(), 1);
+    }
+
+    #[test]
+    fn mbeans_tab_browses_and_pins_attributes() {
+        let mut tab = MBeansTab::new();
+        tab.replace_mbean_names(vec![
+            "java.lang:type=Memory".to_owned(),
+            "java.lang:type=Threading".to_owned(),
+            "slick:type=AsyncExecutor,name=orders".to_owned(),
+        ]);
+
+        assert_eq!(tab.mbeans.items.len(), 5);
+        assert_eq!(tab.enter_request(), FetcherRequest::MBeanNames);
+        tab.mbeans.next();
+        assert_eq!(tab.enter_request(), FetcherRequest::MBean("java.lang:type=Memory".to_owned()));
+
+        let attribute = |name: &str, numeric: Option<f64>| MBeanAttributeValue {
+            name: name.to_owned(),
+            type_name: "long".to_owned(),
+            value: numeric.map_or("{}".to_owned(), |n| n.to_string()),
+            numeric,
+        };
+        tab.replace_mbean(MBean {
+            name: "java.lang:type=Memory".to_owned(),
+            class_name: "sun.management.MemoryImpl".to_owned(),
+            attributes: vec![attribute("HeapMemoryUsage", None), attribute("ObjectPendingFinalizationCount", Some(0.0))],
+        });
+
+        // composite attributes can't be charted
+        assert_eq!(tab.toggle_pin(), None);
+        tab.attributes.next();
+        let pinned = MBeanAttributeRef { mbean: "java.lang:type=Memory".to_owned(), attribute: "ObjectPendingFinalizationCount".to_owned() };
+        assert_eq!(tab.toggle_pin(), Some(vec![pinned.clone()]));
+
+        tab.append_pinned_values(vec![(pinned.clone(), Some(3.0))]);
+        tab.append_pinned_values(vec![(pinned.clone(), None)]);
+        assert_eq!(tab.pinned_values[&pinned], VecDeque::from(vec![3.0]));
+
+        assert_eq!(tab.toggle_pin(), Some(vec![]));
+        assert!(tab.pinned_values.is_empty());
+    }
+
+    #[test]
+    fn jvm_tab_is_shown_without_db_pool() {
+        let mut target = TargetState::new(&TargetSettings {
+            name: "orders".to_owned(),
+            zio_zmx: None,
+            jmx: Some(JMXConnectionSettings::for_test("localhost:9010")),
+            akka: None,
+        });
+        let metrics = |collections: i64, time: i64| JvmMetrics {
+            heap: MemoryUsage { used: 100, committed: 200, max: 400 },
+            non_heap: MemoryUsage { used: 50, committed: 60, max: -1 },
+            garbage_collectors: vec![GarbageCollectorMetrics { name: "G1 Young Generation".to_owned(), collection_count: collections, collection_time: time }],
+            live_threads: 20,
+            daemon_threads: 15,
+            peak_threads: 22,
+            loaded_classes: 9000,
+            unloaded_classes: 0,
+            process_cpu_load: Some(0.1),
+            system_cpu_load: None,
+        };
+
+        let kinds: Vec<AppTabKind> = target.tabs.tabs.iter().map(|t| t.kind.clone()).collect();
+        assert!(matches!(kinds.as_slice(), [AppTabKind::Jvm, AppTabKind::Threads, AppTabKind::MBeans]));
+        assert!(target.slick.is_none());
+
+        target.on_fetcher_response(FetcherResponse::JvmMetrics(Ok(metrics(10, 150))));
+        target.on_fetcher_response(FetcherResponse::JvmMetrics(Ok(metrics(13, 180))));
+
+        let jvm = target.jvm.as_ref().unwrap();
+        assert_eq!(jvm.jvm_metrics.len(), 2);
+        assert_eq!(jvm.recent_gc_activity(), vec![("G1 Young Generation".to_owned(), 3, 30)]);
+
+        // counters start over after a restart
+        target.on_fetcher_response(FetcherResponse::JvmMetrics(Ok(metrics(2, 20))));
+        assert_eq!(target.jvm.as_ref().unwrap().recent_gc_activity(), vec![("G1 Young Generation".to_owned(), 0, 0)]);
+    }
+
+    #[test]
+    fn threads_tab_groups_identical_stacks_by_state() {
+        let frame = |method: &str| StackFrame {
+            class_name: "Worker".to_owned(),
+            method_name: method.to_owned(),
+            file_name: Some("Worker.java".to_owned()),
+            line_number: 12,
+            native_method: false,
+        };
+        let thread = |id: i64, state: ThreadState, method: &str| ThreadInfo {
+            thread_id: id,
+            thread_name: format!("pool-1-thread-{}", id),
+            thread_state: state,
+            daemon: false,
+            lock_name: None,
+            lock_owner_name: None,
+            stack_trace: vec![frame(method)],
+            locked_monitors: vec![],
+        };
+        let mut tab = ThreadsTab::new();
+        tab.replace_thread_dump(ThreadDump {
+            threads: vec![
+                thread(1, ThreadState::Waiting, "take"),
+                thread(2, ThreadState::Blocked, "update"),
+                thread(3, ThreadState::Waiting, "take"),
+                thread(4, ThreadState::Blocked, "insert"),
+                thread(5, ThreadState::Runnable, "run"),
+            ],
+            deadlocked: vec![2, 4],
+        });
+
+        let labels: Vec<&str> = tab.threads.items.iter().map(|t| t.label.as_str()).collect();
+        assert_eq!(labels, vec![
+            "RUNNABLE (1)",
+            "└─pool-1-thread-5",
+            "BLOCKED (2)",
+            "├─pool-1-thread-2",
+            "└─pool-1-thread-4",
+            "WAITING (2)",
+            "└─pool-1-thread-1 (+1 with the same stack)",
+        ]);
+        let deadlocked: Vec<bool> = tab.threads.items.iter().map(|t| t.deadlocked).collect();
+        assert_eq!(deadlocked, vec![false, false, true, true, true, false, false]);
+        assert_eq!(tab.deadlocked_count(), 2);
+        assert!(tab.threads.items[3].dump.starts_with("\"pool-1-thread-2\" #2 BLOCKED (deadlocked)\n    at Worker.update(Worker.java:12)"));
+
+        tab.toggle_group_stacks();
+        assert_eq!(tab.threads.items.len(), 8);
+        assert_eq!(tab.threads.state.selected(), Some(0));
+    }
 }
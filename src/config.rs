@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::akka::model::AkkaSettings;
+use crate::alerts::AlertsConfig;
+use crate::jmx::model::{DbPools, JMXConnectionSettings};
+use crate::target::TargetSettings;
+
+pub const DEFAULT_ACTOR_TREE_TIMEOUT: u64 = 1000;
+pub const DEFAULT_DEAD_LETTERS_WINDOW: u64 = 5000;
+
+/// Contents of panopticon config file.
+///
+/// Example (TOML):
+///
+///   ```toml
+///   [profiles.staging-orders]
+///   jmx = "orders.staging:9010"
+///   db-pool-name = "orders"
+///   zio-zmx = "orders.staging:6789"
+///
+///   [[alerts.rules]]
+///   name = "slick queue filling up"
+///   metric = "slick-queue-size"
+///   above-percent-of-max = 80
+///   ```
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+}
+
+/// A named set of connection options. Every field mirrors a command line option of the same name.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    pub zio_zmx: Option<String>,
+    pub jmx: Option<String>,
+    pub jmx_username: Option<String>,
+    pub jmx_password: Option<String>,
+    pub jmx_ssl: Option<bool>,
+    /// Only set from the command line: the trust store is jvm-wide, so profiles can't have their own.
+    pub jmx_truststore: Option<String>,
+    pub jmx_truststore_password: Option<String>,
+    pub jolokia: Option<String>,
+    /// Comma separated names of database connection pools.
+    pub db_pool_name: Option<String>,
+    pub discover_db_pools: Option<bool>,
+    pub actor_tree: Option<String>,
+    pub actor_system_status: Option<String>,
+    pub actor_tree_timeout: Option<u64>,
+    pub dead_letters: Option<String>,
+    pub dead_letters_window: Option<u64>,
+}
+
+impl Config {
+    /// Loads config from a file, choosing the format by its extension (`.toml`, `.yaml` or `.yml`).
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read config file {}: {}", path.display(), e))?;
+
+        let config: Config = match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+            _ => Err("Unknown config format, expected .toml, .yaml or .yml file".to_owned()),
+        }.map_err(|e| format!("Couldn't parse config file {}: {}", path.display(), e))?;
+        config.validate().map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort();
+        for name in names {
+            let profile = &self.profiles[name];
+            if profile.jmx_truststore.is_some() || profile.jmx_truststore_password.is_some() {
+                return Err(format!(
+                    "profile {} sets a jmx trust store, but it's shared by all targets. Please pass it with --jmx-truststore instead",
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Default config location, e.g. `~/.config/panopticon-tui/config.toml` on Linux.
+    /// Returns the first existing file among supported formats.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().and_then(|dir| {
+            ["config.toml", "config.yaml", "config.yml"].iter()
+                .map(|f| dir.join("panopticon-tui").join(f))
+                .find(|p| p.exists())
+        })
+    }
+
+    pub fn profile(&self, name: &str) -> Result<Profile, String> {
+        self.profiles.get(name).cloned().ok_or_else(|| {
+            let mut known: Vec<&String> = self.profiles.keys().collect();
+            known.sort();
+            format!("Profile {} not found in config. Known profiles: {:?}", name, known)
+        })
+    }
+}
+
+impl Profile {
+    /// Returns a profile where values set in `overrides` take precedence over values of `self`.
+    pub fn merge(self, overrides: Profile) -> Profile {
+        Profile {
+            zio_zmx: overrides.zio_zmx.or(self.zio_zmx),
+            jmx: overrides.jmx.or(self.jmx),
+            jmx_username: overrides.jmx_username.or(self.jmx_username),
+            jmx_password: overrides.jmx_password.or(self.jmx_password),
+            jmx_ssl: overrides.jmx_ssl.or(self.jmx_ssl),
+            jmx_truststore: overrides.jmx_truststore.or(self.jmx_truststore),
+            jmx_truststore_password: overrides.jmx_truststore_password.or(self.jmx_truststore_password),
+            jolokia: overrides.jolokia.or(self.jolokia),
+            db_pool_name: overrides.db_pool_name.or(self.db_pool_name),
+            discover_db_pools: overrides.discover_db_pools.or(self.discover_db_pools),
+            actor_tree: overrides.actor_tree.or(self.actor_tree),
+            actor_system_status: overrides.actor_system_status.or(self.actor_system_status),
+            actor_tree_timeout: overrides.actor_tree_timeout.or(self.actor_tree_timeout),
+            dead_letters: overrides.dead_letters.or(self.dead_letters),
+            dead_letters_window: overrides.dead_letters_window.or(self.dead_letters_window),
+        }
+    }
+
+    /// Settings of either jmx or jolokia connection, jmx takes precedence if both are set.
+    pub fn jmx_settings(&self) -> Option<JMXConnectionSettings> {
+        let (address, jolokia) = match (&self.jmx, &self.jolokia) {
+            (Some(jmx), _) => (jmx, false),
+            (None, Some(jolokia)) => (jolokia, true),
+            (None, None) => return None,
+        };
+        Some(JMXConnectionSettings {
+            address: address.clone(),
+            jolokia,
+            username: self.jmx_username.clone(),
+            password: self.jmx_password.clone(),
+            ssl: self.jmx_ssl.unwrap_or(false),
+            truststore: self.jmx_truststore.clone(),
+            truststore_password: self.jmx_truststore_password.clone(),
+            db_pools: self.db_pools(),
+        })
+    }
+
+    /// Discovery takes precedence over pool names.
+    pub fn db_pools(&self) -> Option<DbPools> {
+        if self.discover_db_pools == Some(true) {
+            return Some(DbPools::Discovered);
+        }
+        self.db_pool_name.as_ref().map(|names| {
+            DbPools::Named(names.split(',').map(|n| n.trim().to_owned()).filter(|n| !n.is_empty()).collect())
+        })
+    }
+
+    /// Akka options that have to be set along with the ones given, since akka settings need all three endpoints.
+    pub fn missing_akka_options(&self) -> Vec<&'static str> {
+        let options = [
+            ("actor-tree", self.actor_tree.is_some()),
+            ("actor-system-status", self.actor_system_status.is_some()),
+            ("dead-letters", self.dead_letters.is_some()),
+        ];
+        if options.iter().all(|(_, set)| !set) {
+            return vec![];
+        }
+        options.iter().filter(|(_, set)| !set).map(|(name, _)| *name).collect()
+    }
+
+    pub fn akka_settings(&self, tick_rate: u64) -> Option<AkkaSettings> {
+        match (&self.actor_tree, &self.actor_system_status, &self.dead_letters) {
+            (Some(tree_addr), Some(status_addr), Some(dead_letters)) => Some(AkkaSettings {
+                tree_address: tree_addr.to_owned(),
+                tree_timeout: self.actor_tree_timeout.unwrap_or(DEFAULT_ACTOR_TREE_TIMEOUT),
+                status_address: status_addr.to_owned(),
+                status_timeout: (tick_rate as f64 * 0.8) as u64,
+                dead_letters_address: dead_letters.to_owned(),
+                dead_letters_window: self.dead_letters_window.unwrap_or(DEFAULT_DEAD_LETTERS_WINDOW),
+            }),
+            _ => None
+        }
+    }
+
+    pub fn target_settings(&self, name: String, tick_rate: u64) -> TargetSettings {
+        TargetSettings {
+            name,
+            zio_zmx: self.zio_zmx.clone(),
+            jmx: self.jmx_settings(),
+            akka: self.akka_settings(tick_rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::alerts::{AlertMetric, AlertRule};
+    use crate::config::{Config, Profile};
+    use crate::jmx::model::DbPools;
+
+    #[test]
+    fn config_loads_toml_and_yaml_profiles() {
+        let dir = std::env::temp_dir();
+        let toml_path = dir.join("panopticon-config-test.toml");
+        let yaml_path = dir.join("panopticon-config-test.yaml");
+        fs::write(&toml_path, "[profiles.staging-orders]\njmx = \"orders:9010\"\ndb-pool-name = \"orders\"\n").unwrap();
+        fs::write(&yaml_path, "profiles:\n  staging-orders:\n    jmx: orders:9010\n    db-pool-name: orders\n").unwrap();
+
+        let expected = Profile {
+            jmx: Some("orders:9010".to_owned()),
+            db_pool_name: Some("orders".to_owned()),
+            ..Profile::default()
+        };
+        assert_eq!(Config::load(&toml_path).unwrap().profile("staging-orders"), Ok(expected.clone()));
+        assert_eq!(Config::load(&yaml_path).unwrap().profile("staging-orders"), Ok(expected));
+        assert!(Config::load(&toml_path).unwrap().profile("prod-orders").is_err());
+
+        fs::remove_file(toml_path).unwrap();
+        fs::remove_file(yaml_path).unwrap();
+    }
+
+    #[test]
+    fn config_rejects_trust_stores_in_profiles() {
+        let path = std::env::temp_dir().join("panopticon-config-truststore-test.toml");
+        fs::write(&path, "[profiles.orders]\njmx = \"orders:9010\"\njmx-truststore = \"orders.p12\"\n").unwrap();
+
+        assert_eq!(
+            Config::load(&path).err(),
+            Some(format!(
+                "Invalid config file {}: profile orders sets a jmx trust store, but it's shared by all targets. Please pass it with --jmx-truststore instead",
+                path.display()
+            ))
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn profile_merge_prefers_overrides() {
+        let profile = Profile {
+            jmx: Some("orders:9010".to_owned()),
+            db_pool_name: Some("orders".to_owned()),
+            actor_tree_timeout: Some(3000),
+            ..Profile::default()
+        };
+        let overrides = Profile {
+            jmx: Some("localhost:9010".to_owned()),
+            ..Profile::default()
+        };
+
+        let merged = profile.merge(overrides);
+
+        assert_eq!(merged.jmx, Some("localhost:9010".to_owned()));
+        assert_eq!(merged.db_pool_name, Some("orders".to_owned()));
+        assert_eq!(merged.actor_tree_timeout, Some(3000));
+    }
+
+    #[test]
+    fn profile_lists_or_discovers_db_pools() {
+        let profile = Profile {
+            db_pool_name: Some("orders-read, orders-write,".to_owned()),
+            ..Profile::default()
+        };
+        assert_eq!(profile.db_pools(), Some(DbPools::Named(vec!["orders-read".to_owned(), "orders-write".to_owned()])));
+
+        let profile = Profile { discover_db_pools: Some(true), ..profile };
+        assert_eq!(profile.db_pools(), Some(DbPools::Discovered));
+        assert_eq!(Profile::default().db_pools(), None);
+    }
+
+    #[test]
+    fn config_loads_alert_rules() {
+        let config: Config = toml::from_str(
+            "[alerts]\nbell = true\n\n[[alerts.rules]]\nname = \"queue\"\nmetric = \"slick-queue-size\"\nabove-percent-of-max = 80\nfor = 10\n"
+        ).unwrap();
+
+        assert!(config.alerts.bell);
+        assert_eq!(config.alerts.command, None);
+        assert_eq!(config.alerts.rules, vec![AlertRule {
+            name: "queue".to_owned(),
+            metric: AlertMetric::SlickQueueSize,
+            above: None,
+            above_percent_of_max: Some(80.0),
+            growing: false,
+            for_secs: 10,
+        }]);
+        assert!(toml::from_str::<Config>("[[alerts.rules]]\nname = \"x\"\nmetric = \"heap\"\nabove = 1\n").is_err());
+    }
+}
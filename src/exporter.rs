@@ -0,0 +1,328 @@
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use chrono::Local;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+
+use crate::app::App;
+use crate::fetcher::FetcherResponse;
+use crate::health::{HealthStatus, Source};
+use crate::target::TargetSettings;
+
+/// Prometheus metric type, written to the `# TYPE` line.
+#[derive(Clone, Copy)]
+enum MetricType {
+    Gauge,
+    /// Cumulative value that only goes up, until the process it comes from restarts.
+    Counter,
+}
+
+impl MetricType {
+    fn name(self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        }
+    }
+}
+
+/// A single metric with its help text and samples of all targets.
+struct MetricFamily {
+    name: &'static str,
+    help: &'static str,
+    metric_type: MetricType,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl MetricFamily {
+    fn new(name: &'static str, help: &'static str) -> MetricFamily {
+        MetricFamily { name, help, metric_type: MetricType::Gauge, samples: vec![] }
+    }
+
+    /// Counter names should end with `_total`.
+    fn counter(name: &'static str, help: &'static str) -> MetricFamily {
+        MetricFamily { metric_type: MetricType::Counter, ..MetricFamily::new(name, help) }
+    }
+
+    fn add(&mut self, labels: Vec<(&'static str, String)>, value: f64) {
+        self.samples.push((labels, value));
+    }
+
+    fn render(&self, out: &mut String) {
+        if self.samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.metric_type.name());
+        for (labels, value) in &self.samples {
+            let labels: Vec<String> = labels.iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+                .collect();
+            let _ = writeln!(out, "{}{{{}}} {}", self.name, labels.join(","), value);
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+///
+/// Keeps the latest fetched metrics of every target and renders them in Prometheus text format.
+///
+/// Responses are collected by the same `App` state the UI uses, so the exported values
+/// are exactly the ones you would see on screen.
+///
+pub struct Exporter {
+    app: Mutex<App<'static>>,
+}
+
+impl Exporter {
+    pub fn new(targets: &[TargetSettings]) -> Exporter {
+        Exporter { app: Mutex::new(App::new("PANOPTICON-TUI", targets)) }
+    }
+
+    pub fn on_fetcher_response(&self, target: &str, response: FetcherResponse) {
+        self.app.lock().unwrap().on_fetcher_response(target, response, Local::now())
+    }
+
+    pub fn render(&self) -> String {
+        let mut up = MetricFamily::new("panopticon_up", "Whether the data source is reachable (1) or not (0).");
+        let mut fibers = MetricFamily::new("panopticon_zio_fibers", "Number of ZIO fibers by status.");
+        let mut slick_active = MetricFamily::new("panopticon_slick_active_threads", "Number of active Slick threads.");
+        let mut slick_queue = MetricFamily::new("panopticon_slick_queue_size", "Number of queued Slick actions.");
+        let mut slick_max_threads = MetricFamily::new("panopticon_slick_max_threads", "Maximum number of Slick threads.");
+        let mut slick_max_queue = MetricFamily::new("panopticon_slick_max_queue_size", "Maximum size of Slick queue.");
+        let mut hikari = MetricFamily::new("panopticon_hikari_connections", "Number of HikariCP connections by state.");
+        let mut hikari_max = MetricFamily::new("panopticon_hikari_max_connections", "Maximum size of HikariCP pool.");
+        let mut hikari_timings = MetricFamily::new(
+            "panopticon_hikari_connection_time_seconds",
+            "Quantiles of HikariCP connection wait, usage and creation times.",
+        );
+        let mut jvm_memory = MetricFamily::new("panopticon_jvm_memory_used_bytes", "Used jvm memory by area.");
+        let mut jvm_threads = MetricFamily::new("panopticon_jvm_threads", "Number of jvm threads by kind.");
+        let mut jvm_classes = MetricFamily::new("panopticon_jvm_classes_loaded", "Number of currently loaded classes.");
+        let mut jvm_cpu = MetricFamily::new("panopticon_jvm_process_cpu_load", "Recent cpu usage of the jvm process, between 0 and 1.");
+        let mut gc_count = MetricFamily::counter("panopticon_jvm_gc_collections_total", "Number of collections since jvm start, by collector.");
+        let mut gc_time = MetricFamily::counter("panopticon_jvm_gc_time_seconds_total", "Time spent in collections since jvm start, by collector.");
+        let mut actors = MetricFamily::new("panopticon_akka_actors", "Number of actors in the actor system.");
+        let mut uptime = MetricFamily::new("panopticon_akka_uptime_seconds", "Uptime of the actor system.");
+        let mut dead_letters = MetricFamily::new(
+            "panopticon_akka_dead_letters",
+            "Number of dead letters by kind, within the configured dead letters window.",
+        );
+
+        let app = self.app.lock().unwrap();
+        for target_tab in app.targets.tabs.iter() {
+            let name = &target_tab.kind;
+            let target = &app.target_states[name];
+            let labels = |extra: &[(&'static str, &str)]| {
+                let mut labels = vec![("target", name.clone())];
+                labels.extend(extra.iter().map(|(k, v)| (*k, (*v).to_owned())));
+                labels
+            };
+
+            let mut sources: Vec<(&Source, HealthStatus)> = target.health.iter().map(|(s, h)| (s, h.status())).collect();
+            sources.sort_by_key(|(s, _)| format!("{:?}", s));
+            for (source, status) in sources {
+                let value = if status == HealthStatus::Connected { 1.0 } else { 0.0 };
+                up.add(labels(&[("source", &format!("{:?}", source).to_lowercase())]), value);
+            }
+
+            if let Some(c) = target.zmx.as_ref().and_then(|z| z.fiber_counts.back()) {
+                fibers.add(labels(&[("status", "done")]), c.done as f64);
+                fibers.add(labels(&[("status", "finishing")]), c.finishing as f64);
+                fibers.add(labels(&[("status", "running")]), c.running as f64);
+                fibers.add(labels(&[("status", "suspended")]), c.suspended as f64);
+            }
+
+            for (pool, db) in target.slick.iter().flat_map(|s| s.pool_metrics.iter()) {
+                if let Some(m) = db.slick_metrics.back() {
+                    slick_active.add(labels(&[("pool", pool)]), m.active_threads as f64);
+                    slick_queue.add(labels(&[("pool", pool)]), m.queue_size as f64);
+                    slick_max_threads.add(labels(&[("pool", pool)]), db.slick_config.max_threads as f64);
+                    slick_max_queue.add(labels(&[("pool", pool)]), db.slick_config.max_queue_size as f64);
+                }
+                if let Some(m) = db.hikari_metrics.back().filter(|_| db.has_hikari) {
+                    hikari.add(labels(&[("pool", pool), ("state", "total")]), m.total as f64);
+                    hikari.add(labels(&[("pool", pool), ("state", "active")]), m.active as f64);
+                    hikari.add(labels(&[("pool", pool), ("state", "idle")]), m.idle as f64);
+                    hikari.add(labels(&[("pool", pool), ("state", "waiting")]), m.waiting as f64);
+                }
+                if let Some(c) = db.hikari_config.as_ref().filter(|_| db.has_hikari) {
+                    hikari_max.add(labels(&[("pool", pool)]), c.maximum_pool_size as f64);
+                }
+                if let Some(t) = db.hikari_timings.as_ref() {
+                    let timings = [("wait", &t.wait), ("usage", &t.usage), ("creation", &t.creation)];
+                    for (timing, p) in timings.iter().filter_map(|(timing, p)| p.as_ref().map(|p| (timing, p))) {
+                        for (quantile, v) in [("0.5", p.p50), ("0.75", p.p75), ("0.95", p.p95), ("0.99", p.p99)].iter() {
+                            hikari_timings.add(labels(&[("pool", pool), ("timing", timing), ("quantile", quantile)]), v / 1000.0);
+                        }
+                    }
+                }
+            }
+
+            if let Some(m) = target.jvm.as_ref().and_then(|j| j.jvm_metrics.back()) {
+                jvm_memory.add(labels(&[("area", "heap")]), m.heap.used as f64);
+                jvm_memory.add(labels(&[("area", "nonheap")]), m.non_heap.used as f64);
+                jvm_threads.add(labels(&[("kind", "live")]), m.live_threads as f64);
+                jvm_threads.add(labels(&[("kind", "daemon")]), m.daemon_threads as f64);
+                jvm_classes.add(labels(&[]), m.loaded_classes as f64);
+                if let Some(cpu) = m.process_cpu_load {
+                    jvm_cpu.add(labels(&[]), cpu);
+                }
+                for gc in &m.garbage_collectors {
+                    gc_count.add(labels(&[("collector", &gc.name)]), gc.collection_count as f64);
+                    gc_time.add(labels(&[("collector", &gc.name)]), gc.collection_time as f64 / 1000.0);
+                }
+            }
+
+            if let Some(akka) = target.akka.as_ref() {
+                if let Some(count) = akka.actor_counts.back() {
+                    actors.add(labels(&[]), *count as f64);
+                    uptime.add(labels(&[]), akka.system_status.uptime as f64);
+                }
+                if let Some(w) = akka.dead_letters_windows.back() {
+                    dead_letters.add(labels(&[("kind", "dead_letters")]), w.dead_letters.count as f64);
+                    dead_letters.add(labels(&[("kind", "unhandled")]), w.unhandled.count as f64);
+                    dead_letters.add(labels(&[("kind", "dropped")]), w.dropped.count as f64);
+                }
+            }
+        }
+
+        let mut out = String::new();
+        for family in [
+            up, fibers, slick_active, slick_queue, slick_max_threads, slick_max_queue, hikari, hikari_max, hikari_timings,
+            jvm_memory, jvm_threads, jvm_classes, jvm_cpu, gc_count, gc_time, actors, uptime, dead_letters,
+        ].iter() {
+            family.render(&mut out);
+        }
+        out
+    }
+
+    fn respond(&self, request: Request<Body>) -> Response<Body> {
+        match (request.method(), request.uri().path()) {
+            (&Method::GET, "/metrics") => Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Body::from(self.render()))
+                .unwrap(),
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Not found, metrics are served at /metrics"))
+                .unwrap(),
+        }
+    }
+}
+
+/// Serves exporter metrics at `http://<addr>/metrics` until the server fails.
+pub async fn serve(addr: SocketAddr, exporter: Arc<Exporter>) -> Result<(), String> {
+    let make_service = make_service_fn(move |_| {
+        let exporter = exporter.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let exporter = exporter.clone();
+                async move { Ok::<_, Infallible>(exporter.respond(request)) }
+            }))
+        }
+    });
+
+    Server::try_bind(&addr)
+        .map_err(|e| format!("Couldn't serve metrics at {}: {}", addr, e))?
+        .serve(make_service)
+        .await
+        .map_err(|e| format!("Metrics server failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Body, Request, StatusCode};
+
+    use crate::exporter::Exporter;
+    use crate::fetcher::FetcherResponse;
+    use crate::jmx::model::{DbPools, GarbageCollectorMetrics, HikariTimings, JMXConnectionSettings, JvmMetrics, MemoryUsage, Percentiles, SlickMetrics};
+    use crate::target::TargetSettings;
+    use crate::zio::model::FiberCount;
+
+    #[test]
+    fn exporter_renders_latest_metrics_of_each_target() {
+        let exporter = Exporter::new(&[
+            TargetSettings {
+                name: "orders".to_owned(),
+                zio_zmx: Some("localhost:6789".to_owned()),
+                jmx: None,
+                akka: None,
+            },
+            TargetSettings {
+                name: "payments".to_owned(),
+                zio_zmx: None,
+                jmx: Some(JMXConnectionSettings {
+                    db_pools: Some(DbPools::Named(vec!["payments-read".to_owned(), "payments-write".to_owned()])),
+                    ..JMXConnectionSettings::for_test("localhost:9010")
+                }),
+                akka: None,
+            },
+        ]);
+        exporter.on_fetcher_response("orders", FetcherResponse::FiberCount(Ok(FiberCount { done: 1, finishing: 0, running: 2, suspended: 3 })));
+        exporter.on_fetcher_response("orders", FetcherResponse::FiberCount(Ok(FiberCount { done: 1, finishing: 0, running: 5, suspended: 3 })));
+        exporter.on_fetcher_response("payments", FetcherResponse::SlickMetrics(Err("connection refused".to_owned())));
+
+        let metrics = exporter.render();
+
+        assert!(metrics.contains("# TYPE panopticon_zio_fibers gauge\n"));
+        assert!(metrics.contains("panopticon_zio_fibers{target=\"orders\",status=\"running\"} 5\n"));
+        assert!(metrics.contains("panopticon_up{target=\"orders\",source=\"zmx\"} 1\n"));
+        assert!(metrics.contains("panopticon_up{target=\"payments\",source=\"jmx\"} 0\n"));
+        assert!(!metrics.contains("panopticon_slick_active_threads"));
+
+        let slick = vec![
+            ("payments-read".to_owned(), SlickMetrics { active_threads: 4, queue_size: 0 }),
+            ("payments-write".to_owned(), SlickMetrics { active_threads: 1, queue_size: 0 }),
+        ];
+        exporter.on_fetcher_response("payments", FetcherResponse::SlickMetrics(Ok(slick.into_iter().collect())));
+        let metrics = exporter.render();
+        assert!(metrics.contains("panopticon_slick_active_threads{target=\"payments\",pool=\"payments-read\"} 4\n"));
+        assert!(metrics.contains("panopticon_slick_active_threads{target=\"payments\",pool=\"payments-write\"} 1\n"));
+
+        let wait = Percentiles { p50: 2.0, p75: 3.0, p95: 8.0, p99: 20.0, max: 31.0, mean: 2.5 };
+        let timings = HikariTimings { wait: Some(wait), usage: None, creation: None };
+        exporter.on_fetcher_response("payments", FetcherResponse::HikariTimings(Ok(vec![("payments-read".to_owned(), timings)].into_iter().collect())));
+        let metrics = exporter.render();
+        assert!(metrics.contains(
+            "panopticon_hikari_connection_time_seconds{target=\"payments\",pool=\"payments-read\",timing=\"wait\",quantile=\"0.99\"} 0.02\n"
+        ));
+        assert!(!metrics.contains("timing=\"usage\""));
+
+        let memory = MemoryUsage { used: 0, committed: 0, max: -1 };
+        let jvm = JvmMetrics {
+            heap: memory.clone(),
+            non_heap: memory,
+            garbage_collectors: vec![GarbageCollectorMetrics { name: "G1 Young Generation".to_owned(), collection_count: 12, collection_time: 340 }],
+            live_threads: 20,
+            daemon_threads: 15,
+            peak_threads: 22,
+            loaded_classes: 8000,
+            unloaded_classes: 0,
+            process_cpu_load: None,
+            system_cpu_load: None,
+        };
+        exporter.on_fetcher_response("payments", FetcherResponse::JvmMetrics(Ok(jvm)));
+        let metrics = exporter.render();
+        assert!(metrics.contains("# TYPE panopticon_jvm_gc_collections_total counter\n"));
+        assert!(metrics.contains("panopticon_jvm_gc_collections_total{target=\"payments\",collector=\"G1 Young Generation\"} 12\n"));
+        assert!(metrics.contains("# TYPE panopticon_jvm_gc_time_seconds_total counter\n"));
+        assert!(metrics.contains("panopticon_jvm_gc_time_seconds_total{target=\"payments\",collector=\"G1 Young Generation\"} 0.34\n"));
+    }
+
+    #[test]
+    fn exporter_serves_only_metrics_path() {
+        let exporter = Exporter::new(&[]);
+        let request = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+
+        assert_eq!(exporter.respond(request("/metrics")).status(), StatusCode::OK);
+        assert_eq!(exporter.respond(request("/")).status(), StatusCode::NOT_FOUND);
+    }
+}
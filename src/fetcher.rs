@@ -1,116 +1,832 @@
-use jmx::MBeanClient;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::{sleep_until, Instant};
 
 use crate::akka;
 use crate::akka::model::{ActorTreeNode, AkkaSettings, DeadLettersSnapshot, DeadLettersWindow, ActorSystemStatus};
-use crate::jmx::client::JMXClient;
-use crate::jmx::model::{HikariMetrics, JMXConnectionSettings, SlickConfig, SlickMetrics};
-use crate::zio::model::Fiber;
+use crate::health::{Backoff, Source};
+use crate::jmx::source::{jmx_source, JmxSource};
+use crate::jmx::model::{DbPoolKind, DbPools, HikariConfig, HikariMetrics, HikariTiming, HikariTimings, JvmMetrics, MBean, MBeanAttributeRef, SlickConfig, SlickMetrics, ThreadDump};
+use crate::target::TargetSettings;
+use crate::zio::model::{Fiber, FiberCount};
 use crate::zio::zmx::{NetworkZMXClient, ZMXClient};
 
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum FetcherRequest {
     FiberDump,
-    RegularFiberDump,
+    FiberCount,
     HikariMetrics,
+    HikariConfig,
+    HikariTimings,
     SlickMetrics,
     SlickConfig,
+    JvmMetrics,
+    ThreadDump,
     ActorTree,
     ActorSystemStatus,
     DeadLetters,
+    MBeanNames,
+    MBean(String),
+    PinnedAttributes,
+    /// Replaces the set of polled MBean attributes and fetches their values right away.
+    SetPinnedAttributes(Vec<MBeanAttributeRef>),
 }
 
+impl FetcherRequest {
+    pub fn source(&self) -> Source {
+        match self {
+            FetcherRequest::FiberDump | FetcherRequest::FiberCount => Source::Zmx,
+            FetcherRequest::HikariMetrics | FetcherRequest::HikariConfig | FetcherRequest::HikariTimings |
+            FetcherRequest::SlickMetrics | FetcherRequest::SlickConfig |
+            FetcherRequest::JvmMetrics | FetcherRequest::ThreadDump |
+            FetcherRequest::MBeanNames | FetcherRequest::MBean(_) |
+            FetcherRequest::PinnedAttributes | FetcherRequest::SetPinnedAttributes(_) => Source::Jmx,
+            FetcherRequest::ActorTree | FetcherRequest::ActorSystemStatus | FetcherRequest::DeadLetters => Source::Akka,
+        }
+    }
+
+    /// Whether the request reads HikariCP MBeans, which are optional.
+    pub fn is_hikari(&self) -> bool {
+        matches!(self, FetcherRequest::HikariMetrics | FetcherRequest::HikariConfig | FetcherRequest::HikariTimings)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum FetcherResponse {
     FiberDump(Result<Vec<Fiber>, String>),
-    RegularFiberDump(Result<Vec<Fiber>, String>),
-    HikariMetrics(Result<HikariMetrics, String>),
-    SlickMetrics(Result<SlickMetrics, String>),
-    SlickConfig(Result<SlickConfig, String>),
+    FiberCount(Result<FiberCount, String>),
+    /// Metrics by pool name. Pools that couldn't be read are left out.
+    HikariMetrics(Result<BTreeMap<String, HikariMetrics>, String>),
+    HikariConfig(Result<BTreeMap<String, HikariConfig>, String>),
+    /// Timings of pools that publish them.
+    HikariTimings(Result<BTreeMap<String, HikariTimings>, String>),
+    SlickMetrics(Result<BTreeMap<String, SlickMetrics>, String>),
+    SlickConfig(Result<BTreeMap<String, SlickConfig>, String>),
+    JvmMetrics(Result<JvmMetrics, String>),
+    ThreadDump(Result<ThreadDump, String>),
     ActorTree(Result<Vec<ActorTreeNode>, String>),
     ActorSystemStatus(Result<ActorSystemStatus, String>),
     DeadLetters(Result<(DeadLettersSnapshot, DeadLettersWindow), String>),
-    FatalFailure(String),
+    MBeanNames(Result<Vec<String>, String>),
+    MBean(String, Result<MBean, String>),
+    /// Every pinned attribute, with its value if it could be read as a number.
+    PinnedAttributes(Result<Vec<(MBeanAttributeRef, Option<f64>)>, String>),
+    /// A polled request failed and will be retried after the given delay.
+    RetryScheduled(FetcherRequest, Duration),
 }
 
-pub struct Fetcher {
+impl FetcherResponse {
+    pub fn is_err(&self) -> bool {
+        self.error().is_some()
+    }
+
+    pub fn error(&self) -> Option<&String> {
+        match self {
+            FetcherResponse::FiberDump(r) => r.as_ref().err(),
+            FetcherResponse::FiberCount(r) => r.as_ref().err(),
+            FetcherResponse::HikariMetrics(r) => r.as_ref().err(),
+            FetcherResponse::HikariConfig(r) => r.as_ref().err(),
+            FetcherResponse::HikariTimings(r) => r.as_ref().err(),
+            FetcherResponse::SlickMetrics(r) => r.as_ref().err(),
+            FetcherResponse::SlickConfig(r) => r.as_ref().err(),
+            FetcherResponse::JvmMetrics(r) => r.as_ref().err(),
+            FetcherResponse::ThreadDump(r) => r.as_ref().err(),
+            FetcherResponse::ActorTree(r) => r.as_ref().err(),
+            FetcherResponse::ActorSystemStatus(r) => r.as_ref().err(),
+            FetcherResponse::DeadLetters(r) => r.as_ref().err(),
+            FetcherResponse::MBeanNames(r) => r.as_ref().err(),
+            FetcherResponse::MBean(_, r) => r.as_ref().err(),
+            FetcherResponse::PinnedAttributes(r) => r.as_ref().err(),
+            FetcherResponse::RetryScheduled(_, _) => None,
+        }
+    }
+
+    pub fn request(&self) -> FetcherRequest {
+        match self {
+            FetcherResponse::FiberDump(_) => FetcherRequest::FiberDump,
+            FetcherResponse::FiberCount(_) => FetcherRequest::FiberCount,
+            FetcherResponse::HikariMetrics(_) => FetcherRequest::HikariMetrics,
+            FetcherResponse::HikariConfig(_) => FetcherRequest::HikariConfig,
+            FetcherResponse::HikariTimings(_) => FetcherRequest::HikariTimings,
+            FetcherResponse::SlickMetrics(_) => FetcherRequest::SlickMetrics,
+            FetcherResponse::SlickConfig(_) => FetcherRequest::SlickConfig,
+            FetcherResponse::JvmMetrics(_) => FetcherRequest::JvmMetrics,
+            FetcherResponse::ThreadDump(_) => FetcherRequest::ThreadDump,
+            FetcherResponse::ActorTree(_) => FetcherRequest::ActorTree,
+            FetcherResponse::ActorSystemStatus(_) => FetcherRequest::ActorSystemStatus,
+            FetcherResponse::DeadLetters(_) => FetcherRequest::DeadLetters,
+            FetcherResponse::MBeanNames(_) => FetcherRequest::MBeanNames,
+            FetcherResponse::MBean(name, _) => FetcherRequest::MBean(name.clone()),
+            FetcherResponse::PinnedAttributes(_) => FetcherRequest::PinnedAttributes,
+            FetcherResponse::RetryScheduled(r, _) => r.clone(),
+        }
+    }
+}
+
+/// Callback receiving fetcher responses, tagged with the target name.
+pub type Responder = Arc<dyn Fn(String, FetcherResponse) + Send + Sync>;
+
+/// Connections to all data sources of a single target.
+pub struct ClientSet {
     pub zmx_client: Option<Box<dyn ZMXClient>>,
-    pub jmx: Option<JMXClient>,
+    pub jmx: Option<Box<dyn JmxSource>>,
+    pub db_pools: Option<DbPools>,
     pub akka_settings: Option<AkkaSettings>,
+    /// MBean attributes polled for charts in the MBeans tab.
+    pub pinned_attributes: Mutex<Vec<MBeanAttributeRef>>,
 }
 
-impl Fetcher {
-    pub fn new(
-        zio_zmx_addr: Option<String>,
-        jmx: Option<JMXConnectionSettings>,
-        akka: Option<AkkaSettings>) -> Result<Fetcher, String> {
-        let jmx_client: Option<JMXClient> = match jmx {
-            None => Ok(None),
-            Some(conn) => {
-                let url_str = format!(
-                    "service:jmx:rmi://{}/jndi/rmi://{}/jmxrmi",
-                    &conn.address, &conn.address
-                );
-                let url = jmx::MBeanAddress::service_url(url_str.clone());
-                MBeanClient::connect(url)
-                    .map(|x| Some(JMXClient::new(x, conn.db_pool_name.clone())))
-                    .map_err(|e| format!(
-                        "Couldn't connect to jmx at {}. Error: {}", url_str, e
-                    ))
-            }
-        }?;
-
-        Ok(Fetcher {
-            zmx_client: zio_zmx_addr.map(|x| {
-                let a: Box<dyn ZMXClient> = Box::new(NetworkZMXClient::new(x));
+impl ClientSet {
+    /// Creates clients for a target. Connections are established lazily, on the first request.
+    pub fn new(target: &TargetSettings) -> ClientSet {
+        ClientSet {
+            zmx_client: target.zio_zmx.clone().map(|x| {
+                let a: Box<dyn ZMXClient> = Box::new(NetworkZMXClient::new(x, NetworkZMXClient::TIMEOUT));
                 a
             }),
-            jmx: jmx_client,
-            akka_settings: akka,
-        })
+            jmx: target.jmx.as_ref().map(jmx_source),
+            db_pools: target.jmx.as_ref().and_then(|x| x.db_pools.clone()),
+            akka_settings: target.akka.clone(),
+            pinned_attributes: Mutex::new(vec![]),
+        }
+    }
+
+    /// Requests that are fetched periodically for this set of clients.
+    pub fn polled_requests(&self) -> Vec<FetcherRequest> {
+        let mut requests = vec![];
+        if self.zmx_client.is_some() {
+            requests.push(FetcherRequest::FiberCount);
+        }
+        if self.has_db_pool() {
+            requests.push(FetcherRequest::SlickMetrics);
+            requests.push(FetcherRequest::HikariMetrics);
+            requests.push(FetcherRequest::HikariTimings);
+        }
+        if self.jmx.is_some() {
+            requests.push(FetcherRequest::JvmMetrics);
+            requests.push(FetcherRequest::PinnedAttributes);
+        }
+        if self.akka_settings.is_some() {
+            requests.push(FetcherRequest::ActorSystemStatus);
+            requests.push(FetcherRequest::DeadLetters);
+        }
+        requests
+    }
+
+    /// Requests that are fetched once after connecting.
+    pub fn initial_requests(&self) -> Vec<FetcherRequest> {
+        let mut requests = vec![];
+        if self.has_db_pool() {
+            requests.push(FetcherRequest::SlickConfig);
+            requests.push(FetcherRequest::HikariConfig);
+        }
+        if self.jmx.is_some() {
+            requests.push(FetcherRequest::MBeanNames);
+        }
+        requests
+    }
+
+    fn has_db_pool(&self) -> bool {
+        self.db_pools.is_some()
+    }
+}
+
+pub struct Fetcher {
+    pub clients: HashMap<String, ClientSet>,
+}
+
+impl Fetcher {
+    pub fn new(targets: Vec<TargetSettings>) -> Fetcher {
+        let clients = targets.iter()
+            .map(|t| (t.name.clone(), ClientSet::new(t)))
+            .collect();
+        Fetcher { clients }
+    }
+
+    pub async fn fetch(&self, target: &str, request: FetcherRequest) -> FetcherResponse {
+        match request {
+            FetcherRequest::FiberDump =>
+                FetcherResponse::FiberDump(self.dump_fibers(target).await),
+            FetcherRequest::FiberCount =>
+                FetcherResponse::FiberCount(self.count_fibers(target).await),
+            FetcherRequest::HikariMetrics =>
+                FetcherResponse::HikariMetrics(self.get_hikari_metrics(target).await),
+            FetcherRequest::HikariConfig =>
+                FetcherResponse::HikariConfig(self.get_hikari_config(target).await),
+            FetcherRequest::HikariTimings =>
+                FetcherResponse::HikariTimings(self.get_hikari_timings(target).await),
+            FetcherRequest::SlickMetrics =>
+                FetcherResponse::SlickMetrics(self.get_slick_metrics(target).await),
+            FetcherRequest::SlickConfig =>
+                FetcherResponse::SlickConfig(self.get_slick_config(target).await),
+            FetcherRequest::JvmMetrics =>
+                FetcherResponse::JvmMetrics(self.get_jvm_metrics(target).await),
+            FetcherRequest::ThreadDump =>
+                FetcherResponse::ThreadDump(self.get_thread_dump(target).await),
+            FetcherRequest::ActorTree =>
+                FetcherResponse::ActorTree(self.get_actor_tree(target).await),
+            FetcherRequest::ActorSystemStatus =>
+                FetcherResponse::ActorSystemStatus(self.get_actor_system_status(target).await),
+            FetcherRequest::DeadLetters =>
+                FetcherResponse::DeadLetters(self.get_dead_letters(target).await),
+            FetcherRequest::MBeanNames =>
+                FetcherResponse::MBeanNames(self.get_mbean_names(target).await),
+            FetcherRequest::MBean(name) => {
+                let mbean = self.get_mbean(target, name.clone()).await;
+                FetcherResponse::MBean(name, mbean)
+            }
+            FetcherRequest::PinnedAttributes =>
+                FetcherResponse::PinnedAttributes(self.get_pinned_attributes(target).await),
+            FetcherRequest::SetPinnedAttributes(attributes) => {
+                *self.clients(target).pinned_attributes.lock().unwrap() = attributes;
+                FetcherResponse::PinnedAttributes(self.get_pinned_attributes(target).await)
+            }
+        }
     }
 
-    pub fn dump_fibers(&self) -> Result<Vec<Fiber>, String> {
-        self.zmx_client.as_ref().unwrap().dump_fibers()
+    pub async fn dump_fibers(&self, target: &str) -> Result<Vec<Fiber>, String> {
+        let zmx = self.clients(target).zmx_client.as_ref().unwrap();
+        zmx.dump_fibers().await
             .map_err(
                 |e| format!(
                     "Couldn't get fiber dump from {}. Make sure zio-zmx is listening on specified port. Underlying error: {}",
-                    self.zmx_client.as_ref().unwrap().address(),
+                    zmx.address(),
                     e
                 )
             )
     }
 
-    pub fn get_hikari_metrics(&self) -> Result<HikariMetrics, String> {
-        self.jmx.as_ref().unwrap().get_hikari_metrics().map_err(|e| Fetcher::format_slick_error(e))
+    pub async fn count_fibers(&self, target: &str) -> Result<FiberCount, String> {
+        let zmx = self.clients(target).zmx_client.as_ref().unwrap();
+        zmx.fiber_count().await
+            .map_err(
+                |e| format!(
+                    "Couldn't get fiber count from {}. Make sure zio-zmx is listening on specified port. Underlying error: {}",
+                    zmx.address(),
+                    e
+                )
+            )
+    }
+
+    pub async fn get_hikari_metrics(&self, target: &str) -> Result<BTreeMap<String, HikariMetrics>, String> {
+        let db_pools = self.db_pools(target)?;
+        let jmx = self.jmx(target);
+        async {
+            let pools = Fetcher::hikari_pool_names(jmx, db_pools).await?;
+            if pools.is_empty() {
+                return Err(format!("No MBeans found matching {}", DbPoolKind::Hikari.pattern()));
+            }
+            let results = join_all(pools.iter().map(|p| jmx.get_hikari_metrics(p))).await;
+            Fetcher::by_pool(db_pools, pools, results)
+        }.await.map_err(Fetcher::format_slick_error)
+    }
+
+    pub async fn get_hikari_config(&self, target: &str) -> Result<BTreeMap<String, HikariConfig>, String> {
+        let db_pools = self.db_pools(target)?;
+        let jmx = self.jmx(target);
+        async {
+            let pools = Fetcher::hikari_pool_names(jmx, db_pools).await?;
+            if pools.is_empty() {
+                return Err(format!("No MBeans found matching {}", DbPoolKind::Hikari.pattern()));
+            }
+            let results = join_all(pools.iter().map(|p| jmx.get_hikari_config(p))).await;
+            Fetcher::by_pool(db_pools, pools, results)
+        }.await.map_err(|e| format!("Error loading HikariCP config: {}", e))
+    }
+
+    /// Timings of pools that publish them. Pools without any timing MBeans are left out.
+    pub async fn get_hikari_timings(&self, target: &str) -> Result<BTreeMap<String, HikariTimings>, String> {
+        let db_pools = self.db_pools(target)?;
+        let jmx = self.jmx(target);
+        async {
+            let names = jmx.query_names(&format!("{}:*", HikariTiming::METRICS_DOMAIN)).await?;
+            let pools: Vec<String> = Fetcher::hikari_pool_names(jmx, db_pools).await?.into_iter()
+                .filter(|p| Fetcher::has_hikari_timings(p, &names))
+                .collect();
+            if pools.is_empty() {
+                return Err(format!("No timing MBeans found for HikariCP pools in the {} domain", HikariTiming::METRICS_DOMAIN));
+            }
+            let results = join_all(pools.iter().map(|p| Fetcher::hikari_timings(jmx, p, &names))).await;
+            Fetcher::by_pool(db_pools, pools, results)
+        }.await.map_err(|e| format!("Error loading HikariCP timings: {}", e))
+    }
+
+    /// Whether the optional data of a request is published at all, e.g. whether any pool is a HikariCP one.
+    /// Fails if the source itself can't be read, in which case the data may turn up later.
+    pub async fn is_available(&self, target: &str, request: &FetcherRequest) -> Result<bool, String> {
+        let db_pools = match self.clients(target).db_pools.as_ref() {
+            Some(db_pools) if request.is_hikari() => db_pools,
+            _ => return Ok(true),
+        };
+        let jmx = self.jmx(target);
+        let pools = Fetcher::hikari_pool_names(jmx, db_pools).await?;
+        if *request != FetcherRequest::HikariTimings {
+            return Ok(!pools.is_empty());
+        }
+        let names = jmx.query_names(&format!("{}:*", HikariTiming::METRICS_DOMAIN)).await?;
+        Ok(pools.iter().any(|p| Fetcher::has_hikari_timings(p, &names)))
+    }
+
+    pub async fn get_slick_metrics(&self, target: &str) -> Result<BTreeMap<String, SlickMetrics>, String> {
+        let db_pools = self.db_pools(target)?;
+        let jmx = self.jmx(target);
+        async {
+            let pools = Fetcher::db_pool_names(jmx, db_pools, DbPoolKind::Slick).await?;
+            let results = join_all(pools.iter().map(|p| jmx.get_slick_metrics(p))).await;
+            Fetcher::by_pool(db_pools, pools, results)
+        }.await.map_err(Fetcher::format_slick_error)
+    }
+
+    pub async fn get_slick_config(&self, target: &str) -> Result<BTreeMap<String, SlickConfig>, String> {
+        let db_pools = self.db_pools(target)?;
+        let jmx = self.jmx(target);
+        async {
+            let pools = Fetcher::db_pool_names(jmx, db_pools, DbPoolKind::Slick).await?;
+            let results = join_all(pools.iter().map(|p| jmx.get_slick_config(p))).await;
+            Fetcher::by_pool(db_pools, pools, results)
+        }.await.map_err(Fetcher::format_slick_error)
+    }
+
+    pub async fn get_jvm_metrics(&self, target: &str) -> Result<JvmMetrics, String> {
+        self.jmx(target).get_jvm_metrics().await
+            .map_err(|e| format!("Error loading JVM metrics: {}", e))
     }
 
-    pub fn get_slick_metrics(&self) -> Result<SlickMetrics, String> {
-        self.jmx.as_ref().unwrap().get_slick_metrics().map_err(|e| Fetcher::format_slick_error(e))
+    pub async fn get_thread_dump(&self, target: &str) -> Result<ThreadDump, String> {
+        self.jmx(target).get_thread_dump().await
+            .map_err(|e| format!("Error loading thread dump: {}", e))
     }
 
-    pub fn get_slick_config(&self) -> Result<SlickConfig, String> {
-        self.jmx.as_ref().unwrap().get_slick_config().map_err(|e| Fetcher::format_slick_error(e))
+    pub async fn get_mbean_names(&self, target: &str) -> Result<Vec<String>, String> {
+        self.jmx(target).get_mbean_names().await
+            .map_err(|e| format!("Error loading MBean names: {}", e))
     }
 
-    pub fn get_actor_tree(&self) -> Result<Vec<ActorTreeNode>, String> {
-        let s = self.akka_settings.as_ref().unwrap();
-        akka::client::get_actors(&s.tree_address, s.tree_timeout)
-            .map_err(|e| format!("Error loading akka actor tree tree: {}", e))
+    pub async fn get_mbean(&self, target: &str, name: String) -> Result<MBean, String> {
+        self.jmx(target).get_mbean(&name).await
+            .map_err(|e| format!("Error loading MBean: {}", e))
     }
 
-    pub fn get_actor_system_status(&self) -> Result<ActorSystemStatus, String> {
-        let s = self.akka_settings.as_ref().unwrap();
-        akka::client::get_actor_system_status(&s.status_address, s.status_timeout)
+    /// Values of pinned numeric attributes. Attributes that can't be read (e.g. their MBean is gone) have no value.
+    pub async fn get_pinned_attributes(&self, target: &str) -> Result<Vec<(MBeanAttributeRef, Option<f64>)>, String> {
+        let attributes = self.clients(target).pinned_attributes.lock().unwrap().clone();
+        if attributes.is_empty() {
+            return Ok(vec![]);
+        }
+        let jmx = self.jmx(target);
+        let mut values = vec![];
+        let mut last_error = None;
+        for a in attributes {
+            match jmx.get_numeric_attribute(&a).await {
+                Ok(v) => values.push((a, v)),
+                Err(e) => {
+                    last_error = Some(e);
+                    values.push((a, None));
+                }
+            }
+        }
+        match last_error {
+            // nothing could be read, most likely the connection is gone
+            Some(e) if values.iter().all(|(_, v)| v.is_none()) => Err(format!("Error loading pinned MBean attributes: {}", e)),
+            _ => Ok(values),
+        }
+    }
+
+    pub async fn get_actor_tree(&self, target: &str) -> Result<Vec<ActorTreeNode>, String> {
+        let s = self.clients(target).akka_settings.as_ref().unwrap();
+        akka::client::get_actors(&s.tree_address, s.tree_timeout).await
+            .map_err(|e| format!("Error loading akka actor tree: {}", e))
+    }
+
+    pub async fn get_actor_system_status(&self, target: &str) -> Result<ActorSystemStatus, String> {
+        let s = self.clients(target).akka_settings.as_ref().unwrap();
+        akka::client::get_actor_system_status(&s.status_address, s.status_timeout).await
             .map_err(|e| format!("Error loading akka actor system status: {}", e))
     }
 
-    pub fn get_dead_letters(&self) -> Result<(DeadLettersSnapshot, DeadLettersWindow), String> {
-        let s = self.akka_settings.as_ref().unwrap();
-        akka::client::get_deadletters(&s.dead_letters_address, s.dead_letters_window)
+    pub async fn get_dead_letters(&self, target: &str) -> Result<(DeadLettersSnapshot, DeadLettersWindow), String> {
+        let s = self.clients(target).akka_settings.as_ref().unwrap();
+        akka::client::get_deadletters(&s.dead_letters_address, s.dead_letters_window).await
             .map_err(|e| format!("Error loading dead letters metrics: {}", e))
     }
 
-    fn format_slick_error(e: jmx::Error) -> String {
+    fn jmx(&self, target: &str) -> &dyn JmxSource {
+        self.clients(target).jmx.as_deref().unwrap()
+    }
+
+    fn clients(&self, target: &str) -> &ClientSet {
+        &self.clients[target]
+    }
+
+    fn db_pools(&self, target: &str) -> Result<&DbPools, String> {
+        self.clients(target).db_pools.as_ref()
+            .ok_or_else(|| "Slick and HikariCP metrics need connection pool names, please specify --db-pool-name or --discover-db-pools".to_owned())
+    }
+
+    /// Names of pools to read, discovering them from registered MBeans if needed.
+    async fn db_pool_names(jmx: &dyn JmxSource, db_pools: &DbPools, kind: DbPoolKind) -> Result<Vec<String>, String> {
+        match db_pools {
+            DbPools::Named(names) => Ok(names.clone()),
+            DbPools::Discovered => {
+                let mut names: Vec<String> = jmx.query_names(kind.pattern()).await?.iter()
+                    .filter_map(|n| kind.pool_name(n))
+                    .map(|n| n.to_owned())
+                    .collect();
+                names.sort();
+                if names.is_empty() {
+                    return Err(format!("No MBeans found matching {}", kind.pattern()));
+                }
+                Ok(names)
+            }
+        }
+    }
+
+    /// Pools among the monitored ones that have HikariCP MBeans registered.
+    async fn hikari_pool_names(jmx: &dyn JmxSource, db_pools: &DbPools) -> Result<Vec<String>, String> {
+        let mut registered: Vec<String> = jmx.query_names(DbPoolKind::Hikari.pattern()).await?.iter()
+            .filter_map(|n| DbPoolKind::Hikari.pool_name(n))
+            .map(|n| n.to_owned())
+            .collect();
+        registered.sort();
+        match db_pools {
+            DbPools::Named(names) => Ok(names.iter().filter(|n| registered.contains(n)).cloned().collect()),
+            DbPools::Discovered => Ok(registered),
+        }
+    }
+
+    fn has_hikari_timings(db_pool_name: &str, names: &[String]) -> bool {
+        [HikariTiming::Wait, HikariTiming::Usage, HikariTiming::Creation].iter()
+            .any(|t| t.find_mbean(db_pool_name, names).is_some())
+    }
+
+    async fn hikari_timings(jmx: &dyn JmxSource, db_pool_name: &str, names: &[String]) -> Result<HikariTimings, String> {
+        let percentiles = |timing: HikariTiming| async move {
+            match timing.find_mbean(db_pool_name, names) {
+                Some(mbean) => jmx.get_percentiles(mbean).await.map(Some),
+                None => Ok(None),
+            }
+        };
+        let timings = HikariTimings {
+            wait: percentiles(HikariTiming::Wait).await?,
+            usage: percentiles(HikariTiming::Usage).await?,
+            creation: percentiles(HikariTiming::Creation).await?,
+        };
+        Ok(timings)
+    }
+
+    /// Collects results by pool. Pools named by the user have to be readable, while discovered ones
+    /// that can't be read are left out, unless none of them can.
+    fn by_pool<T>(db_pools: &DbPools, pools: Vec<String>, results: Vec<Result<T, String>>) -> Result<BTreeMap<String, T>, String> {
+        let mut metrics = BTreeMap::new();
+        let mut last_error = None;
+        for (pool, result) in pools.into_iter().zip(results) {
+            match result {
+                Ok(m) => {
+                    metrics.insert(pool, m);
+                }
+                Err(e) if *db_pools == DbPools::Discovered => last_error = Some(e),
+                Err(e) => return Err(format!("Couldn't read pool {}: {}", pool, e)),
+            }
+        }
+        match last_error {
+            Some(e) if metrics.is_empty() => Err(e),
+            _ => Ok(metrics),
+        }
+    }
+
+    fn format_slick_error(e: String) -> String {
         format!(
             "No Slick JMX metrics found. Are you sure you have registerMbeans=true in your Slick config?\r\nUnderlying error: {}", e
         )
     }
 }
+
+///
+/// Runs the fetcher on a shared tokio runtime.
+///
+/// Every polled source of every target gets its own task and schedule, so a slow source only delays itself.
+/// A failing source is retried with exponential backoff.
+/// Optional requests, like HikariCP ones, stop once the target turns out not to publish their data.
+/// On-demand requests run concurrently as well; a request that is already in flight for the same target is dropped.
+///
+pub fn run(
+    runtime: &Handle,
+    targets: Vec<TargetSettings>,
+    tick_rate: Duration,
+    mut requests: UnboundedReceiver<(String, FetcherRequest)>,
+    respond: Responder) {
+    runtime.spawn(async move {
+        let fetcher = Arc::new(Fetcher::new(targets));
+
+        for target in fetcher.clients.keys() {
+            tokio::spawn(start_polling(fetcher.clone(), target.clone(), tick_rate, respond.clone()));
+        }
+
+        let in_flight: Arc<Mutex<HashSet<(String, FetcherRequest)>>> = Arc::new(Mutex::new(HashSet::new()));
+        while let Some((target, request)) = requests.recv().await {
+            if !in_flight.lock().unwrap().insert((target.clone(), request.clone())) {
+                continue;
+            }
+            let fetcher = fetcher.clone();
+            let in_flight = in_flight.clone();
+            let respond = respond.clone();
+            tokio::spawn(async move {
+                let response = fetcher.fetch(&target, request.clone()).await;
+                in_flight.lock().unwrap().remove(&(target.clone(), request));
+                respond(target, response);
+            });
+        }
+    });
+}
+
+async fn start_polling(fetcher: Arc<Fetcher>, target: String, every: Duration, respond: Responder) {
+    let clients = fetcher.clients(&target);
+    for request in clients.polled_requests() {
+        tokio::spawn(poll(fetcher.clone(), target.clone(), request, every, respond.clone()));
+    }
+    for request in clients.initial_requests() {
+        tokio::spawn(retry_until_success(fetcher.clone(), target.clone(), request, every, respond.clone()));
+    }
+}
+
+async fn poll(fetcher: Arc<Fetcher>, target: String, request: FetcherRequest, every: Duration, respond: Responder) {
+    let mut backoff = Backoff::new(every);
+    loop {
+        let started = Instant::now();
+        let response = fetcher.fetch(&target, request.clone()).await;
+        if response.is_err() && is_unavailable(&fetcher, &target, &request).await {
+            respond(target, response);
+            return;
+        }
+        if response.is_err() {
+            backoff.on_failure();
+            let delay = backoff.delay();
+            respond(target.clone(), response);
+            respond(target.clone(), FetcherResponse::RetryScheduled(request.clone(), delay));
+            sleep_until(Instant::now() + delay).await;
+        } else {
+            backoff.on_success();
+            respond(target.clone(), response);
+            sleep_until(started + every).await;
+        }
+    }
+}
+
+async fn retry_until_success(fetcher: Arc<Fetcher>, target: String, request: FetcherRequest, every: Duration, respond: Responder) {
+    let mut backoff = Backoff::new(every);
+    loop {
+        let response = fetcher.fetch(&target, request.clone()).await;
+        if !response.is_err() || is_unavailable(&fetcher, &target, &request).await {
+            respond(target, response);
+            return;
+        }
+        backoff.on_failure();
+        respond(target.clone(), response);
+        respond(target.clone(), FetcherResponse::RetryScheduled(request.clone(), backoff.delay()));
+        sleep_until(Instant::now() + backoff.delay()).await;
+    }
+}
+
+/// Whether a failed optional request can stop, since its data isn't published at all.
+async fn is_unavailable(fetcher: &Fetcher, target: &str, request: &FetcherRequest) -> bool {
+    request.is_hikari() && fetcher.is_available(target, request).await == Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, mpsc, Mutex};
+    use std::time::Duration;
+
+    use crate::app::TargetState;
+    use crate::fetcher::{self, ClientSet, Fetcher, FetcherRequest, FetcherResponse, Responder};
+    use crate::health::{HealthStatus, Source};
+    use crate::jmx::model::{DbPools, HikariMetrics, JMXConnectionSettings, Percentiles, SlickMetrics};
+    use crate::jmx::source::StubJmxSource;
+    use crate::target::TargetSettings;
+    use crate::zio::zmx::{StubZMXClient, ZMXClient};
+
+    fn fetcher_with_zmx(targets: Vec<(&str, StubZMXClient)>) -> Fetcher {
+        let clients = targets.into_iter()
+            .map(|(name, zmx)| {
+                let zmx_client: Box<dyn ZMXClient> = Box::new(zmx);
+                (name.to_owned(), ClientSet { zmx_client: Some(zmx_client), jmx: None, db_pools: None, akka_settings: None, pinned_attributes: Mutex::new(vec![]) })
+            })
+            .collect::<HashMap<String, ClientSet>>();
+        Fetcher { clients }
+    }
+
+    fn fetcher_with_jmx(jmx: StubJmxSource, db_pools: Option<DbPools>) -> Fetcher {
+        let clients = ClientSet {
+            zmx_client: None,
+            jmx: Some(Box::new(jmx)),
+            db_pools,
+            akka_settings: None,
+            pinned_attributes: Mutex::new(vec![]),
+        };
+        Fetcher { clients: vec![("orders".to_owned(), clients)].into_iter().collect() }
+    }
+
+    fn named(pools: &[&str]) -> Option<DbPools> {
+        Some(DbPools::Named(pools.iter().map(|p| p.to_string()).collect()))
+    }
+
+    fn by_pool<T>(metrics: Vec<(&str, T)>) -> HashMap<String, T> {
+        metrics.into_iter().map(|(p, m)| (p.to_owned(), m)).collect()
+    }
+
+    fn jmx_target(db_pools: Option<DbPools>) -> TargetSettings {
+        TargetSettings {
+            name: "orders".to_owned(),
+            zio_zmx: None,
+            jmx: Some(JMXConnectionSettings { db_pools, ..JMXConnectionSettings::for_test("localhost:9010") }),
+            akka: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn fetcher_dumps_fibers_of_requested_target() {
+        let fetcher = fetcher_with_zmx(vec![
+            ("replica-1", StubZMXClient::new(Ok(vec![]))),
+            ("replica-2", StubZMXClient::new(Err("connection refused".to_owned()))),
+        ]);
+
+        assert_eq!(fetcher.dump_fibers("replica-1").await, Ok(vec![]));
+        assert_eq!(
+            fetcher.dump_fibers("replica-2").await,
+            Err("Couldn't get fiber dump from <stub>. Make sure zio-zmx is listening on specified port. Underlying error: connection refused".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn fetcher_explains_missing_slick_metrics() {
+        let fetcher = fetcher_with_jmx(StubJmxSource::new(), named(&["orders"]));
+        assert_eq!(
+            fetcher.get_slick_metrics("orders").await.err(),
+            Some("No Slick JMX metrics found. Are you sure you have registerMbeans=true in your Slick config?\r\n\
+                 Underlying error: Couldn't read pool orders: javax.management.InstanceNotFoundException".to_owned())
+        );
+
+        let fetcher = fetcher_with_jmx(StubJmxSource::new(), Some(DbPools::Discovered));
+        assert_eq!(
+            fetcher.get_slick_metrics("orders").await.err(),
+            Some("No Slick JMX metrics found. Are you sure you have registerMbeans=true in your Slick config?\r\n\
+                 Underlying error: No MBeans found matching slick:type=AsyncExecutor,*".to_owned())
+        );
+
+        let fetcher = fetcher_with_jmx(StubJmxSource::new(), None);
+        assert_eq!(
+            fetcher.get_slick_metrics("orders").await.err(),
+            Some("Slick and HikariCP metrics need connection pool names, please specify --db-pool-name or --discover-db-pools".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn fetcher_reads_named_and_discovered_pools() {
+        let slick = |active_threads| SlickMetrics { active_threads, queue_size: 0 };
+        let jmx = || StubJmxSource {
+            slick: by_pool(vec![("orders-read", slick(1)), ("orders-write", slick(2)), ("reporting", slick(3))]),
+            mbean_names: vec![
+                "slick:type=AsyncExecutor,name=orders-write".to_owned(),
+                "slick:type=AsyncExecutor,name=orders-read".to_owned(),
+                "slick:type=AsyncExecutor,name=orders-reed".to_owned(),
+                "com.zaxxer.hikari:type=Pool (orders-read)".to_owned(),
+            ],
+            ..StubJmxSource::new()
+        };
+
+        // a pool named by the user has to be readable
+        let fetcher = fetcher_with_jmx(jmx(), named(&["reporting", "orders-reed"]));
+        let response = fetcher.fetch("orders", FetcherRequest::SlickMetrics).await;
+        assert_eq!(
+            response.error(),
+            Some(&"No Slick JMX metrics found. Are you sure you have registerMbeans=true in your Slick config?\r\n\
+                   Underlying error: Couldn't read pool orders-reed: javax.management.InstanceNotFoundException".to_owned())
+        );
+        let mut target = TargetState::new(&jmx_target(named(&["reporting", "orders-reed"])));
+        target.on_fetcher_response(response);
+        assert_eq!(target.health(Source::Jmx).unwrap().status(), HealthStatus::Degraded);
+
+        let fetcher = fetcher_with_jmx(jmx(), named(&["reporting"]));
+        let pools = fetcher.get_slick_metrics("orders").await.unwrap();
+        assert_eq!(pools.keys().collect::<Vec<&String>>(), vec!["reporting"]);
+
+        // discovered ones that can't be read are left out
+        let fetcher = fetcher_with_jmx(jmx(), Some(DbPools::Discovered));
+        let pools = fetcher.get_slick_metrics("orders").await.unwrap();
+        assert_eq!(pools.keys().collect::<Vec<&String>>(), vec!["orders-read", "orders-write"]);
+        assert_eq!(pools["orders-write"].active_threads, 2);
+    }
+
+    #[tokio::test]
+    async fn fetcher_reads_hikari_timings_of_pools_that_publish_them() {
+        let wait = Percentiles { p50: 1.0, p75: 2.0, p95: 5.0, p99: 9.0, max: 40.0, mean: 1.5 };
+        let jmx = StubJmxSource {
+            mbean_names: vec![
+                "com.zaxxer.hikari:type=Pool (orders-read)".to_owned(),
+                "com.zaxxer.hikari:type=Pool (orders-write)".to_owned(),
+                "metrics:name=orders-read.pool.Wait,type=timers".to_owned(),
+                "metrics:name=orders-read.pool.Usage,type=histograms".to_owned(),
+            ],
+            percentiles: by_pool(vec![("metrics:name=orders-read.pool.Wait,type=timers", wait.clone())]),
+            ..StubJmxSource::new()
+        };
+        let fetcher = fetcher_with_jmx(jmx, Some(DbPools::Discovered));
+
+        // orders-read fails on its unreadable usage MBean, orders-write has no timing MBeans at all
+        assert!(fetcher.get_hikari_timings("orders").await.is_err());
+
+        let jmx = StubJmxSource {
+            mbean_names: vec![
+                "com.zaxxer.hikari:type=Pool (orders-read)".to_owned(),
+                "com.zaxxer.hikari:type=Pool (orders-write)".to_owned(),
+                "metrics:name=orders-read.pool.Wait,type=timers".to_owned(),
+            ],
+            percentiles: by_pool(vec![("metrics:name=orders-read.pool.Wait,type=timers", wait.clone())]),
+            ..StubJmxSource::new()
+        };
+        let fetcher = fetcher_with_jmx(jmx, Some(DbPools::Discovered));
+        assert_eq!(fetcher.is_available("orders", &FetcherRequest::HikariTimings).await, Ok(true));
+        let timings = fetcher.get_hikari_timings("orders").await.unwrap();
+        assert_eq!(timings.keys().collect::<Vec<&String>>(), vec!["orders-read"]);
+        assert_eq!(timings["orders-read"].wait, Some(wait));
+        assert_eq!(timings["orders-read"].usage, None);
+    }
+
+    #[tokio::test]
+    async fn slick_tab_works_without_hikari() {
+        let slick = SlickMetrics { active_threads: 2, queue_size: 5 };
+        let hikari = HikariMetrics { total: 10, active: 2, idle: 8, waiting: 0 };
+        let mut target = TargetState::new(&jmx_target(named(&["orders"])));
+
+        let fetcher = fetcher_with_jmx(StubJmxSource { slick: by_pool(vec![("orders", slick.clone())]), ..StubJmxSource::new() }, named(&["orders"]));
+        target.on_fetcher_response(fetcher.fetch("orders", FetcherRequest::SlickMetrics).await);
+        target.on_fetcher_response(fetcher.fetch("orders", FetcherRequest::HikariMetrics).await);
+        target.on_fetcher_response(FetcherResponse::RetryScheduled(FetcherRequest::HikariMetrics, Duration::from_secs(4)));
+        assert_eq!(fetcher.is_available("orders", &FetcherRequest::HikariMetrics).await, Ok(false));
+
+        // a missing hikari pool only hides its chart, jmx stays healthy
+        let pool = target.slick.as_ref().unwrap().current_pool().unwrap();
+        assert!(!pool.has_hikari);
+        assert_eq!(pool.slick_metrics.len(), 1);
+        assert_eq!(target.health(Source::Jmx).unwrap().status(), HealthStatus::Connected);
+
+        let fetcher = fetcher_with_jmx(
+            StubJmxSource {
+                slick: by_pool(vec![("orders", slick)]),
+                hikari: by_pool(vec![("orders", hikari)]),
+                mbean_names: vec!["com.zaxxer.hikari:type=Pool (orders)".to_owned()],
+                ..StubJmxSource::new()
+            },
+            named(&["orders"]),
+        );
+        assert_eq!(fetcher.is_available("orders", &FetcherRequest::HikariMetrics).await, Ok(true));
+        assert_eq!(fetcher.is_available("orders", &FetcherRequest::HikariTimings).await, Ok(false));
+        target.on_fetcher_response(fetcher.fetch("orders", FetcherRequest::HikariMetrics).await);
+
+        let pool = target.slick.as_ref().unwrap().current_pool().unwrap();
+        assert!(pool.has_hikari);
+        assert_eq!(pool.hikari_metrics.len(), 1);
+    }
+
+    #[test]
+    fn fetcher_polls_each_target_and_serves_requests() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let respond: Responder = Arc::new(move |t, r: FetcherResponse| {
+            let kind = match r {
+                FetcherResponse::FiberCount(_) => "regular",
+                FetcherResponse::FiberDump(_) => "on-demand",
+                _ => return,
+            };
+            let _ = tx.lock().unwrap().send((t, kind));
+        });
+        // a port that was just released, so every dump fails fast
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let target = TargetSettings {
+            name: "replica-1".to_owned(),
+            zio_zmx: Some(address.to_string()),
+            jmx: None,
+            akka: None,
+        };
+        let (txf, rxf) = tokio::sync::mpsc::unbounded_channel();
+
+        fetcher::run(runtime.handle(), vec![target], Duration::from_secs(60), rxf, respond);
+        txf.send(("replica-1".to_owned(), FetcherRequest::FiberDump)).unwrap();
+
+        let mut received = vec![
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ];
+        received.sort();
+        assert_eq!(received, vec![("replica-1".to_owned(), "on-demand"), ("replica-1".to_owned(), "regular")]);
+        runtime.shutdown_background();
+    }
+}
@@ -0,0 +1,230 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::fetcher::FetcherRequest;
+
+/// A data source panopticon connects to. A target has at most one of each.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Source {
+    Zmx,
+    Jmx,
+    Akka,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HealthStatus {
+    Connecting,
+    Connected,
+    Degraded,
+    Down,
+}
+
+/// Health of a single request to a data source, updated with every response to it.
+#[derive(Clone, Debug)]
+pub struct RequestHealth {
+    pub status: HealthStatus,
+    pub failures: u32,
+    pub retry_at: Option<Instant>,
+}
+
+impl RequestHealth {
+    /// Number of consecutive failures after which a request is considered down.
+    pub const DOWN_AFTER_FAILURES: u32 = 3;
+
+    pub fn new() -> RequestHealth {
+        RequestHealth {
+            status: HealthStatus::Connecting,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    pub fn on_success(&mut self) {
+        self.status = HealthStatus::Connected;
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    pub fn on_failure(&mut self) {
+        self.failures += 1;
+        self.status = if self.failures >= RequestHealth::DOWN_AFTER_FAILURES {
+            HealthStatus::Down
+        } else {
+            HealthStatus::Degraded
+        };
+    }
+
+    fn is_failing(&self) -> bool {
+        matches!(self.status, HealthStatus::Degraded | HealthStatus::Down)
+    }
+}
+
+/// Health of a single data source, derived from the health of every request made to it.
+/// Requests fail independently, e.g. Slick metrics may be missing while JVM metrics are read fine,
+/// so the source is down only when all of its requests are.
+#[derive(Clone, Debug)]
+pub struct SourceHealth {
+    requests: HashMap<FetcherRequest, RequestHealth>,
+    pub last_error: Option<String>,
+}
+
+impl SourceHealth {
+    pub fn new() -> SourceHealth {
+        SourceHealth {
+            requests: HashMap::new(),
+            last_error: None,
+        }
+    }
+
+    pub fn on_success(&mut self, request: &FetcherRequest) {
+        self.request_mut(request).on_success();
+    }
+
+    pub fn on_failure(&mut self, request: &FetcherRequest, error: String) {
+        self.request_mut(request).on_failure();
+        self.last_error = Some(error);
+    }
+
+    pub fn on_retry_scheduled(&mut self, request: &FetcherRequest, retry_in: Duration) {
+        self.request_mut(request).retry_at = Some(Instant::now() + retry_in);
+    }
+
+    fn request_mut(&mut self, request: &FetcherRequest) -> &mut RequestHealth {
+        self.requests.entry(SourceHealth::key(request)).or_insert_with(RequestHealth::new)
+    }
+
+    /// MBeans are read one at a time on demand, so a single MBean that can't be read
+    /// doesn't keep the source degraded after another one is read fine.
+    fn key(request: &FetcherRequest) -> FetcherRequest {
+        match request {
+            FetcherRequest::MBean(_) => FetcherRequest::MBean(String::new()),
+            r => r.clone(),
+        }
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        let mut statuses = self.requests.values().map(|r| r.status).filter(|s| *s != HealthStatus::Connecting).peekable();
+        if statuses.peek().is_none() {
+            return HealthStatus::Connecting;
+        }
+        let statuses: Vec<HealthStatus> = statuses.collect();
+        if statuses.iter().all(|s| *s == HealthStatus::Down) {
+            HealthStatus::Down
+        } else if statuses.iter().any(|s| *s != HealthStatus::Connected) {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Connected
+        }
+    }
+
+    /// Time left until the next attempt to make one of the failing requests.
+    pub fn retry_in(&self) -> Option<Duration> {
+        self.requests.values()
+            .filter(|r| r.is_failing())
+            .filter_map(|r| r.retry_at)
+            .min()
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn summary(&self) -> String {
+        match (self.status(), self.retry_in()) {
+            (HealthStatus::Connecting, _) => "connecting".to_owned(),
+            (HealthStatus::Connected, _) => "connected".to_owned(),
+            (HealthStatus::Degraded, Some(d)) => format!("degraded, retry in {}s", d.as_secs()),
+            (HealthStatus::Degraded, None) => "degraded".to_owned(),
+            (HealthStatus::Down, Some(d)) => format!("down, retry in {}s", d.as_secs()),
+            (HealthStatus::Down, None) => "down".to_owned(),
+        }
+    }
+}
+
+/// Exponential backoff for a polled source: the interval doubles with every consecutive failure.
+pub struct Backoff {
+    every: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    pub const MAX_DELAY: Duration = Duration::from_secs(60);
+
+    pub fn new(every: Duration) -> Backoff {
+        Backoff { every, max: Backoff::MAX_DELAY.max(every), failures: 0 }
+    }
+
+    pub fn on_success(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn on_failure(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    pub fn delay(&self) -> Duration {
+        let factor = 2u32.saturating_pow(min(self.failures, 16));
+        min(self.every.saturating_mul(factor), self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::fetcher::FetcherRequest;
+    use crate::health::{Backoff, HealthStatus, RequestHealth, SourceHealth};
+
+    #[test]
+    fn source_health_degrades_and_recovers() {
+        let mut health = SourceHealth::new();
+        assert_eq!(health.status(), HealthStatus::Connecting);
+
+        health.on_failure(&FetcherRequest::FiberCount, "connection refused".to_owned());
+        assert_eq!(health.status(), HealthStatus::Degraded);
+        health.on_failure(&FetcherRequest::FiberCount, "connection refused".to_owned());
+        health.on_failure(&FetcherRequest::FiberCount, "connection refused".to_owned());
+        assert_eq!(health.status(), HealthStatus::Down);
+        health.on_retry_scheduled(&FetcherRequest::FiberCount, Duration::from_secs(8));
+        assert!(health.retry_in().unwrap() <= Duration::from_secs(8));
+
+        health.on_success(&FetcherRequest::FiberCount);
+        assert_eq!(health.status(), HealthStatus::Connected);
+        assert_eq!(health.retry_in(), None);
+        assert_eq!(health.last_error, Some("connection refused".to_owned()));
+    }
+
+    #[test]
+    fn source_is_down_only_when_every_request_is() {
+        let mut health = SourceHealth::new();
+        for _ in 0..RequestHealth::DOWN_AFTER_FAILURES {
+            health.on_failure(&FetcherRequest::SlickMetrics, "no slick beans".to_owned());
+            health.on_success(&FetcherRequest::JvmMetrics);
+        }
+        health.on_retry_scheduled(&FetcherRequest::SlickMetrics, Duration::from_secs(30));
+        assert_eq!(health.status(), HealthStatus::Degraded);
+
+        for _ in 0..RequestHealth::DOWN_AFTER_FAILURES {
+            health.on_failure(&FetcherRequest::JvmMetrics, "connection refused".to_owned());
+        }
+        health.on_retry_scheduled(&FetcherRequest::JvmMetrics, Duration::from_secs(4));
+        assert_eq!(health.status(), HealthStatus::Down);
+        // the earliest retry is shown
+        assert!(health.retry_in().unwrap() <= Duration::from_secs(4));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(2));
+        assert_eq!(backoff.delay(), Duration::from_secs(2));
+        backoff.on_failure();
+        assert_eq!(backoff.delay(), Duration::from_secs(4));
+        backoff.on_failure();
+        assert_eq!(backoff.delay(), Duration::from_secs(8));
+        for _ in 0..10 {
+            backoff.on_failure();
+        }
+        assert_eq!(backoff.delay(), Backoff::MAX_DELAY);
+        backoff.on_success();
+        assert_eq!(backoff.delay(), Duration::from_secs(2));
+    }
+}
@@ -1,22 +1,42 @@
+use std::sync::Mutex;
+
+use crate::jmx::connection::MBeanConnection;
 use crate::jmx::model::*;
-use jmx::{MBeanClient, MBeanClientTrait};
+use crate::jmx::platform::PlatformMXBeans;
 use serde::de::DeserializeOwned;
+use serde_json::Value;
 
+/// j4rs instances can't be shared between threads, hence the locks.
 pub struct JMXClient {
-    connection: MBeanClient,
-    db_pool_name: String,
+    connection: Mutex<MBeanConnection>,
+    platform: Mutex<PlatformMXBeans>,
 }
 
 impl JMXClient {
-    pub fn new(connection: MBeanClient, db_pool_name: String) -> JMXClient {
-        JMXClient { connection, db_pool_name }
+    pub fn new(connection: MBeanConnection, platform: PlatformMXBeans) -> JMXClient {
+        JMXClient { connection: Mutex::new(connection), platform: Mutex::new(platform) }
+    }
+
+    /// Connects to a remote jmx source. This loads a jvm, so it blocks for a while.
+    pub fn connect(settings: &JMXConnectionSettings) -> Result<JMXClient, String> {
+        MBeanConnection::connect(settings)
+            .map(|connection| {
+                let platform = PlatformMXBeans::new(&connection);
+                JMXClient::new(connection, platform)
+            })
+            .map_err(|e| format!("Couldn't connect to jmx at {}. Error: {}", settings.service_url(), e))
+    }
+
+    /// Checks whether the connection is still usable, by reading an attribute every jvm has.
+    pub fn is_alive(&self) -> bool {
+        self.get_attribute::<i64>("java.lang:type=Runtime", "Uptime").is_ok()
     }
 
-    pub fn get_hikari_metrics(&self) -> Result<HikariMetrics, jmx::Error> {
-        let total: i32 = self.get_hikari_attribute("TotalConnections")?;
-        let active: i32 = self.get_hikari_attribute("ActiveConnections")?;
-        let waiting: i32 = self.get_hikari_attribute("ThreadsAwaitingConnection")?;
-        let idle: i32 = self.get_hikari_attribute("IdleConnections")?;
+    pub fn get_hikari_metrics(&self, db_pool_name: &str) -> Result<HikariMetrics, jmx::Error> {
+        let total: i32 = self.get_hikari_attribute(db_pool_name, "TotalConnections")?;
+        let active: i32 = self.get_hikari_attribute(db_pool_name, "ActiveConnections")?;
+        let waiting: i32 = self.get_hikari_attribute(db_pool_name, "ThreadsAwaitingConnection")?;
+        let idle: i32 = self.get_hikari_attribute(db_pool_name, "IdleConnections")?;
 
         Result::Ok(HikariMetrics {
             total,
@@ -26,9 +46,34 @@ impl JMXClient {
         })
     }
 
-    pub fn get_slick_metrics(&self) -> Result<SlickMetrics, jmx::Error> {
-        let active_threads: i32 = self.get_slick_attribute("ActiveThreads")?;
-        let queue_size: i32 = self.get_slick_attribute("QueueSize")?;
+    pub fn get_hikari_config(&self, db_pool_name: &str) -> Result<HikariConfig, jmx::Error> {
+        let mbean = format!("com.zaxxer.hikari:type=PoolConfig ({})", db_pool_name);
+
+        Ok(HikariConfig {
+            maximum_pool_size: self.get_attribute(&mbean, "MaximumPoolSize")?,
+            minimum_idle: self.get_attribute(&mbean, "MinimumIdle")?,
+            connection_timeout: self.get_attribute(&mbean, "ConnectionTimeout")?,
+            leak_detection_threshold: self.get_attribute(&mbean, "LeakDetectionThreshold")?,
+        })
+    }
+
+    /// Reads percentiles of a Dropwizard timer or histogram MBean.
+    pub fn get_percentiles(&self, mbean: &str) -> Result<Percentiles, jmx::Error> {
+        let [p50, p75, p95, p99, max, mean] = Percentiles::ATTRIBUTES;
+
+        Ok(Percentiles {
+            p50: self.get_attribute(mbean, p50)?,
+            p75: self.get_attribute(mbean, p75)?,
+            p95: self.get_attribute(mbean, p95)?,
+            p99: self.get_attribute(mbean, p99)?,
+            max: self.get_attribute(mbean, max)?,
+            mean: self.get_attribute(mbean, mean)?,
+        })
+    }
+
+    pub fn get_slick_metrics(&self, db_pool_name: &str) -> Result<SlickMetrics, jmx::Error> {
+        let active_threads: i32 = self.get_slick_attribute(db_pool_name, "ActiveThreads")?;
+        let queue_size: i32 = self.get_slick_attribute(db_pool_name, "QueueSize")?;
 
         Result::Ok(SlickMetrics {
             active_threads,
@@ -36,9 +81,9 @@ impl JMXClient {
         })
     }
 
-    pub fn get_slick_config(&self) -> Result<SlickConfig, jmx::Error> {
-        let max_threads: i32 = self.get_slick_attribute("MaxThreads")?;
-        let max_queue_size: i32 = self.get_slick_attribute("MaxQueueSize")?;
+    pub fn get_slick_config(&self, db_pool_name: &str) -> Result<SlickConfig, jmx::Error> {
+        let max_threads: i32 = self.get_slick_attribute(db_pool_name, "MaxThreads")?;
+        let max_queue_size: i32 = self.get_slick_attribute(db_pool_name, "MaxQueueSize")?;
 
         Result::Ok(SlickConfig {
             max_threads,
@@ -46,12 +91,89 @@ impl JMXClient {
         })
     }
 
-    fn get_slick_attribute<T: DeserializeOwned>(&self, attr: &str) -> Result<T, jmx::Error> {
-        self.connection.get_attribute(format!("slick:type=AsyncExecutor,name={}", self.db_pool_name), attr)
+    /// Reads memory, gc, thread, class loading and cpu metrics from the platform MBeans.
+    pub fn get_jvm_metrics(&self) -> Result<JvmMetrics, jmx::Error> {
+        let (heap, non_heap) = self.platform.lock().unwrap().memory_usage()?;
+
+        let collectors = self.connection.lock().unwrap().query_names("java.lang:type=GarbageCollector,*")?;
+        let mut garbage_collectors = vec![];
+        for gc in collectors {
+            garbage_collectors.push(GarbageCollectorMetrics {
+                name: name_property(&gc).unwrap_or(&gc).to_owned(),
+                collection_count: self.get_attribute(&gc, "CollectionCount")?,
+                collection_time: self.get_attribute(&gc, "CollectionTime")?,
+            });
+        }
+        garbage_collectors.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // cpu loads are only available on HotSpot-like jvms, and are negative until the first measurement
+        let cpu_load = |attr: &str| self.get_attribute::<f64>("java.lang:type=OperatingSystem", attr).ok()
+            .filter(|x| *x >= 0.0);
+
+        Ok(JvmMetrics {
+            heap,
+            non_heap,
+            garbage_collectors,
+            live_threads: self.get_attribute("java.lang:type=Threading", "ThreadCount")?,
+            daemon_threads: self.get_attribute("java.lang:type=Threading", "DaemonThreadCount")?,
+            peak_threads: self.get_attribute("java.lang:type=Threading", "PeakThreadCount")?,
+            loaded_classes: self.get_attribute("java.lang:type=ClassLoading", "LoadedClassCount")?,
+            unloaded_classes: self.get_attribute("java.lang:type=ClassLoading", "UnloadedClassCount")?,
+            process_cpu_load: cpu_load("ProcessCpuLoad"),
+            system_cpu_load: cpu_load("SystemCpuLoad"),
+        })
+    }
+
+    pub fn get_thread_dump(&self) -> Result<ThreadDump, jmx::Error> {
+        self.platform.lock().unwrap().thread_dump()
+    }
+
+    /// Names of all MBeans registered in the remote jvm, sorted.
+    pub fn get_mbean_names(&self) -> Result<Vec<String>, jmx::Error> {
+        let mut names = self.connection.lock().unwrap().query_names("*:*")?;
+        names.sort();
+        Ok(names)
+    }
+
+    /// Names of MBeans matching a pattern, e.g. `slick:type=AsyncExecutor,*`.
+    pub fn query_names(&self, pattern: &str) -> Result<Vec<String>, jmx::Error> {
+        self.connection.lock().unwrap().query_names(pattern)
+    }
+
+    /// Reads all readable attributes of an MBean. Attributes that can't be read are kept, with the error as their value.
+    pub fn get_mbean(&self, name: &str) -> Result<MBean, jmx::Error> {
+        let info = self.connection.lock().unwrap().get_mbean_info(name)?;
+        let attributes = info.attributes.iter()
+            .filter(|a| a.is_readable)
+            .map(|a| {
+                let (value, numeric) = match self.get_attribute::<Value>(name, &a.name) {
+                    Ok(Value::String(s)) => (s, None),
+                    Ok(v) => (v.to_string(), v.as_f64()),
+                    Err(e) => (format!("<unavailable: {}>", e), None),
+                };
+                MBeanAttributeValue { name: a.name.clone(), type_name: a.type_name.clone(), value, numeric }
+            })
+            .collect();
+
+        Ok(MBean { name: name.to_owned(), class_name: info.class_name, attributes })
+    }
+
+    /// Reads a numeric attribute. Non-numeric values are reported as `None`.
+    pub fn get_numeric_attribute(&self, attribute: &MBeanAttributeRef) -> Result<Option<f64>, jmx::Error> {
+        let value: Value = self.get_attribute(&attribute.mbean, &attribute.attribute)?;
+        Ok(value.as_f64())
+    }
+
+    fn get_attribute<T: DeserializeOwned>(&self, mbean: &str, attr: &str) -> Result<T, jmx::Error> {
+        self.connection.lock().unwrap().get_attribute(mbean, attr)
+    }
+
+    fn get_slick_attribute<T: DeserializeOwned>(&self, db_pool_name: &str, attr: &str) -> Result<T, jmx::Error> {
+        self.get_attribute(&format!("slick:type=AsyncExecutor,name={}", db_pool_name), attr)
     }
 
-    fn get_hikari_attribute<T: DeserializeOwned>(&self, attr: &str) -> Result<T, jmx::Error> {
-        self.connection.get_attribute(format!("com.zaxxer.hikari:type=Pool ({})", self.db_pool_name), attr)
+    fn get_hikari_attribute<T: DeserializeOwned>(&self, db_pool_name: &str, attr: &str) -> Result<T, jmx::Error> {
+        self.get_attribute(&format!("com.zaxxer.hikari:type=Pool ({})", db_pool_name), attr)
     }
 }
 
@@ -0,0 +1,130 @@
+use std::convert::TryFrom;
+
+use j4rs::{Instance, InvocationArg, Jvm, JvmBuilder};
+use serde::de::DeserializeOwned;
+
+use crate::jmx::model::JMXConnectionSettings;
+
+///
+/// A remote `MBeanServerConnection`, made with j4rs directly.
+///
+/// The jmx crate connects with a service url only, so it can't pass credentials
+/// or a socket factory for registries protected with SSL.
+///
+pub struct MBeanConnection {
+    connector: Instance,
+    connection: Instance,
+}
+
+impl MBeanConnection {
+    /// Connects to a remote jmx source, starting the jvm first if needed.
+    pub fn connect(settings: &JMXConnectionSettings) -> Result<MBeanConnection, jmx::Error> {
+        // attaches to the jvm if it's already running
+        let jvm = JvmBuilder::new().build()?;
+
+        // trust stores are jvm-wide, so there's a single one for all targets, given on the command line
+        if let Some(truststore) = &settings.truststore {
+            MBeanConnection::set_system_property(&jvm, "javax.net.ssl.trustStore", truststore)?;
+        }
+        if let Some(password) = &settings.truststore_password {
+            MBeanConnection::set_system_property(&jvm, "javax.net.ssl.trustStorePassword", password)?;
+        }
+
+        let environment = jvm.create_instance("java.util.HashMap", &[])?;
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            let credentials = jvm.create_java_array(
+                "java.lang.String",
+                &[InvocationArg::try_from(username.as_str())?, InvocationArg::try_from(password.as_str())?],
+            )?;
+            MBeanConnection::put(&jvm, &environment, "jmx.remote.credentials", credentials)?;
+        }
+        if settings.ssl {
+            let factory = jvm.create_instance("javax.rmi.ssl.SslRMIClientSocketFactory", &[])?;
+            MBeanConnection::put(&jvm, &environment, "com.sun.jndi.rmi.factory.socket", factory)?;
+        }
+
+        let url = jvm.create_instance(
+            "javax.management.remote.JMXServiceURL",
+            &[InvocationArg::try_from(settings.service_url())?],
+        )?;
+        let connector = jvm.invoke_static(
+            "javax.management.remote.JMXConnectorFactory",
+            "connect",
+            &[InvocationArg::from(url), InvocationArg::from(jvm.cast(&environment, "java.util.Map")?)],
+        )?;
+        let connection = jvm.invoke(&connector, "getMBeanServerConnection", &[])?;
+        // j4rs looks methods up by the declared types of arguments
+        let connection = jvm.cast(&connection, "javax.management.MBeanServerConnection")?;
+        Ok(MBeanConnection { connector, connection })
+    }
+
+    pub fn get_attribute<T: DeserializeOwned>(&self, mbean: &str, attribute: &str) -> Result<T, jmx::Error> {
+        let jvm = Jvm::attach_thread()?;
+        let value = jvm.invoke(
+            &self.connection,
+            "getAttribute",
+            &[InvocationArg::from(MBeanConnection::object_name(&jvm, mbean)?), InvocationArg::try_from(attribute)?],
+        )?;
+        Ok(jvm.to_rust(value)?)
+    }
+
+    pub fn get_mbean_info(&self, mbean: &str) -> Result<jmx::MBeanInfo, jmx::Error> {
+        let jvm = Jvm::attach_thread()?;
+        let info = jvm.invoke(&self.connection, "getMBeanInfo", &[InvocationArg::from(MBeanConnection::object_name(&jvm, mbean)?)])?;
+        jmx::MBeanInfo::from_instance(&jvm, info)
+    }
+
+    /// Names of MBeans matching a pattern, e.g. `java.lang:type=GarbageCollector,*`.
+    pub fn query_names(&self, pattern: &str) -> Result<Vec<String>, jmx::Error> {
+        let jvm = Jvm::attach_thread()?;
+        let query = jvm.cast(&MBeanConnection::object_name(&jvm, pattern)?, "javax.management.QueryExp")?;
+        let names = jvm.invoke(
+            &self.connection,
+            "queryNames",
+            &[InvocationArg::from(MBeanConnection::object_name(&jvm, pattern)?), InvocationArg::from(query)],
+        )?;
+        let iterator = jvm.invoke(&names, "iterator", &[])?;
+        let mut result = vec![];
+        while jvm.to_rust::<bool>(jvm.invoke(&iterator, "hasNext", &[])?)? {
+            let name = jvm.invoke(&iterator, "next", &[])?;
+            result.push(jvm.to_rust(jvm.invoke(&name, "toString", &[])?)?);
+        }
+        Ok(result)
+    }
+
+    /// A typed proxy of a platform MXBean, e.g. `java.lang:type=Memory` as `java.lang.management.MemoryMXBean`.
+    pub fn platform_proxy(&self, name: &str, interface: &str) -> Result<Instance, jmx::Error> {
+        let jvm = Jvm::attach_thread()?;
+        let class = jvm.invoke_static("java.lang.Class", "forName", &[InvocationArg::try_from(interface)?])?;
+        let proxy = jvm.invoke_static(
+            "java.lang.management.ManagementFactory",
+            "newPlatformMXBeanProxy",
+            &[InvocationArg::from(jvm.clone_instance(&self.connection)?), InvocationArg::try_from(name)?, InvocationArg::from(class)],
+        )?;
+        Ok(jvm.cast(&proxy, interface)?)
+    }
+
+    fn object_name(jvm: &Jvm, name: &str) -> Result<Instance, jmx::Error> {
+        Ok(jvm.create_instance("javax.management.ObjectName", &[InvocationArg::try_from(name)?])?)
+    }
+
+    fn put(jvm: &Jvm, map: &Instance, key: &str, value: Instance) -> Result<(), jmx::Error> {
+        let key = jvm.cast(&jvm.create_instance("java.lang.String", &[InvocationArg::try_from(key)?])?, "java.lang.Object")?;
+        let value = jvm.cast(&value, "java.lang.Object")?;
+        jvm.invoke(map, "put", &[InvocationArg::from(key), InvocationArg::from(value)])?;
+        Ok(())
+    }
+
+    fn set_system_property(jvm: &Jvm, name: &str, value: &str) -> Result<(), jmx::Error> {
+        jvm.invoke_static("java.lang.System", "setProperty", &[InvocationArg::try_from(name)?, InvocationArg::try_from(value)?])?;
+        Ok(())
+    }
+}
+
+impl Drop for MBeanConnection {
+    fn drop(&mut self) {
+        if let Ok(jvm) = Jvm::attach_thread() {
+            let _ = jvm.invoke(&self.connector, "close", &[]);
+        }
+    }
+}
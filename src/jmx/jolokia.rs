@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::jmx::model::*;
+use crate::jmx::source::JmxSource;
+
+///
+/// Reads MBeans through a [Jolokia](https://jolokia.org) agent, i.e. JMX over HTTP and JSON.
+///
+/// Offers the same operations as `JMXClient`, without loading a jvm. Every operation is a POST
+/// of a single Jolokia request, e.g.:
+///
+///   ```json
+///   {"type": "read", "mbean": "java.lang:type=Threading", "attribute": ["ThreadCount", "PeakThreadCount"]}
+///   ```
+///
+pub struct JolokiaClient {
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+    http: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct JolokiaResponse {
+    status: u16,
+    #[serde(default)]
+    value: Value,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HikariPoolAttributes {
+    total_connections: i32,
+    active_connections: i32,
+    idle_connections: i32,
+    threads_awaiting_connection: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HikariPoolConfigAttributes {
+    maximum_pool_size: i32,
+    minimum_idle: i32,
+    connection_timeout: i64,
+    leak_detection_threshold: i64,
+}
+
+#[derive(Deserialize)]
+struct PercentilesAttributes {
+    #[serde(rename = "50thPercentile")]
+    p50: f64,
+    #[serde(rename = "75thPercentile")]
+    p75: f64,
+    #[serde(rename = "95thPercentile")]
+    p95: f64,
+    #[serde(rename = "99thPercentile")]
+    p99: f64,
+    #[serde(rename = "Max")]
+    max: f64,
+    #[serde(rename = "Mean")]
+    mean: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AsyncExecutorAttributes {
+    active_threads: i32,
+    queue_size: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AsyncExecutorConfigAttributes {
+    max_threads: i32,
+    max_queue_size: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MemoryAttributes {
+    heap_memory_usage: MemoryUsage,
+    non_heap_memory_usage: MemoryUsage,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GarbageCollectorAttributes {
+    collection_count: i64,
+    collection_time: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ThreadingAttributes {
+    thread_count: i32,
+    daemon_thread_count: i32,
+    peak_thread_count: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ClassLoadingAttributes {
+    loaded_class_count: i32,
+    unloaded_class_count: i64,
+}
+
+impl JolokiaClient {
+    pub fn new(settings: &JMXConnectionSettings) -> JolokiaClient {
+        JolokiaClient {
+            url: settings.address.clone(),
+            username: settings.username.clone(),
+            password: settings.password.clone(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    // cpu loads are only available on HotSpot-like jvms, and are negative until the first measurement
+    async fn get_cpu_load(&self, attribute: &str) -> Option<f64> {
+        self.request::<f64>(json!({"type": "read", "mbean": "java.lang:type=OperatingSystem", "attribute": attribute})).await
+            .ok()
+            .filter(|x| *x >= 0.0)
+    }
+
+    async fn read<T: DeserializeOwned>(&self, mbean: &str, attributes: &[&str]) -> Result<T, String> {
+        self.request(json!({"type": "read", "mbean": mbean, "attribute": attributes})).await
+    }
+
+    async fn execute<T: DeserializeOwned>(&self, mbean: &str, operation: &str, arguments: Value) -> Result<T, String> {
+        self.request(json!({"type": "exec", "mbean": mbean, "operation": operation, "arguments": arguments})).await
+    }
+
+    /// Sends a single request and returns its value. Jolokia reports most errors in the response body.
+    async fn request<T: DeserializeOwned>(&self, body: Value) -> Result<T, String> {
+        let mut request = self.http.post(&self.url).json(&body);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Request to {} failed with status: {}", self.url, response.status()));
+        }
+        let response: JolokiaResponse = response.json().await.map_err(|e| e.to_string())?;
+        if response.status != 200 {
+            let status = response.status;
+            return Err(response.error.unwrap_or_else(|| format!("Jolokia responded with status {}", status)));
+        }
+        serde_json::from_value(response.value).map_err(|e| format!("Unexpected Jolokia response: {}", e))
+    }
+}
+
+#[async_trait]
+impl JmxSource for JolokiaClient {
+    async fn get_hikari_metrics(&self, db_pool_name: &str) -> Result<HikariMetrics, String> {
+        let pool: HikariPoolAttributes = self.read(
+            &format!("com.zaxxer.hikari:type=Pool ({})", db_pool_name),
+            &["TotalConnections", "ActiveConnections", "IdleConnections", "ThreadsAwaitingConnection"],
+        ).await?;
+
+        Ok(HikariMetrics {
+            total: pool.total_connections,
+            active: pool.active_connections,
+            idle: pool.idle_connections,
+            waiting: pool.threads_awaiting_connection,
+        })
+    }
+
+    async fn get_hikari_config(&self, db_pool_name: &str) -> Result<HikariConfig, String> {
+        let config: HikariPoolConfigAttributes = self.read(
+            &format!("com.zaxxer.hikari:type=PoolConfig ({})", db_pool_name),
+            &["MaximumPoolSize", "MinimumIdle", "ConnectionTimeout", "LeakDetectionThreshold"],
+        ).await?;
+
+        Ok(HikariConfig {
+            maximum_pool_size: config.maximum_pool_size,
+            minimum_idle: config.minimum_idle,
+            connection_timeout: config.connection_timeout,
+            leak_detection_threshold: config.leak_detection_threshold,
+        })
+    }
+
+    async fn get_percentiles(&self, mbean: &str) -> Result<Percentiles, String> {
+        let p: PercentilesAttributes = self.read(mbean, &Percentiles::ATTRIBUTES).await?;
+        Ok(Percentiles { p50: p.p50, p75: p.p75, p95: p.p95, p99: p.p99, max: p.max, mean: p.mean })
+    }
+
+    async fn get_slick_metrics(&self, db_pool_name: &str) -> Result<SlickMetrics, String> {
+        let executor: AsyncExecutorAttributes = self.read(
+            &format!("slick:type=AsyncExecutor,name={}", db_pool_name),
+            &["ActiveThreads", "QueueSize"],
+        ).await?;
+
+        Ok(SlickMetrics { active_threads: executor.active_threads, queue_size: executor.queue_size })
+    }
+
+    async fn get_slick_config(&self, db_pool_name: &str) -> Result<SlickConfig, String> {
+        let executor: AsyncExecutorConfigAttributes = self.read(
+            &format!("slick:type=AsyncExecutor,name={}", db_pool_name),
+            &["MaxThreads", "MaxQueueSize"],
+        ).await?;
+
+        Ok(SlickConfig { max_threads: executor.max_threads, max_queue_size: executor.max_queue_size })
+    }
+
+    async fn get_jvm_metrics(&self) -> Result<JvmMetrics, String> {
+        let memory: MemoryAttributes = self.read("java.lang:type=Memory", &["HeapMemoryUsage", "NonHeapMemoryUsage"]).await?;
+        // a pattern read responds with attributes of every matching MBean, by name
+        let collectors: HashMap<String, GarbageCollectorAttributes> = self
+            .read("java.lang:type=GarbageCollector,*", &["CollectionCount", "CollectionTime"]).await?;
+        let threading: ThreadingAttributes = self
+            .read("java.lang:type=Threading", &["ThreadCount", "DaemonThreadCount", "PeakThreadCount"]).await?;
+        let class_loading: ClassLoadingAttributes = self
+            .read("java.lang:type=ClassLoading", &["LoadedClassCount", "UnloadedClassCount"]).await?;
+
+        let mut garbage_collectors: Vec<GarbageCollectorMetrics> = collectors.into_iter()
+            .map(|(name, gc)| GarbageCollectorMetrics {
+                name: name_property(&name).unwrap_or(&name).to_owned(),
+                collection_count: gc.collection_count,
+                collection_time: gc.collection_time,
+            })
+            .collect();
+        garbage_collectors.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(JvmMetrics {
+            heap: memory.heap_memory_usage,
+            non_heap: memory.non_heap_memory_usage,
+            garbage_collectors,
+            live_threads: threading.thread_count,
+            daemon_threads: threading.daemon_thread_count,
+            peak_threads: threading.peak_thread_count,
+            loaded_classes: class_loading.loaded_class_count,
+            unloaded_classes: class_loading.unloaded_class_count,
+            process_cpu_load: self.get_cpu_load("ProcessCpuLoad").await,
+            system_cpu_load: self.get_cpu_load("SystemCpuLoad").await,
+        })
+    }
+
+    async fn get_thread_dump(&self) -> Result<ThreadDump, String> {
+        let threads = self.execute("java.lang:type=Threading", "dumpAllThreads(boolean,boolean)", json!([true, true])).await?;
+        // null when there are no deadlocks
+        let deadlocked: Option<Vec<i64>> = self.execute("java.lang:type=Threading", "findDeadlockedThreads", json!([])).await?;
+        Ok(ThreadDump { threads, deadlocked: deadlocked.unwrap_or_default() })
+    }
+
+    async fn get_mbean_names(&self) -> Result<Vec<String>, String> {
+        let mut names: Vec<String> = self.request(json!({"type": "search", "mbean": "*:*"})).await?;
+        names.sort();
+        Ok(names)
+    }
+
+    async fn query_names(&self, pattern: &str) -> Result<Vec<String>, String> {
+        self.request(json!({"type": "search", "mbean": pattern})).await
+    }
+
+    /// Reads all attributes of an MBean. Attributes that can't be read are kept, with the error as their value.
+    async fn get_mbean(&self, name: &str) -> Result<MBean, String> {
+        let (domain, properties) = name.split_at(name.find(':').ok_or(format!("Invalid MBean name: {}", name))?);
+        let domain_info: Value = self.request(json!({"type": "list", "path": escape_path(domain)})).await?;
+        // listed MBeans are keyed by their property lists, which may be ordered differently
+        let info = domain_info.as_object()
+            .and_then(|mbeans| mbeans.iter().find(|(k, _)| same_properties(k, &properties[1..])))
+            .map(|(_, info)| info)
+            .ok_or(format!("MBean {} not found", name))?;
+
+        let values: Value = self.request(json!({"type": "read", "mbean": name, "config": {"ignoreErrors": true}})).await?;
+        let mut attributes: Vec<MBeanAttributeValue> = info["attr"].as_object().into_iter().flatten()
+            .map(|(attribute, a)| {
+                let (value, numeric) = match &values[attribute] {
+                    Value::String(s) => (s.clone(), None),
+                    Value::Null => ("<unavailable>".to_owned(), None),
+                    v => (v.to_string(), v.as_f64()),
+                };
+                MBeanAttributeValue { name: attribute.clone(), type_name: a["type"].as_str().unwrap_or("").to_owned(), value, numeric }
+            })
+            .collect();
+        attributes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(MBean {
+            name: name.to_owned(),
+            class_name: info["class"].as_str().unwrap_or("").to_owned(),
+            attributes,
+        })
+    }
+
+    async fn get_numeric_attribute(&self, attribute: &MBeanAttributeRef) -> Result<Option<f64>, String> {
+        let value: Value = self.request(json!({"type": "read", "mbean": attribute.mbean, "attribute": attribute.attribute})).await?;
+        Ok(value.as_f64())
+    }
+}
+
+fn same_properties(a: &str, b: &str) -> bool {
+    let mut a: Vec<&str> = a.split(',').collect();
+    let mut b: Vec<&str> = b.split(',').collect();
+    a.sort_unstable();
+    b.sort_unstable();
+    a == b
+}
+
+/// Escapes a part of a Jolokia path, in which `/` separates parts.
+fn escape_path(s: &str) -> String {
+    s.replace('!', "!!").replace('/', "!/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use hyper::{Body, Request, Response, Server};
+    use hyper::service::{make_service_fn, service_fn};
+    use serde_json::{json, Value};
+
+    use crate::jmx::jolokia::JolokiaClient;
+    use crate::jmx::model::{GarbageCollectorMetrics, JMXConnectionSettings, MBeanAttributeValue, MemoryUsage, ThreadState};
+    use crate::jmx::source::JmxSource;
+
+    /// Starts a Jolokia stub, responding to requests for known MBeans with canned values.
+    async fn start_stub() -> SocketAddr {
+        async fn handle(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let target = request["mbean"].as_str().or_else(|| request["path"].as_str());
+            let response = match (request["type"].as_str().unwrap(), target, request["operation"].as_str()) {
+                ("read", Some("com.zaxxer.hikari:type=Pool (orders)"), _) => json!({"status": 200, "value": {
+                    "TotalConnections": 10, "ActiveConnections": 3, "IdleConnections": 7, "ThreadsAwaitingConnection": 1
+                }}),
+                ("read", Some("com.zaxxer.hikari:type=PoolConfig (orders)"), _) => json!({"status": 200, "value": {
+                    "MaximumPoolSize": 10, "MinimumIdle": 2, "ConnectionTimeout": 30000, "LeakDetectionThreshold": 0
+                }}),
+                ("read", Some("metrics:name=orders.pool.Wait"), _) => json!({"status": 200, "value": {
+                    "50thPercentile": 0.5, "75thPercentile": 1.0, "95thPercentile": 4.2, "99thPercentile": 12.0, "Max": 30.1, "Mean": 1.1
+                }}),
+                ("read", Some("slick:type=AsyncExecutor,name=orders"), _) => json!({"status": 200, "value": {"ActiveThreads": 4, "QueueSize": 12}}),
+                ("read", Some("java.lang:type=Memory"), _) => json!({"status": 200, "value": {
+                    "HeapMemoryUsage": {"init": 0, "used": 100, "committed": 200, "max": 400},
+                    "NonHeapMemoryUsage": {"init": 0, "used": 50, "committed": 60, "max": -1}
+                }}),
+                ("read", Some("java.lang:type=GarbageCollector,*"), _) => json!({"status": 200, "value": {
+                    "java.lang:name=G1 Young Generation,type=GarbageCollector": {"CollectionCount": 12, "CollectionTime": 150},
+                    "java.lang:name=G1 Old Generation,type=GarbageCollector": {"CollectionCount": 1, "CollectionTime": 80}
+                }}),
+                ("read", Some("java.lang:type=Threading"), _) => json!({"status": 200, "value": {
+                    "ThreadCount": 20, "DaemonThreadCount": 15, "PeakThreadCount": 22
+                }}),
+                ("read", Some("java.lang:type=ClassLoading"), _) => json!({"status": 200, "value": {
+                    "LoadedClassCount": 9000, "UnloadedClassCount": 3
+                }}),
+                ("read", Some("java.lang:type=OperatingSystem"), _) => match request["attribute"].as_str() {
+                    Some("ProcessCpuLoad") => json!({"status": 200, "value": 0.25}),
+                    // not measured yet
+                    _ => json!({"status": 200, "value": -1.0}),
+                },
+                ("exec", Some("java.lang:type=Threading"), Some("dumpAllThreads(boolean,boolean)")) => json!({"status": 200, "value": [{
+                    "threadId": 7,
+                    "threadName": "orders-worker-1",
+                    "threadState": "BLOCKED",
+                    "lockName": "java.lang.Object@1b2c3d",
+                    "lockOwnerName": "orders-worker-2",
+                    "stackTrace": [{"className": "Worker", "methodName": "run", "fileName": "Worker.java", "lineNumber": 12, "nativeMethod": false}]
+                }]}),
+                ("exec", Some("java.lang:type=Threading"), Some("findDeadlockedThreads")) => json!({"status": 200, "value": null}),
+                // slashes in the domain have to be escaped, or they'd split the path
+                ("list", Some("com.acme!/orders"), _) => json!({"status": 200, "value": {
+                    "type=Cache,name=orders": {"class": "com.acme.OrdersCache", "attr": {
+                        "Size": {"type": "int"},
+                        "Name": {"type": "java.lang.String"},
+                        "Stats": {"type": "javax.management.openmbean.CompositeData"}
+                    }}
+                }}),
+                ("read", Some("com.acme/orders:name=orders,type=Cache"), _) => json!({"status": 200, "value": {
+                    "Size": 12, "Name": "orders", "Stats": null
+                }}),
+                _ => json!({"status": 404, "error": "javax.management.InstanceNotFoundException : no such MBean"}),
+            };
+            Ok(Response::new(Body::from(response.to_string())))
+        }
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into())
+            .serve(make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) }));
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    fn client(addr: SocketAddr) -> JolokiaClient {
+        JolokiaClient::new(&JMXConnectionSettings { jolokia: true, ..JMXConnectionSettings::for_test(&format!("http://{}/jolokia", addr)) })
+    }
+
+    #[tokio::test]
+    async fn jolokia_client_reads_pool_metrics() {
+        let jolokia = client(start_stub().await);
+
+        let hikari = jolokia.get_hikari_metrics("orders").await.unwrap();
+        assert_eq!((hikari.total, hikari.active, hikari.idle, hikari.waiting), (10, 3, 7, 1));
+
+        let config = jolokia.get_hikari_config("orders").await.unwrap();
+        assert_eq!((config.maximum_pool_size, config.minimum_idle, config.connection_timeout), (10, 2, 30000));
+
+        let wait = jolokia.get_percentiles("metrics:name=orders.pool.Wait").await.unwrap();
+        assert_eq!((wait.p50, wait.p99, wait.max), (0.5, 12.0, 30.1));
+
+        let slick = jolokia.get_slick_metrics("orders").await.unwrap();
+        assert_eq!((slick.active_threads, slick.queue_size), (4, 12));
+
+        assert_eq!(
+            jolokia.get_slick_metrics("payments").await.err(),
+            Some("javax.management.InstanceNotFoundException : no such MBean".to_owned())
+        );
+        assert!(jolokia.get_hikari_metrics("payments").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn jolokia_client_reads_jvm_metrics() {
+        let jolokia = client(start_stub().await);
+
+        let jvm = jolokia.get_jvm_metrics().await.unwrap();
+        assert_eq!(jvm.heap, MemoryUsage { used: 100, committed: 200, max: 400 });
+        assert_eq!(jvm.non_heap, MemoryUsage { used: 50, committed: 60, max: -1 });
+        // collectors are named after the name property of their MBean
+        assert_eq!(jvm.garbage_collectors, vec![
+            GarbageCollectorMetrics { name: "G1 Old Generation".to_owned(), collection_count: 1, collection_time: 80 },
+            GarbageCollectorMetrics { name: "G1 Young Generation".to_owned(), collection_count: 12, collection_time: 150 },
+        ]);
+        assert_eq!((jvm.live_threads, jvm.daemon_threads, jvm.peak_threads), (20, 15, 22));
+        assert_eq!((jvm.loaded_classes, jvm.unloaded_classes), (9000, 3));
+        assert_eq!((jvm.process_cpu_load, jvm.system_cpu_load), (Some(0.25), None));
+    }
+
+    #[tokio::test]
+    async fn jolokia_client_takes_thread_dump() {
+        let jolokia = client(start_stub().await);
+
+        let dump = jolokia.get_thread_dump().await.unwrap();
+        assert!(dump.deadlocked.is_empty());
+        assert_eq!(dump.threads.len(), 1);
+        let thread = &dump.threads[0];
+        assert_eq!((thread.thread_id, thread.thread_name.as_str(), thread.thread_state), (7, "orders-worker-1", ThreadState::Blocked));
+        assert_eq!(thread.lock_owner_name.as_deref(), Some("orders-worker-2"));
+        assert_eq!(thread.stack_trace[0].to_string(), "Worker.run(Worker.java:12)");
+    }
+
+    #[tokio::test]
+    async fn jolokia_client_reads_mbean_with_escaped_domain() {
+        let jolokia = client(start_stub().await);
+
+        // properties are listed in another order than in the name
+        let mbean = jolokia.get_mbean("com.acme/orders:name=orders,type=Cache").await.unwrap();
+        assert_eq!(mbean.class_name, "com.acme.OrdersCache");
+        let attribute = |name: &str, type_name: &str, value: &str, numeric: Option<f64>| MBeanAttributeValue {
+            name: name.to_owned(), type_name: type_name.to_owned(), value: value.to_owned(), numeric,
+        };
+        assert_eq!(mbean.attributes, vec![
+            attribute("Name", "java.lang.String", "orders", None),
+            attribute("Size", "int", "12", Some(12.0)),
+            attribute("Stats", "javax.management.openmbean.CompositeData", "<unavailable>", None),
+        ]);
+
+        assert_eq!(
+            jolokia.get_mbean("com.acme/orders:type=Cache,name=payments").await.err(),
+            Some("MBean com.acme/orders:type=Cache,name=payments not found".to_owned())
+        );
+    }
+}
@@ -1,2 +1,6 @@
 pub mod model;
 pub mod client;
+pub mod connection;
+pub mod platform;
+pub mod jolokia;
+pub mod source;
@@ -1,26 +1,434 @@
-#[derive(Clone)]
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Credentials are never serialized, so that they don't end up in session recordings.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct JMXConnectionSettings {
+    /// Either `host:port` of an RMI registry or a full service URL, or the URL of a Jolokia agent.
     pub address: String,
+    /// Whether `address` points to a Jolokia agent, which is read over HTTP instead of RMI.
+    #[serde(default)]
+    pub jolokia: bool,
+    #[serde(skip)]
     pub username: Option<String>,
+    #[serde(skip)]
     pub password: Option<String>,
-    pub db_pool_name: String,
+    /// Whether the RMI registry is protected with SSL.
+    #[serde(default)]
+    pub ssl: bool,
+    #[serde(default)]
+    pub truststore: Option<String>,
+    #[serde(skip)]
+    pub truststore_password: Option<String>,
+    /// Pools of Slick and HikariCP MBeans. Without them only JVM metrics and the MBean browser are available.
+    pub db_pools: Option<DbPools>,
+}
+
+/// Database connection pools to monitor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DbPools {
+    /// Pools with given names, which qualify their MBeans.
+    Named(Vec<String>),
+    /// All pools with registered Slick or HikariCP MBeans.
+    Discovered,
+}
+
+/// Kind of MBean that a database connection pool is monitored with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DbPoolKind {
+    Slick,
+    Hikari,
+}
+
+impl DbPoolKind {
+    /// ObjectName pattern matching MBeans of all pools of this kind.
+    pub fn pattern(self) -> &'static str {
+        match self {
+            DbPoolKind::Slick => "slick:type=AsyncExecutor,*",
+            DbPoolKind::Hikari => "com.zaxxer.hikari:type=Pool (*)",
+        }
+    }
+
+    /// Name of the pool an MBean belongs to, e.g. `orders` for `com.zaxxer.hikari:type=Pool (orders)`.
+    pub fn pool_name(self, object_name: &str) -> Option<&str> {
+        match self {
+            DbPoolKind::Slick => name_property(object_name),
+            DbPoolKind::Hikari => object_name.strip_prefix("com.zaxxer.hikari:type=Pool (")?.strip_suffix(')'),
+        }
+    }
 }
 
-#[derive(Clone)]
+impl JMXConnectionSettings {
+    /// Plain jmx connection to `address`, without credentials, SSL or db pools.
+    #[cfg(test)]
+    pub fn for_test(address: &str) -> JMXConnectionSettings {
+        JMXConnectionSettings {
+            address: address.to_owned(),
+            jolokia: false,
+            username: None,
+            password: None,
+            ssl: false,
+            truststore: None,
+            truststore_password: None,
+            db_pools: None,
+        }
+    }
+
+    /// `address` if it's a service URL already, otherwise the default RMI URL for `host:port`.
+    pub fn service_url(&self) -> String {
+        if self.address.starts_with("service:jmx:") {
+            self.address.clone()
+        } else {
+            format!("service:jmx:rmi://{}/jndi/rmi://{}/jmxrmi", self.address, self.address)
+        }
+    }
+}
+
+/// Value of the `name` key of an ObjectName, e.g. `G1 Young Generation`.
+pub fn name_property(object_name: &str) -> Option<&str> {
+    let properties = &object_name[object_name.find(':')? + 1..];
+    properties.split(',').find_map(|p| p.strip_prefix("name="))
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SlickMetrics {
     pub active_threads: i32,
     pub queue_size: i32,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SlickConfig {
     pub max_threads: i32,
     pub max_queue_size: i32,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct HikariMetrics {
     pub total: i32,
     pub active: i32,
     pub idle: i32,
     pub waiting: i32,
 }
+
+/// Settings of a HikariCP pool, from its `HikariConfigMXBean`. Timeouts are in milliseconds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HikariConfig {
+    pub maximum_pool_size: i32,
+    pub minimum_idle: i32,
+    pub connection_timeout: i64,
+    /// 0 when leak detection is disabled.
+    pub leak_detection_threshold: i64,
+}
+
+/// Timings of a HikariCP pool in milliseconds, published when the pool has a Dropwizard or Micrometer registry
+/// that's reported over JMX. Each of them may be missing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HikariTimings {
+    /// Time it takes to acquire a connection from the pool.
+    pub wait: Option<Percentiles>,
+    /// Time connections are used for before being returned to the pool.
+    pub usage: Option<Percentiles>,
+    /// Time it takes to create a new connection.
+    pub creation: Option<Percentiles>,
+}
+
+/// Distribution of a timer or histogram MBean of a Dropwizard `JmxReporter`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p75: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Percentiles {
+    /// Attributes of the MBean, in the order of fields.
+    pub const ATTRIBUTES: [&'static str; 6] = ["50thPercentile", "75thPercentile", "95thPercentile", "99thPercentile", "Max", "Mean"];
+}
+
+/// Kind of HikariCP timing metric.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HikariTiming {
+    Wait,
+    Usage,
+    Creation,
+}
+
+impl HikariTiming {
+    /// Domain of MBeans registered by a `JmxReporter` with default settings.
+    pub const METRICS_DOMAIN: &'static str = "metrics";
+
+    /// Metric names of a pool, as registered by HikariCP with Dropwizard and with Micrometer.
+    pub fn metric_names(self, db_pool_name: &str) -> [String; 2] {
+        match self {
+            HikariTiming::Wait => [format!("{}.pool.Wait", db_pool_name), format!("hikaricpConnectionsAcquire.pool.{}", db_pool_name)],
+            HikariTiming::Usage => [format!("{}.pool.Usage", db_pool_name), format!("hikaricpConnectionsUsage.pool.{}", db_pool_name)],
+            HikariTiming::Creation => [format!("{}.pool.ConnectionCreation", db_pool_name), format!("hikaricpConnectionsCreation.pool.{}", db_pool_name)],
+        }
+    }
+
+    /// Name of the MBean with this timing of a pool, among names in the metrics domain.
+    pub fn find_mbean<'a>(self, db_pool_name: &str, names: &'a [String]) -> Option<&'a str> {
+        let metric_names = self.metric_names(db_pool_name);
+        names.iter()
+            .find(|n| name_property(n).is_some_and(|name| metric_names.iter().any(|m| m == name)))
+            .map(|n| n.as_str())
+    }
+}
+
+/// Value of a `java.lang.management.MemoryUsage` attribute, in bytes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    pub used: i64,
+    pub committed: i64,
+    /// -1 if the maximum is undefined.
+    pub max: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GarbageCollectorMetrics {
+    pub name: String,
+    pub collection_count: i64,
+    /// Accumulated collection time, in ms.
+    pub collection_time: i64,
+}
+
+/// Runtime metrics every jvm exposes under the `java.lang` domain.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JvmMetrics {
+    pub heap: MemoryUsage,
+    pub non_heap: MemoryUsage,
+    pub garbage_collectors: Vec<GarbageCollectorMetrics>,
+    pub live_threads: i32,
+    pub daemon_threads: i32,
+    pub peak_threads: i32,
+    pub loaded_classes: i32,
+    pub unloaded_classes: i64,
+    /// Recent cpu usage of the jvm process, between 0 and 1. `None` if the jvm doesn't report it.
+    pub process_cpu_load: Option<f64>,
+    /// Recent cpu usage of the whole system, between 0 and 1.
+    pub system_cpu_load: Option<f64>,
+}
+
+/// Result of `ThreadMXBean.dumpAllThreads`, along with ids of deadlocked threads.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThreadDump {
+    pub threads: Vec<ThreadInfo>,
+    pub deadlocked: Vec<i64>,
+}
+
+impl ThreadDump {
+    pub fn is_deadlocked(&self, thread: &ThreadInfo) -> bool {
+        self.deadlocked.contains(&thread.thread_id)
+    }
+}
+
+/// A `java.lang.management.ThreadInfo`, as converted by j4rs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadInfo {
+    pub thread_id: i64,
+    pub thread_name: String,
+    pub thread_state: ThreadState,
+    /// Only reported since java 9.
+    #[serde(default)]
+    pub daemon: bool,
+    pub lock_name: Option<String>,
+    pub lock_owner_name: Option<String>,
+    pub stack_trace: Vec<StackFrame>,
+    #[serde(default)]
+    pub locked_monitors: Vec<LockedMonitor>,
+}
+
+impl ThreadInfo {
+    /// Formats the thread similarly to `jstack`.
+    pub fn dump(&self, deadlocked: bool) -> String {
+        let mut s = format!("\"{}\" #{}{} {}", self.thread_name, self.thread_id, if self.daemon { " daemon" } else { "" }, self.thread_state);
+        if let Some(lock) = &self.lock_name {
+            s.push_str(&format!(" on {}", lock));
+        }
+        if let Some(owner) = &self.lock_owner_name {
+            s.push_str(&format!(" owned by \"{}\"", owner));
+        }
+        if deadlocked {
+            s.push_str(" (deadlocked)");
+        }
+        for (depth, frame) in self.stack_trace.iter().enumerate() {
+            s.push_str(&format!("\n    at {}", frame));
+            if depth == 0 {
+                if let Some(lock) = &self.lock_name {
+                    let action = if self.thread_state == ThreadState::Blocked { "waiting to lock" } else { "waiting on" };
+                    s.push_str(&format!("\n    - {} {}", action, lock));
+                }
+            }
+            for m in self.locked_monitors.iter().filter(|m| m.locked_stack_depth == depth as i32) {
+                s.push_str(&format!("\n    - locked {}@{:x}", m.class_name, m.identity_hash_code));
+            }
+        }
+        s
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ThreadState {
+    New,
+    Runnable,
+    Blocked,
+    Waiting,
+    TimedWaiting,
+    Terminated,
+}
+
+impl fmt::Display for ThreadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ThreadState::New => "NEW",
+            ThreadState::Runnable => "RUNNABLE",
+            ThreadState::Blocked => "BLOCKED",
+            ThreadState::Waiting => "WAITING",
+            ThreadState::TimedWaiting => "TIMED_WAITING",
+            ThreadState::Terminated => "TERMINATED",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A `java.lang.StackTraceElement`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StackFrame {
+    pub class_name: String,
+    pub method_name: String,
+    pub file_name: Option<String>,
+    /// Negative if unknown.
+    pub line_number: i32,
+    pub native_method: bool,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.class_name, self.method_name)?;
+        match (&self.file_name, self.native_method) {
+            (_, true) => write!(f, "(Native Method)"),
+            (Some(file), _) if self.line_number >= 0 => write!(f, "({}:{})", file, self.line_number),
+            (Some(file), _) => write!(f, "({})", file),
+            (None, _) => write!(f, "(Unknown Source)"),
+        }
+    }
+}
+
+/// A monitor held by a thread, locked at the given depth of its stack.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockedMonitor {
+    pub class_name: String,
+    pub identity_hash_code: i32,
+    pub locked_stack_depth: i32,
+}
+
+/// An attribute of a specific MBean, e.g. `java.lang:type=Memory` / `ObjectPendingFinalizationCount`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MBeanAttributeRef {
+    pub mbean: String,
+    pub attribute: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MBeanAttributeValue {
+    pub name: String,
+    pub type_name: String,
+    /// Printable value, or the reason it couldn't be read.
+    pub value: String,
+    /// Value as a number, if the attribute is numeric and can be charted.
+    pub numeric: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MBean {
+    pub name: String,
+    pub class_name: String,
+    pub attributes: Vec<MBeanAttributeValue>,
+}
+
+/// A row of the MBean browser tree: either a domain or an MBean within a domain.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MBeanTreeNode {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub label: String,
+    /// Full ObjectName, `None` for domain nodes.
+    pub name: Option<String>,
+}
+
+impl MBeanTreeNode {
+    /// Groups ObjectNames by domain, e.g. `java.lang:type=Memory` becomes `type=Memory` under `java.lang`.
+    pub fn from_names(names: &[String]) -> Vec<MBeanTreeNode> {
+        let mut nodes: Vec<MBeanTreeNode> = vec![];
+        let mut domains: Vec<(String, usize)> = vec![];
+        for name in names {
+            let (domain, properties) = match name.find(':') {
+                Some(i) => (&name[..i], &name[i + 1..]),
+                None => (name.as_str(), ""),
+            };
+            let domain_id = match domains.iter().find(|(d, _)| d == domain) {
+                Some((_, id)) => *id,
+                None => {
+                    let id = nodes.len();
+                    nodes.push(MBeanTreeNode { id, parent: None, label: domain.to_owned(), name: None });
+                    domains.push((domain.to_owned(), id));
+                    id
+                }
+            };
+            let id = nodes.len();
+            nodes.push(MBeanTreeNode { id, parent: Some(domain_id), label: properties.to_owned(), name: Some(name.clone()) });
+        }
+        nodes
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::jmx::model::{DbPoolKind, HikariTiming, JMXConnectionSettings};
+
+    #[test]
+    fn service_url_is_built_from_address() {
+        assert_eq!(
+            JMXConnectionSettings::for_test("localhost:9010").service_url(),
+            "service:jmx:rmi://localhost:9010/jndi/rmi://localhost:9010/jmxrmi"
+        );
+        assert_eq!(
+            JMXConnectionSettings::for_test("service:jmx:jmxmp://orders:9875").service_url(),
+            "service:jmx:jmxmp://orders:9875"
+        );
+    }
+
+    #[test]
+    fn db_pool_names_are_read_from_mbean_names() {
+        assert_eq!(DbPoolKind::Slick.pool_name("slick:type=AsyncExecutor,name=orders-read"), Some("orders-read"));
+        assert_eq!(DbPoolKind::Slick.pool_name("slick:name=orders-read,type=AsyncExecutor"), Some("orders-read"));
+        assert_eq!(DbPoolKind::Hikari.pool_name("com.zaxxer.hikari:type=Pool (orders-write)"), Some("orders-write"));
+        assert_eq!(DbPoolKind::Hikari.pool_name("com.zaxxer.hikari:type=PoolConfig (orders-write)"), None);
+    }
+
+    #[test]
+    fn hikari_timing_mbeans_are_found_by_metric_name() {
+        let names = vec![
+            "metrics:name=orders.pool.Wait".to_owned(),
+            "metrics:name=orders-read.pool.Usage,type=histograms".to_owned(),
+            "metrics:name=hikaricpConnectionsCreation.pool.orders-read,type=timers".to_owned(),
+        ];
+
+        assert_eq!(HikariTiming::Wait.find_mbean("orders", &names), Some("metrics:name=orders.pool.Wait"));
+        assert_eq!(HikariTiming::Wait.find_mbean("orders-read", &names), None);
+        assert_eq!(HikariTiming::Usage.find_mbean("orders-read", &names), Some("metrics:name=orders-read.pool.Usage,type=histograms"));
+        assert_eq!(
+            HikariTiming::Creation.find_mbean("orders-read", &names),
+            Some("metrics:name=hikaricpConnectionsCreation.pool.orders-read,type=timers")
+        );
+    }
+}
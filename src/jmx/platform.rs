@@ -0,0 +1,60 @@
+use std::convert::TryFrom;
+
+use j4rs::errors::J4RsError;
+use j4rs::{Instance, InvocationArg, Jvm};
+
+use crate::jmx::connection::MBeanConnection;
+use crate::jmx::model::{MemoryUsage, ThreadDump};
+
+///
+/// Proxies of `java.lang.management` MXBeans.
+///
+/// Plain attribute reads lose the contents of `CompositeData` values (e.g. `HeapMemoryUsage`)
+/// on their way to rust, while proxies return typed beans, which are converted as expected.
+///
+/// A proxy that couldn't be created only fails the reads that need it, e.g. a jvm without
+/// a `ThreadMXBean` still has memory metrics.
+///
+pub struct PlatformMXBeans {
+    memory: Result<Instance, String>,
+    threading: Result<Instance, String>,
+}
+
+impl PlatformMXBeans {
+    pub fn new(connection: &MBeanConnection) -> PlatformMXBeans {
+        let proxy = |name: &str, interface: &str| connection.platform_proxy(name, interface)
+            .map_err(|e| format!("{} isn't available: {}", name, e));
+        PlatformMXBeans {
+            memory: proxy("java.lang:type=Memory", "java.lang.management.MemoryMXBean"),
+            threading: proxy("java.lang:type=Threading", "java.lang.management.ThreadMXBean"),
+        }
+    }
+
+    /// Heap and non-heap memory usage.
+    pub fn memory_usage(&self) -> Result<(MemoryUsage, MemoryUsage), jmx::Error> {
+        let memory = available(&self.memory)?;
+        let jvm = Jvm::attach_thread()?;
+        let heap = jvm.invoke(memory, "getHeapMemoryUsage", &[])?;
+        let non_heap = jvm.invoke(memory, "getNonHeapMemoryUsage", &[])?;
+        Ok((jvm.to_rust(heap)?, jvm.to_rust(non_heap)?))
+    }
+
+    /// Stack traces and held locks of all live threads, and which of them are deadlocked.
+    pub fn thread_dump(&self) -> Result<ThreadDump, jmx::Error> {
+        let threading = available(&self.threading)?;
+        let jvm = Jvm::attach_thread()?;
+        let locked_monitors = InvocationArg::try_from(true)?.into_primitive()?;
+        let locked_synchronizers = InvocationArg::try_from(true)?.into_primitive()?;
+        let threads = jvm.invoke(threading, "dumpAllThreads", &[locked_monitors, locked_synchronizers])?;
+        // null when there are no deadlocks
+        let deadlocked = jvm.invoke(threading, "findDeadlockedThreads", &[])?;
+        Ok(ThreadDump {
+            threads: jvm.to_rust(threads)?,
+            deadlocked: jvm.to_rust::<Option<Vec<i64>>>(deadlocked)?.unwrap_or_default(),
+        })
+    }
+}
+
+fn available(proxy: &Result<Instance, String>) -> Result<&Instance, jmx::Error> {
+    proxy.as_ref().map_err(|e| J4RsError::GeneralError(e.clone()).into())
+}
@@ -0,0 +1,233 @@
+#[cfg(test)]
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::jmx::client::JMXClient;
+use crate::jmx::jolokia::JolokiaClient;
+use crate::jmx::model::*;
+
+/// A source of MBean data, e.g. a remote jvm over RMI or a Jolokia agent.
+#[async_trait]
+pub trait JmxSource: Send + Sync {
+    async fn get_hikari_metrics(&self, db_pool_name: &str) -> Result<HikariMetrics, String>;
+    async fn get_hikari_config(&self, db_pool_name: &str) -> Result<HikariConfig, String>;
+    /// Reads percentiles of a Dropwizard timer or histogram MBean, e.g. `metrics:name=orders.pool.Wait`.
+    async fn get_percentiles(&self, mbean: &str) -> Result<Percentiles, String>;
+    async fn get_slick_metrics(&self, db_pool_name: &str) -> Result<SlickMetrics, String>;
+    async fn get_slick_config(&self, db_pool_name: &str) -> Result<SlickConfig, String>;
+    async fn get_jvm_metrics(&self) -> Result<JvmMetrics, String>;
+    async fn get_thread_dump(&self) -> Result<ThreadDump, String>;
+    /// Names of all registered MBeans, sorted.
+    async fn get_mbean_names(&self) -> Result<Vec<String>, String>;
+    /// Names of MBeans matching a pattern, e.g. `slick:type=AsyncExecutor,*`.
+    async fn query_names(&self, pattern: &str) -> Result<Vec<String>, String>;
+    async fn get_mbean(&self, name: &str) -> Result<MBean, String>;
+    /// Reads a numeric attribute. Non-numeric values are reported as `None`.
+    async fn get_numeric_attribute(&self, attribute: &MBeanAttributeRef) -> Result<Option<f64>, String>;
+}
+
+/// Creates a source for connection settings. Connections are established lazily, on the first request.
+pub fn jmx_source(settings: &JMXConnectionSettings) -> Box<dyn JmxSource> {
+    if settings.jolokia {
+        Box::new(JolokiaClient::new(settings))
+    } else {
+        Box::new(RmiJmxSource::new(settings.clone()))
+    }
+}
+
+/// Jmx connection over RMI that is established on first use and re-established after it's lost.
+pub struct RmiJmxSource {
+    connection: Arc<LazyConnection>,
+}
+
+struct LazyConnection {
+    settings: JMXConnectionSettings,
+    client: Mutex<Option<Arc<JMXClient>>>,
+}
+
+impl LazyConnection {
+    /// Runs a call against the connection, connecting first if needed. Blocks the current thread.
+    fn call<T, F>(&self, f: F) -> Result<T, String>
+        where F: FnOnce(&JMXClient) -> Result<T, jmx::Error>, {
+        let client = {
+            let mut guard = self.client.lock().unwrap();
+            match guard.as_ref() {
+                Some(c) => c.clone(),
+                None => {
+                    let c = Arc::new(JMXClient::connect(&self.settings)?);
+                    *guard = Some(c.clone());
+                    c
+                }
+            }
+        };
+        f(&client).map_err(|e| {
+            // a failed call may mean either a missing bean or a dropped connection
+            if !client.is_alive() {
+                *self.client.lock().unwrap() = None;
+            }
+            e.to_string()
+        })
+    }
+}
+
+impl RmiJmxSource {
+    pub fn new(settings: JMXConnectionSettings) -> RmiJmxSource {
+        RmiJmxSource { connection: Arc::new(LazyConnection { settings, client: Mutex::new(None) }) }
+    }
+
+    /// Runs a blocking jmx call on a dedicated thread, so that it doesn't stall other sources.
+    async fn call<T, F>(&self, f: F) -> Result<T, String>
+        where T: Send + 'static,
+              F: FnOnce(&JMXClient) -> Result<T, jmx::Error> + Send + 'static, {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || connection.call(f))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+}
+
+#[async_trait]
+impl JmxSource for RmiJmxSource {
+    async fn get_hikari_metrics(&self, db_pool_name: &str) -> Result<HikariMetrics, String> {
+        let db_pool_name = db_pool_name.to_owned();
+        self.call(move |jmx| jmx.get_hikari_metrics(&db_pool_name)).await
+    }
+
+    async fn get_hikari_config(&self, db_pool_name: &str) -> Result<HikariConfig, String> {
+        let db_pool_name = db_pool_name.to_owned();
+        self.call(move |jmx| jmx.get_hikari_config(&db_pool_name)).await
+    }
+
+    async fn get_percentiles(&self, mbean: &str) -> Result<Percentiles, String> {
+        let mbean = mbean.to_owned();
+        self.call(move |jmx| jmx.get_percentiles(&mbean)).await
+    }
+
+    async fn get_slick_metrics(&self, db_pool_name: &str) -> Result<SlickMetrics, String> {
+        let db_pool_name = db_pool_name.to_owned();
+        self.call(move |jmx| jmx.get_slick_metrics(&db_pool_name)).await
+    }
+
+    async fn get_slick_config(&self, db_pool_name: &str) -> Result<SlickConfig, String> {
+        let db_pool_name = db_pool_name.to_owned();
+        self.call(move |jmx| jmx.get_slick_config(&db_pool_name)).await
+    }
+
+    async fn get_jvm_metrics(&self) -> Result<JvmMetrics, String> {
+        self.call(|jmx| jmx.get_jvm_metrics()).await
+    }
+
+    async fn get_thread_dump(&self) -> Result<ThreadDump, String> {
+        self.call(|jmx| jmx.get_thread_dump()).await
+    }
+
+    async fn get_mbean_names(&self) -> Result<Vec<String>, String> {
+        self.call(|jmx| jmx.get_mbean_names()).await
+    }
+
+    async fn query_names(&self, pattern: &str) -> Result<Vec<String>, String> {
+        let pattern = pattern.to_owned();
+        self.call(move |jmx| jmx.query_names(&pattern)).await
+    }
+
+    async fn get_mbean(&self, name: &str) -> Result<MBean, String> {
+        let name = name.to_owned();
+        self.call(move |jmx| jmx.get_mbean(&name)).await
+    }
+
+    async fn get_numeric_attribute(&self, attribute: &MBeanAttributeRef) -> Result<Option<f64>, String> {
+        let attribute = attribute.clone();
+        self.call(move |jmx| jmx.get_numeric_attribute(&attribute)).await
+    }
+}
+
+/// Responds with preset values. Everything that isn't set fails, as if the MBean wasn't registered.
+#[cfg(test)]
+pub struct StubJmxSource {
+    /// Metrics by pool name.
+    pub hikari: HashMap<String, HikariMetrics>,
+    pub hikari_config: HashMap<String, HikariConfig>,
+    pub slick: HashMap<String, SlickMetrics>,
+    pub slick_config: HashMap<String, SlickConfig>,
+    pub jvm: Result<JvmMetrics, String>,
+    pub mbean_names: Vec<String>,
+    pub attributes: HashMap<MBeanAttributeRef, f64>,
+    /// Percentiles by MBean name.
+    pub percentiles: HashMap<String, Percentiles>,
+}
+
+#[cfg(test)]
+impl StubJmxSource {
+    pub fn new() -> StubJmxSource {
+        StubJmxSource {
+            hikari: HashMap::new(),
+            hikari_config: HashMap::new(),
+            slick: HashMap::new(),
+            slick_config: HashMap::new(),
+            jvm: Err(StubJmxSource::not_found()),
+            mbean_names: vec![],
+            attributes: HashMap::new(),
+            percentiles: HashMap::new(),
+        }
+    }
+
+    fn not_found() -> String {
+        "javax.management.InstanceNotFoundException".to_owned()
+    }
+
+    fn get<T: Clone>(metrics: &HashMap<String, T>, key: &str) -> Result<T, String> {
+        metrics.get(key).cloned().ok_or_else(StubJmxSource::not_found)
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl JmxSource for StubJmxSource {
+    async fn get_hikari_metrics(&self, db_pool_name: &str) -> Result<HikariMetrics, String> {
+        StubJmxSource::get(&self.hikari, db_pool_name)
+    }
+
+    async fn get_hikari_config(&self, db_pool_name: &str) -> Result<HikariConfig, String> {
+        StubJmxSource::get(&self.hikari_config, db_pool_name)
+    }
+
+    async fn get_percentiles(&self, mbean: &str) -> Result<Percentiles, String> {
+        StubJmxSource::get(&self.percentiles, mbean)
+    }
+
+    async fn get_slick_metrics(&self, db_pool_name: &str) -> Result<SlickMetrics, String> {
+        StubJmxSource::get(&self.slick, db_pool_name)
+    }
+
+    async fn get_slick_config(&self, db_pool_name: &str) -> Result<SlickConfig, String> {
+        StubJmxSource::get(&self.slick_config, db_pool_name)
+    }
+
+    async fn get_jvm_metrics(&self) -> Result<JvmMetrics, String> {
+        self.jvm.clone()
+    }
+
+    async fn get_thread_dump(&self) -> Result<ThreadDump, String> {
+        Err(StubJmxSource::not_found())
+    }
+
+    async fn get_mbean_names(&self) -> Result<Vec<String>, String> {
+        Ok(self.mbean_names.clone())
+    }
+
+    /// Only domains of patterns are matched.
+    async fn query_names(&self, pattern: &str) -> Result<Vec<String>, String> {
+        let domain = &pattern[..=pattern.find(':').unwrap_or(0)];
+        Ok(self.mbean_names.iter().filter(|n| n.starts_with(domain)).cloned().collect())
+    }
+
+    async fn get_mbean(&self, _: &str) -> Result<MBean, String> {
+        Err(StubJmxSource::not_found())
+    }
+
+    async fn get_numeric_attribute(&self, attribute: &MBeanAttributeRef) -> Result<Option<f64>, String> {
+        self.attributes.get(attribute).map(|v| Some(*v)).ok_or_else(StubJmxSource::not_found)
+    }
+}
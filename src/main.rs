@@ -5,126 +5,430 @@ mod akka;
 mod app;
 mod fetcher;
 mod widgets;
+mod target;
+mod config;
+mod health;
+mod recording;
+mod exporter;
+mod snapshot;
+mod alerts;
 
 use std::{
+    collections::{HashMap, HashSet},
     env,
+    fs,
     io::{stdout, Write},
-    sync::mpsc,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, mpsc, Mutex},
     thread,
     time::{Duration, Instant},
 };
 
+use chrono::Local;
 use crossterm::{
     event::{self, Event as CEvent, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use structopt::clap::ArgMatches;
 use structopt::StructOpt;
 use tui::{
     backend::CrosstermBackend,
     Terminal,
 };
 
+use crate::alerts::{Alerts, AlertsConfig};
 use crate::app::{App, AppTabKind};
-use crate::fetcher::{Fetcher, FetcherRequest, FetcherResponse};
+use crate::fetcher::{FetcherRequest, FetcherResponse, Responder};
 
-use crate::akka::model::AkkaSettings;
-use crate::jmx::model::JMXConnectionSettings;
+use crate::config::{Config, Profile};
+use crate::exporter::Exporter;
+use crate::recording::{Player, Recorder, Recording};
+use crate::snapshot::SnapshotOptions;
+use crate::target::TargetSettings;
+use crate::zio::dump_parser::{self, FiberDumps};
+use crate::zio::model::FiberCount;
+
+const REPLAY_TICK_RATE: Duration = Duration::from_millis(100);
+const REPLAY_SEEK_STEP: Duration = Duration::from_secs(10);
 
 enum Event<I> {
     Input(I),
     Tick,
-    FetcherResponse(FetcherResponse),
+    FetcherResponse(String, FetcherResponse),
 }
 
 /// At least one of the following option sets has to be specified for panopticon-tui to launch:
 ///
 /// - zio-zmx
 ///
-/// - jmx + db-pool-name
+/// - jmx or jolokia (add db-pool-name or discover-db-pools for Slick and HikariCP metrics)
 ///
 /// - actor-tree + actor-system-status + dead-letters
+///
+/// To monitor several app instances at once, repeat the connection options.
+/// Options belong to the target they're given with: the next target starts
+/// where an option of the previous one is given again, e.g.
+/// --jmx orders-1:9010 --db-pool-name orders --jmx orders-2:9010
+/// applies the pool name to orders-1 only. --jmx-truststore is shared by all targets.
+///
+/// Connection options can also be stored as named profiles in a config file
+/// (see --config and --profile). Command line options override profile values.
+///
+/// A session can be written to a file with --record and viewed later with --replay,
+/// which needs no connection options.
+///
+/// With --serve-metrics panopticon runs without UI and exposes everything it collects
+/// as a Prometheus endpoint.
+///
+/// The snapshot subcommand fetches data once, prints it and exits, e.g.:
+/// panopticon-tui --zio-zmx localhost:6789 snapshot fibers --format json
 #[derive(Debug, StructOpt)]
 struct Cli {
     /// Frequency (in ms) to use for fetching metrics.
     /// Don't set this too low, because currently zmx tab does a full fiber dump every tick
     #[structopt(long = "tick-rate", default_value = "2000")]
     tick_rate: u64,
-    /// Address of zio-zmx server, e.g. localhost:6789
-    #[structopt(long = "zio-zmx")]
-    zio_zmx: Option<String>,
-    /// Address of remote jmx source, e.g. localhost:9010
-    #[structopt(long = "jmx")]
-    jmx: Option<String>,
-    /// Optional username for authorized jmx access
-    #[structopt(long = "jmx-username")]
-    jmx_username: Option<String>,
-    /// Optional password for authorized jmx access
-    #[structopt(long = "jmx-password")]
-    jmx_password: Option<String>,
-    /// Connection pool name, used to qualify JMX beans for Slick and/or HikariCP
-    #[structopt(long = "db-pool-name")]
-    db_pool_name: Option<String>,
-    /// Address of http endpoint to get akka actor tree
-    #[structopt(long = "actor-tree")]
-    actor_tree: Option<String>,
-    /// Address of http endpoint to get current actor system status
-    #[structopt(long = "actor-system-status")]
-    actor_system_status: Option<String>,
-    /// Time period (in ms) to assemble akka actor tree
-    #[structopt(long = "actor-tree-timeout", default_value = "1000")]
-    actor_tree_timeout: u64,
-    /// Address of http endpoint to get akka dead-letters metrics
-    #[structopt(long = "dead-letters")]
-    dead_letters: Option<String>,
-    /// Time window for akka dead-letters metrics
-    #[structopt(long = "dead-letters-window", default_value = "5000")]
-    dead_letters_window: u64,
+    /// Path to a TOML or YAML config file with connection profiles.
+    /// Defaults to panopticon-tui/config.toml (or config.yaml) in the user config directory
+    #[structopt(long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Name of a profile from the config file to monitor. Can be repeated, one per target
+    #[structopt(long = "profile", number_of_values = 1)]
+    profile: Vec<String>,
+    /// Optional target name, shown in the target selector. Can be repeated, one per target
+    #[structopt(long = "target-name", number_of_values = 1)]
+    target_name: Vec<String>,
+    /// Address of zio-zmx server, e.g. localhost:6789. Can be repeated, one per target
+    #[structopt(long = "zio-zmx", number_of_values = 1)]
+    zio_zmx: Vec<String>,
+    /// Address of remote jmx source, e.g. localhost:9010, or its full service URL,
+    /// e.g. service:jmx:rmi:///jndi/rmi://localhost:9010/jmxrmi. Can be repeated, one per target
+    #[structopt(long = "jmx", number_of_values = 1)]
+    jmx: Vec<String>,
+    /// Optional username for authorized jmx access. Can be repeated, one per target
+    #[structopt(long = "jmx-username", number_of_values = 1)]
+    jmx_username: Vec<String>,
+    /// Optional password for authorized jmx access. Can be repeated, one per target
+    #[structopt(long = "jmx-password", number_of_values = 1)]
+    jmx_password: Vec<String>,
+    /// Connect to an RMI registry protected with SSL (com.sun.management.jmxremote.registry.ssl=true).
+    /// Can be repeated, one per target
+    #[structopt(long = "jmx-ssl", parse(from_occurrences))]
+    jmx_ssl: usize,
+    /// Trust store to verify the certificate of an SSL jmx connection with.
+    /// It's set for the whole jvm, so it's shared by all targets and can't be set in profiles
+    #[structopt(long = "jmx-truststore")]
+    jmx_truststore: Option<String>,
+    /// Optional password of the jmx trust store
+    #[structopt(long = "jmx-truststore-password")]
+    jmx_truststore_password: Option<String>,
+    /// URL of a Jolokia agent, e.g. http://localhost:8778/jolokia. Reads the same MBeans as --jmx over HTTP,
+    /// so it doesn't need Java installed. Can be repeated, one per target
+    #[structopt(long = "jolokia", number_of_values = 1)]
+    jolokia: Vec<String>,
+    /// Connection pool name, used to qualify JMX beans for Slick and/or HikariCP.
+    /// Several pools can be given as a comma separated list, e.g. orders-read,orders-write.
+    /// Can be repeated, one per target
+    #[structopt(long = "db-pool-name", number_of_values = 1)]
+    db_pool_name: Vec<String>,
+    /// Monitor all pools with registered Slick or HikariCP beans, instead of the ones given with --db-pool-name.
+    /// Can be repeated, one per target
+    #[structopt(long = "discover-db-pools", parse(from_occurrences))]
+    discover_db_pools: usize,
+    /// Address of http endpoint to get akka actor tree. Can be repeated, one per target
+    #[structopt(long = "actor-tree", number_of_values = 1)]
+    actor_tree: Vec<String>,
+    /// Address of http endpoint to get current actor system status. Can be repeated, one per target
+    #[structopt(long = "actor-system-status", number_of_values = 1)]
+    actor_system_status: Vec<String>,
+    /// Time period (in ms) to assemble akka actor tree [default: 1000]. Can be repeated, one per target
+    #[structopt(long = "actor-tree-timeout", number_of_values = 1)]
+    actor_tree_timeout: Vec<u64>,
+    /// Address of http endpoint to get akka dead-letters metrics. Can be repeated, one per target
+    #[structopt(long = "dead-letters", number_of_values = 1)]
+    dead_letters: Vec<String>,
+    /// Time window for akka dead-letters metrics [default: 5000]. Can be repeated, one per target
+    #[structopt(long = "dead-letters-window", number_of_values = 1)]
+    dead_letters_window: Vec<u64>,
+    /// Record every fetched metric to a session file, to be viewed later with --replay
+    #[structopt(long = "record", parse(from_os_str))]
+    record: Option<PathBuf>,
+    /// Replay a session file, written with --record, instead of connecting to any app
+    #[structopt(long = "replay", parse(from_os_str), conflicts_with = "record")]
+    replay: Option<PathBuf>,
+    /// Run without UI, serving collected metrics in Prometheus format at http://<addr>/metrics, e.g. 0.0.0.0:9100
+    #[structopt(long = "serve-metrics", conflicts_with = "replay")]
+    serve_metrics: Option<SocketAddr>,
+    /// Fetch the fiber dump or actor tree shown again every given number of seconds.
+    /// Auto-refresh can be toggled with <R> as well, every 10 seconds unless given here
+    #[structopt(long = "auto-refresh", parse(try_from_str = parse_refresh_interval))]
+    auto_refresh: Option<u64>,
+    /// Number of fiber dumps kept to compare with <C>, at least 2
+    #[structopt(long = "fiber-snapshots", default_value = "5", parse(try_from_str = parse_fiber_snapshots))]
+    fiber_snapshots: usize,
+    /// Directory to save fiber dumps, thread dumps and actor trees to, with <W> (text) or <Shift+W> (json)
+    #[structopt(long = "output-dir", parse(from_os_str), default_value = ".")]
+    output_dir: PathBuf,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    /// Target of every occurrence of each per-target option, by option name.
+    #[structopt(skip)]
+    targets_of: HashMap<&'static str, Vec<usize>>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Fetch data once, print it and exit.
+    /// Exits with 1 if fetching failed and with 2 if the data can't be fetched with given options
+    Snapshot(SnapshotOptions),
+    /// Open a fiber dump file, e.g. the output of Fiber.dumpAll, in a read-only ZIO tab.
+    /// Needs no connection options
+    AnalyzeDump {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
 }
 
+fn parse_refresh_interval(s: &str) -> Result<u64, String> {
+    match s.parse::<u64>() {
+        Ok(0) => Err("auto-refresh interval has to be at least 1 second".to_owned()),
+        Ok(secs) => Ok(secs),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_fiber_snapshots(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n < 2 => Err("at least 2 fiber dumps have to be kept to compare them".to_owned()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Options that are given once per target, by their clap names.
+const TARGET_OPTIONS: [&str; 15] = [
+    "profile", "target-name", "zio-zmx", "jmx", "jmx-username", "jmx-password", "jmx-ssl", "jolokia",
+    "db-pool-name", "discover-db-pools", "actor-tree", "actor-system-status", "actor-tree-timeout",
+    "dead-letters", "dead-letters-window",
+];
+
 impl Cli {
-    fn jmx_settings(&self) -> Option<JMXConnectionSettings> {
-        match (&self.jmx, &self.db_pool_name) {
-            (Some(addr), Some(db_pool)) => Some(JMXConnectionSettings {
-                address: addr.clone(),
-                username: self.jmx_username.clone(),
-                password: self.jmx_password.clone(),
-                db_pool_name: db_pool.clone(),
-            }),
-            _ => None
+    /// Parses the command line, grouping per-target options by their position:
+    /// the next target starts where an option of the current one is given again.
+    fn from_matches(matches: &ArgMatches) -> Cli {
+        let mut cli = Cli::from_clap(matches);
+        let mut given: Vec<(usize, &'static str)> = TARGET_OPTIONS.iter()
+            .flat_map(|&o| matches.indices_of(o).into_iter().flatten().map(move |i| (i, o)))
+            .collect();
+        given.sort_unstable();
+
+        let mut target = 0;
+        let mut options_of_target = HashSet::new();
+        for (_, option) in given {
+            if !options_of_target.insert(option) {
+                target += 1;
+                options_of_target.clear();
+                options_of_target.insert(option);
+            }
+            cli.targets_of.entry(option).or_default().push(target);
         }
+        cli
     }
 
-    fn akka_settings(&self) -> Option<AkkaSettings> {
-        match (&self.actor_tree, &self.actor_system_status, &self.dead_letters) {
-            (Some(tree_addr), Some(status_addr), Some(dead_letters)) => Some(AkkaSettings {
-                tree_address: tree_addr.to_owned(),
-                tree_timeout: self.actor_tree_timeout,
-                status_address: status_addr.to_owned(),
-                status_timeout: (self.tick_rate as f64 * 0.8) as u64,
-                dead_letters_address: dead_letters.to_owned(),
-                dead_letters_window: self.dead_letters_window,
-            }),
-            _ => None
+    /// The value of a per-target option given for the n-th target, if any.
+    fn value_of<T: Clone>(&self, option: &str, values: &[T], n: usize) -> Option<T> {
+        self.targets_of.get(option).into_iter().flatten().zip(values)
+            .find(|(t, _)| **t == n)
+            .map(|(_, v)| v.clone())
+    }
+
+    fn target_count(&self) -> usize {
+        self.targets_of.values().flatten().max().map_or(0, |t| t + 1)
+    }
+
+    /// Options of the n-th target given on the command line.
+    fn overrides(&self, n: usize) -> Profile {
+        Profile {
+            zio_zmx: self.value_of("zio-zmx", &self.zio_zmx, n),
+            jmx: self.value_of("jmx", &self.jmx, n),
+            jmx_username: self.value_of("jmx-username", &self.jmx_username, n),
+            jmx_password: self.value_of("jmx-password", &self.jmx_password, n),
+            jmx_ssl: self.value_of("jmx-ssl", &vec![true; self.jmx_ssl], n),
+            jmx_truststore: self.jmx_truststore.clone(),
+            jmx_truststore_password: self.jmx_truststore_password.clone(),
+            jolokia: self.value_of("jolokia", &self.jolokia, n),
+            db_pool_name: self.value_of("db-pool-name", &self.db_pool_name, n),
+            discover_db_pools: self.value_of("discover-db-pools", &vec![true; self.discover_db_pools], n),
+            actor_tree: self.value_of("actor-tree", &self.actor_tree, n),
+            actor_system_status: self.value_of("actor-system-status", &self.actor_system_status, n),
+            actor_tree_timeout: self.value_of("actor-tree-timeout", &self.actor_tree_timeout, n),
+            dead_letters: self.value_of("dead-letters", &self.dead_letters, n),
+            dead_letters_window: self.value_of("dead-letters-window", &self.dead_letters_window, n),
         }
     }
+
+    fn load_config(&self) -> Result<Config, String> {
+        match self.config.clone().or_else(Config::default_path) {
+            Some(path) => Config::load(&path),
+            None if self.profile.is_empty() => Ok(Config::default()),
+            None => Err("Profiles requested, but no config file found. Please specify one with --config".to_owned()),
+        }
+    }
+
+    fn targets(&self, config: &Config) -> Result<Vec<TargetSettings>, String> {
+        let mut targets: Vec<TargetSettings> = vec![];
+        for n in 0..self.target_count() {
+            let profile_name = self.value_of("profile", &self.profile, n);
+            let profile = match &profile_name {
+                Some(p) => config.profile(p)?,
+                None => Profile::default(),
+            };
+            let name = self.value_of("target-name", &self.target_name, n)
+                .or(profile_name)
+                .unwrap_or_else(|| format!("target-{}", n + 1));
+            let profile = profile.merge(self.overrides(n));
+            if profile.jmx.is_some() && profile.jolokia.is_some() {
+                return Err(format!("Target {} has both jmx and jolokia set, please choose one", name));
+            }
+            if profile.jmx_username.is_some() != profile.jmx_password.is_some() {
+                return Err(format!("Target {} needs both a jmx username and a password, or neither", name));
+            }
+            let missing_akka = profile.missing_akka_options();
+            if !missing_akka.is_empty() {
+                return Err(format!(
+                    "Target {} is missing akka option(s) {}, actor-tree, actor-system-status and dead-letters go together",
+                    name, missing_akka.join(", ")
+                ));
+            }
+            let target = profile.target_settings(name, self.tick_rate);
+
+            if target.is_empty() {
+                return Err(format!("Target {} has an incomplete set of connection options", target.name));
+            }
+            if targets.iter().any(|t| t.name == target.name) {
+                return Err(format!("Target name {} is used more than once", target.name));
+            }
+            targets.push(target);
+        }
+        Ok(targets)
+    }
 }
 
 fn main() -> Result<(), failure::Error> {
-    let cli = Cli::from_args();
+    let cli = Cli::from_matches(&Cli::clap().get_matches());
 
     // disable jmx crate logging
     env::set_var("J4RS_CONSOLE_LOG_LEVEL", "disabled");
 
-    if cli.zio_zmx.is_none() && cli.jmx_settings().is_none() && cli.akka_settings().is_none() {
+    if let Some(Command::Snapshot(options)) = &cli.command {
+        let code = match cli.load_config().and_then(|config| cli.targets(&config)) {
+            Ok(targets) => snapshot::run(options, targets),
+            Err(e) => {
+                eprintln!("{}", e);
+                snapshot::EXIT_MISCONFIGURED
+            }
+        };
+        std::process::exit(code);
+    }
+
+    let mut player = match &cli.replay {
+        Some(path) => match Recording::load(path) {
+            Ok(recording) => Some(Player::new(recording)),
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let dump = match &cli.command {
+        Some(Command::AnalyzeDump { .. }) if cli.record.is_some() || cli.replay.is_some() || cli.serve_metrics.is_some() => {
+            println!("analyze-dump can't be combined with --record, --replay or --serve-metrics");
+            return Ok(());
+        }
+        Some(Command::AnalyzeDump { file }) => match load_fiber_dump(file) {
+            Ok(dump) => Some(dump),
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        },
+        _ => None,
+    };
+    // there's nothing to request data from when looking at a recording or a dump file
+    let read_only = player.is_some() || dump.is_some();
+
+    // a dump file needs nothing from the config, and a replay only uses its alert rules, which aren't worth failing for
+    let mut config_error = None;
+    let config = match (&dump, &player) {
+        (Some(_), _) => Config::default(),
+        (None, Some(_)) => cli.load_config().unwrap_or_else(|e| {
+            config_error = Some(e);
+            Config::default()
+        }),
+        (None, None) => match cli.load_config() {
+            Ok(config) => config,
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        },
+    };
+
+    let targets = match (&player, &dump) {
+        (Some(p), _) => p.targets().to_vec(),
+        (None, Some((target, _))) => vec![target.clone()],
+        (None, None) => match cli.targets(&config) {
+            Ok(targets) => targets,
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        },
+    };
+
+    if targets.is_empty() {
         let mut clap = Cli::clap();
         println!("Nothing to monitor. Please check the following help message.\n");
         clap.print_long_help().expect("Failed printing help message");
         return Ok(());
     }
 
+    // replayed alerts are only listed, the bell and the command hook are for live sessions
+    let alerts_config = match &player {
+        Some(_) => AlertsConfig { bell: false, command: None, ..config.alerts.clone() },
+        None => config.alerts.clone(),
+    };
+    let alerts = match Alerts::new(&alerts_config) {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(());
+        }
+    };
+
+    let recorder = match &cli.record {
+        Some(path) => match Recorder::create(path, &targets) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
     let tick_rate = Duration::from_millis(cli.tick_rate);
-    let has_jmx = cli.jmx_settings().is_some();
+
+    if let Some(addr) = cli.serve_metrics {
+        return serve_metrics(addr, targets, tick_rate, recorder);
+    }
+
+    // replay is driven by ticks, so they have to be frequent enough for smooth playback
+    let ui_tick_rate = if player.is_some() { REPLAY_TICK_RATE } else { tick_rate };
 
     enable_raw_mode()?;
 
@@ -136,12 +440,27 @@ fn main() -> Result<(), failure::Error> {
     let mut terminal = Terminal::new(backend)?;
     terminal.hide_cursor()?;
 
-    let mut app = App::new(
-        "PANOPTICON-TUI",
-        cli.zio_zmx.clone(),
-        cli.jmx_settings(),
-        cli.akka_settings(),
-    );
+    let title = match &dump {
+        Some((target, _)) => format!("PANOPTICON-TUI [{}]", target.name),
+        None => "PANOPTICON-TUI".to_owned(),
+    };
+    let mut app = App::new(&title, &targets);
+    app.alerts = alerts;
+    app.save_dir = cli.output_dir.clone();
+    app.set_fiber_snapshots(cli.fiber_snapshots);
+    if let Some(e) = config_error {
+        app.show_notice(format!("Alert rules not loaded: {}", e), Instant::now());
+    }
+    if let Some(secs) = cli.auto_refresh {
+        app.refresh_interval = Duration::from_secs(secs);
+        app.auto_refresh = !read_only;
+    }
+    if let Some((target, dumps)) = dump {
+        // set directly rather than with show_notice, so that it stays, just like the dump itself
+        app.notice = dumps.skipped_notice();
+        app.on_fetcher_response(&target.name, FetcherResponse::FiberCount(Ok(FiberCount::from_fibers(&dumps.fibers))), Local::now());
+        app.on_fetcher_response(&target.name, FetcherResponse::FiberDump(Ok(dumps.fibers)), Local::now());
+    }
 
     terminal.clear()?;
 
@@ -149,68 +468,31 @@ fn main() -> Result<(), failure::Error> {
     let (tx, rx) = mpsc::channel();
 
     // Setup fetcher interaction
-    let (txf, rxf) = mpsc::channel();
-    {
-        let tx = tx.clone();
-        thread::spawn(move || {
-            let respond = |r| tx.send(Event::FetcherResponse(r)).unwrap();
-
-            match Fetcher::new(cli.zio_zmx.clone(),
-                               cli.jmx_settings(),
-                               cli.akka_settings()) {
-                Err(e) => {
-                    eprintln!("Responding with failure {}", e);
-                    loop {
-                        rxf.recv().unwrap();
-                        respond(FetcherResponse::FatalFailure(e.to_owned()))
-                    }
-                }
-                Ok(fetcher) =>
-                    loop {
-                        match rxf.recv().unwrap() {
-                            FetcherRequest::FiberDump =>
-                                respond(FetcherResponse::FiberDump(fetcher.dump_fibers())),
-                            FetcherRequest::RegularFiberDump =>
-                                respond(FetcherResponse::RegularFiberDump(fetcher.dump_fibers())),
-                            FetcherRequest::HikariMetrics =>
-                                respond(FetcherResponse::HikariMetrics(fetcher.get_hikari_metrics())),
-                            FetcherRequest::SlickMetrics =>
-                                respond(FetcherResponse::SlickMetrics(fetcher.get_slick_metrics())),
-                            FetcherRequest::SlickConfig =>
-                                respond(FetcherResponse::SlickConfig(fetcher.get_slick_config())),
-                            FetcherRequest::ActorTree =>
-                                respond(FetcherResponse::ActorTree(fetcher.get_actor_tree())),
-                            FetcherRequest::ActorSystemStatus =>
-                                respond(FetcherResponse::ActorSystemStatus(fetcher.get_actor_system_status())),
-                            FetcherRequest::DeadLetters =>
-                                respond(FetcherResponse::DeadLetters(fetcher.get_dead_letters())),
-                        }
-                    }
-            }
+    let runtime = tokio::runtime::Runtime::new()?;
+    let (txf, rxf) = tokio::sync::mpsc::unbounded_channel::<(String, FetcherRequest)>();
+    if !read_only {
+        let tx = Mutex::new(tx.clone());
+        let respond = responder(recorder, move |t, r| {
+            // the receiving end is gone only when the app is shutting down
+            let _ = tx.lock().unwrap().send(Event::FetcherResponse(t, r));
         });
+        fetcher::run(runtime.handle(), targets, tick_rate, rxf, respond);
     }
 
     // Setup input handling
     {
         let tx = tx.clone();
-        let txf = txf.clone();
         thread::spawn(move || {
             let mut last_tick = Instant::now();
 
-            if has_jmx {
-                txf.send(FetcherRequest::SlickConfig).unwrap();
-                txf.send(FetcherRequest::HikariMetrics).unwrap();
-                txf.send(FetcherRequest::SlickMetrics).unwrap();
-            }
-
             loop {
                 // poll for tick rate duration, if no events, sent tick event.
-                if event::poll(tick_rate - last_tick.elapsed()).unwrap() {
+                if event::poll(ui_tick_rate.checked_sub(last_tick.elapsed()).unwrap_or_default()).unwrap() {
                     if let CEvent::Key(key) = event::read().unwrap() {
                         tx.send(Event::Input(key)).unwrap();
                     }
                 }
-                if last_tick.elapsed() >= tick_rate {
+                if last_tick.elapsed() >= ui_tick_rate {
                     tx.send(Event::Tick).unwrap();
                     last_tick = Instant::now();
                 }
@@ -218,9 +500,17 @@ fn main() -> Result<(), failure::Error> {
         });
     }
 
+    let mut last_replay_tick = Instant::now();
     loop {
+        if let Some(p) = &player {
+            app.replay_status = Some(p.status());
+        }
         ui::draw(&mut terminal, &mut app)?;
         match rx.recv()? {
+            Event::Input(event) if app.is_searching() && on_search_key(&mut app, event.code) => {}
+            Event::Input(event) if player.as_mut().is_some_and(|p| on_replay_key(p, &mut app, event.code)) => {}
+            // on-demand requests can't be made without a connection
+            Event::Input(event) if read_only && matches!(event.code, KeyCode::Enter | KeyCode::Char('p') | KeyCode::Char('r')) => {}
             Event::Input(event) => match event.code {
                 KeyCode::Char('q') => {
                     disable_raw_mode()?;
@@ -228,6 +518,12 @@ fn main() -> Result<(), failure::Error> {
                     terminal.show_cursor()?;
                     break;
                 }
+                KeyCode::Char('p') => {
+                    if let Some(pins) = app.on_pin() {
+                        let t = app.current_target().to_owned();
+                        txf.send((t, FetcherRequest::SetPinnedAttributes(pins)))?
+                    }
+                }
                 KeyCode::Char(c) => app.on_key(c),
                 KeyCode::Left => app.on_left(),
                 KeyCode::Up => app.on_up(),
@@ -235,94 +531,300 @@ fn main() -> Result<(), failure::Error> {
                 KeyCode::Down => app.on_down(),
                 KeyCode::PageUp => app.on_page_up(),
                 KeyCode::PageDown => app.on_page_down(),
+                KeyCode::Tab => app.on_next_target(),
+                KeyCode::BackTab => app.on_prev_target(),
+                KeyCode::Backspace => app.on_parent(),
                 KeyCode::Enter => {
-                    match app.tabs.current().kind {
-                        AppTabKind::ZMX => txf.send(FetcherRequest::FiberDump)?,
-                        AppTabKind::Slick => {}
-                        AppTabKind::Akka => txf.send(FetcherRequest::ActorTree)?,
+                    let t = app.current_target().to_owned();
+                    match app.current().tabs.current().kind {
+                        AppTabKind::Zmx => txf.send((t, FetcherRequest::FiberDump))?,
+                        AppTabKind::Slick | AppTabKind::Jvm => {}
+                        AppTabKind::Threads => txf.send((t, FetcherRequest::ThreadDump))?,
+                        AppTabKind::Akka => txf.send((t, FetcherRequest::ActorTree))?,
+                        AppTabKind::MBeans => txf.send((t, app.current().mbeans.as_ref().unwrap().enter_request()))?,
                     }
                 }
                 _ => {}
             },
-            Event::FetcherResponse(r) => match r {
-                FetcherResponse::FatalFailure(e) =>
-                    app.quit(Some(e)),
-
-                FetcherResponse::FiberDump(d) =>
-                    match d {
-                        Err(e) => app.quit(Some(e)),
-                        Ok(x) => app.zmx.as_mut().unwrap().replace_fiber_dump(x),
-                    },
-                FetcherResponse::RegularFiberDump(d) =>
-                    match d {
-                        Err(e) => app.quit(Some(e)),
-                        Ok(x) => app.zmx.as_mut().unwrap().append_fiber_dump_for_counts(x),
-                    },
-                FetcherResponse::HikariMetrics(d) =>
-                    match d {
-                        Err(_) => app.slick.as_mut().unwrap().has_hikari = false,
-                        Ok(x) => {
-                            app.slick.as_mut().unwrap().has_hikari = true;
-                            app.slick.as_mut().unwrap().append_hikari_metrics(x)
-                        }
-                    },
-                FetcherResponse::SlickMetrics(d) =>
-                    match d {
-                        Err(e) => app.quit(Some(e)),
-                        Ok(x) => app.slick.as_mut().unwrap().append_slick_metrics(x)
-                    },
-                FetcherResponse::SlickConfig(d) =>
-                    match d {
-                        Err(e) => app.quit(Some(e)),
-                        Ok(x) => app.slick.as_mut().unwrap().replace_slick_config(x)
-                    },
-                FetcherResponse::ActorTree(d) =>
-                    match d {
-                        Err(e) => app.quit(Some(e)),
-                        Ok(x) => {
-                            let akka = app.akka.as_mut().unwrap();
-                            akka.update_actor_tree(x);
-                            akka.reload_dead_letters_log();
-                        }
-                    },
-                FetcherResponse::ActorSystemStatus(d) =>
-                    match d {
-                        Err(e) => app.quit(Some(e)),
-                        Ok(x) => app.akka.as_mut().unwrap().append_system_status(x)
-                    },
-                FetcherResponse::DeadLetters(d) =>
-                    match d {
-                        Err(e) => app.quit(Some(e)),
-                        Ok(x) => app.akka.as_mut().unwrap().append_dead_letters(x.0, x.1)
-                    },
-            }
+            Event::FetcherResponse(t, r) => app.on_fetcher_response(&t, r, Local::now()),
 
             Event::Tick => {
-                if app.zmx.is_some() {
-                    txf.send(FetcherRequest::RegularFiberDump)?;
+                // metrics are polled by the fetcher, so ticks only keep the ui fresh and trigger auto-refresh, unless we replay a recording
+                app.on_tick(Instant::now());
+                if let Some(p) = player.as_mut() {
+                    p.advance(&mut app, last_replay_tick.elapsed());
+                    last_replay_tick = Instant::now();
                 }
-
-                match &app.slick {
-                    Some(s) => {
-                        txf.send(FetcherRequest::SlickMetrics)?;
-                        if s.has_hikari {
-                            txf.send(FetcherRequest::HikariMetrics)?;
-                        }
-                    }
-                    None => {}
-                }
-
-                if app.akka.is_some() {
-                    txf.send(FetcherRequest::ActorSystemStatus)?;
-                    txf.send(FetcherRequest::DeadLetters)?;
+                if let Some(request) = app.refresh_request(Instant::now()) {
+                    let t = app.current_target().to_owned();
+                    txf.send((t, request))?
                 }
             }
         }
+        app.alerts.notify();
         if app.should_quit {
             break;
         }
     }
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    app.exit_reason.map(|e| println!("{}", e));
+    // don't wait for in-flight requests, some of them may take a while
+    runtime.shutdown_background();
+    if let Some(e) = app.exit_reason {
+        println!("{}", e);
+    }
+    Ok(())
+}
+
+/// Reads a fiber dump file as a target with the ZIO tab only.
+fn load_fiber_dump(file: &Path) -> Result<(TargetSettings, FiberDumps), String> {
+    let text = fs::read_to_string(file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let dumps = dump_parser::parse_fiber_dumps(&text);
+    if dumps.fibers.is_empty() {
+        return Err(format!("No fiber dumps found in {}", file.display()));
+    }
+    let name = file.file_name().map_or_else(|| file.display().to_string(), |n| n.to_string_lossy().to_string());
+    // the address is never connected to, it only makes the ZIO tab show up
+    let target = TargetSettings { name, zio_zmx: Some(file.display().to_string()), jmx: None, akka: None };
+    Ok((target, dumps))
+}
+
+/// Wraps a response handler, so that every response is recorded first, if recording is on.
+fn responder<F>(recorder: Option<Recorder>, handle: F) -> Responder
+    where F: Fn(String, FetcherResponse) + Send + Sync + 'static,
+{
+    Arc::new(move |t, r| {
+        if let Some(recorder) = &recorder {
+            // a broken recording shouldn't interrupt monitoring
+            let _ = recorder.record(&t, &r);
+        }
+        handle(t, r)
+    })
+}
+
+/// Headless mode: polls all targets and serves the latest metrics until killed.
+fn serve_metrics(addr: SocketAddr, targets: Vec<TargetSettings>, tick_rate: Duration, recorder: Option<Recorder>) -> Result<(), failure::Error> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let exporter = Arc::new(Exporter::new(&targets));
+    // there's nobody to make on-demand requests without UI
+    let (_txf, rxf) = tokio::sync::mpsc::unbounded_channel::<(String, FetcherRequest)>();
+    {
+        let exporter = exporter.clone();
+        let respond = responder(recorder, move |t, r| exporter.on_fetcher_response(&t, r));
+        fetcher::run(runtime.handle(), targets, tick_rate, rxf, respond);
+    }
+
+    println!("Serving metrics at http://{}/metrics", addr);
+    if let Err(e) = runtime.block_on(exporter::serve(addr, exporter)) {
+        println!("{}", e);
+    }
+    runtime.shutdown_background();
     Ok(())
 }
+
+/// Edits the fiber search query, returns false for keys that should be handled as usual.
+fn on_search_key(app: &mut App, key: KeyCode) -> bool {
+    match key {
+        KeyCode::Char(c) => app.on_search_char(c),
+        KeyCode::Backspace => app.on_search_backspace(),
+        KeyCode::Enter => app.on_search_end(true),
+        KeyCode::Esc => app.on_search_end(false),
+        _ => return false,
+    }
+    true
+}
+
+/// Handles playback controls, returns false for keys that should be handled as usual.
+fn on_replay_key(player: &mut Player, app: &mut App, key: KeyCode) -> bool {
+    match key {
+        KeyCode::Char(' ') => player.toggle_pause(),
+        KeyCode::Char('+') => player.faster(),
+        KeyCode::Char('-') => player.slower(),
+        KeyCode::Char(']') => player.seek_forward(app, REPLAY_SEEK_STEP),
+        KeyCode::Char('[') => player.seek_backward(app, REPLAY_SEEK_STEP),
+        // on-demand requests can't be made, their recorded responses are replayed instead
+        KeyCode::Enter | KeyCode::Char('p') => {}
+        _ => return false,
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::StructOpt;
+
+    use crate::{Cli, Command, load_fiber_dump};
+    use crate::config::{Config, Profile};
+    use crate::jmx::model::DbPools;
+
+    fn parse(args: Vec<&str>) -> Cli {
+        Cli::from_matches(&Cli::clap().get_matches_from(args))
+    }
+
+    #[test]
+    fn cli_groups_repeated_options_into_targets() {
+        let cli = parse(vec![
+            "panopticon-tui",
+            "--target-name", "orders-1", "--zio-zmx", "localhost:6789", "--jmx", "localhost:9010",
+            "--target-name", "orders-2", "--zio-zmx", "localhost:6790",
+            "--db-pool-name", "orders",
+        ]);
+
+        let targets = cli.targets(&Config::default()).unwrap();
+
+        assert_eq!(targets.iter().map(|t| t.name.as_str()).collect::<Vec<&str>>(), vec!["orders-1", "orders-2"]);
+        assert_eq!(targets[0].zio_zmx, Some("localhost:6789".to_owned()));
+        assert_eq!(targets[0].jmx.as_ref().map(|j| j.address.as_str()), Some("localhost:9010"));
+        assert_eq!(targets[1].zio_zmx, Some("localhost:6790".to_owned()));
+        assert!(targets[1].jmx.is_none());
+    }
+
+    #[test]
+    fn cli_applies_options_to_the_target_they_are_given_with() {
+        let cli = parse(vec![
+            "panopticon-tui",
+            "--jmx", "orders-1:9010", "--jmx-username", "monitor", "--jmx-password", "s3cret", "--db-pool-name", "orders",
+            "--jmx", "orders-2:9010", "--discover-db-pools", "--jmx-ssl",
+            "--jmx-truststore", "/etc/panopticon/truststore.p12",
+        ]);
+
+        let targets = cli.targets(&Config::default()).unwrap();
+        let jmx: Vec<_> = targets.iter().map(|t| t.jmx.as_ref().unwrap()).collect();
+
+        assert_eq!(targets.iter().map(|t| t.name.as_str()).collect::<Vec<&str>>(), vec!["target-1", "target-2"]);
+        assert_eq!((jmx[0].username.as_deref(), jmx[0].ssl), (Some("monitor"), false));
+        assert_eq!(jmx[0].db_pools, Some(DbPools::Named(vec!["orders".to_owned()])));
+        assert_eq!((jmx[1].username.as_deref(), jmx[1].ssl), (None, true));
+        assert_eq!(jmx[1].db_pools, Some(DbPools::Discovered));
+        // the trust store is set for the whole jvm
+        assert!(jmx.iter().all(|j| j.truststore.as_deref() == Some("/etc/panopticon/truststore.p12")));
+    }
+
+    #[test]
+    fn cli_loads_fiber_dump_file() {
+        let path = std::env::temp_dir().join("panopticon-fibers.txt");
+        std::fs::write(&path, "#1 (10ms)\n   Status: Running()\n#2 (5ms)\n   Status: Done()\n").unwrap();
+        let cli = Cli::from_iter(vec!["panopticon-tui", "analyze-dump", path.to_str().unwrap()]);
+
+        let (target, dumps) = match &cli.command {
+            Some(Command::AnalyzeDump { file }) => load_fiber_dump(file).unwrap(),
+            c => panic!("Unexpected command: {:?}", c),
+        };
+        assert_eq!(target.name, "panopticon-fibers.txt");
+        assert!(target.zio_zmx.is_some() && target.jmx.is_none() && target.akka.is_none());
+        assert_eq!(dumps.fibers.iter().map(|f| f.id).collect::<Vec<usize>>(), vec![1, 2]);
+        assert!(dumps.skipped.is_empty());
+
+        std::fs::write(&path, "nothing to see here").unwrap();
+        assert!(load_fiber_dump(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cli_rejects_incomplete_target() {
+        let cli = parse(vec![
+            "panopticon-tui",
+            "--zio-zmx", "localhost:6789",
+            "--actor-tree", "localhost:8080/tree", "--actor-tree", "localhost:8081/tree",
+        ]);
+
+        assert!(cli.targets(&Config::default()).is_err());
+    }
+
+    #[test]
+    fn cli_names_missing_akka_options() {
+        let cli = parse(vec![
+            "panopticon-tui",
+            "--zio-zmx", "localhost:6789",
+            "--actor-tree", "localhost:8080/tree", "--actor-system-status", "localhost:8080/status",
+        ]);
+
+        assert_eq!(
+            cli.targets(&Config::default()).err(),
+            Some("Target target-1 is missing akka option(s) dead-letters, actor-tree, actor-system-status and dead-letters go together".to_owned())
+        );
+    }
+
+    #[test]
+    fn cli_options_override_profiles() {
+        let mut config = Config::default();
+        config.profiles.insert("staging-orders".to_owned(), Profile {
+            jmx: Some("orders.staging:9010".to_owned()),
+            db_pool_name: Some("orders".to_owned()),
+            zio_zmx: Some("orders.staging:6789".to_owned()),
+            ..Profile::default()
+        });
+        let cli = parse(vec![
+            "panopticon-tui", "--profile", "staging-orders", "--jmx", "localhost:9010",
+        ]);
+
+        let targets = cli.targets(&config).unwrap();
+
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].name, "staging-orders");
+        assert_eq!(targets[0].zio_zmx, Some("orders.staging:6789".to_owned()));
+        assert_eq!(targets[0].jmx.as_ref().map(|j| j.address.as_str()), Some("localhost:9010"));
+        assert_eq!(targets[0].jmx.as_ref().and_then(|j| j.db_pools.clone()), Some(DbPools::Named(vec!["orders".to_owned()])));
+    }
+
+    #[test]
+    fn cli_passes_jmx_security_options() {
+        let cli = parse(vec![
+            "panopticon-tui",
+            "--jmx", "service:jmx:rmi:///jndi/rmi://orders:9010/jmxrmi",
+            "--jmx-username", "monitor", "--jmx-password", "s3cret",
+            "--jmx-ssl", "--jmx-truststore", "/etc/panopticon/truststore.p12",
+        ]);
+
+        let targets = cli.targets(&Config::default()).unwrap();
+        let jmx = targets[0].jmx.as_ref().unwrap();
+
+        assert_eq!(jmx.service_url(), "service:jmx:rmi:///jndi/rmi://orders:9010/jmxrmi");
+        assert_eq!(jmx.username.as_deref(), Some("monitor"));
+        assert_eq!(jmx.password.as_deref(), Some("s3cret"));
+        assert!(jmx.ssl);
+        assert_eq!(jmx.truststore.as_deref(), Some("/etc/panopticon/truststore.p12"));
+        assert_eq!(jmx.truststore_password, None);
+
+        let cli = parse(vec!["panopticon-tui", "--jmx", "orders:9010", "--jmx-username", "monitor"]);
+        assert_eq!(
+            cli.targets(&Config::default()).err(),
+            Some("Target target-1 needs both a jmx username and a password, or neither".to_owned())
+        );
+    }
+
+    #[test]
+    fn cli_rejects_zero_refresh_interval() {
+        let cli = Cli::from_iter_safe(vec!["panopticon-tui", "--zio-zmx", "localhost:6789", "--auto-refresh", "0"]);
+        assert!(cli.unwrap_err().message.contains("auto-refresh interval has to be at least 1 second"));
+
+        let cli = Cli::from_iter(vec!["panopticon-tui", "--zio-zmx", "localhost:6789", "--auto-refresh", "5"]);
+        assert_eq!(cli.auto_refresh, Some(5));
+    }
+
+    #[test]
+    fn cli_keeps_at_least_two_fiber_dumps() {
+        let cli = Cli::from_iter(vec!["panopticon-tui", "--zio-zmx", "localhost:6789"]);
+        assert_eq!(cli.fiber_snapshots, 5);
+
+        let cli = Cli::from_iter_safe(vec!["panopticon-tui", "--zio-zmx", "localhost:6789", "--fiber-snapshots", "1"]);
+        assert!(cli.unwrap_err().message.contains("at least 2 fiber dumps have to be kept"));
+    }
+
+    #[test]
+    fn cli_connects_to_jolokia() {
+        let cli = parse(vec![
+            "panopticon-tui", "--jolokia", "http://orders:8778/jolokia", "--db-pool-name", "orders",
+            "--jmx-username", "monitor", "--jmx-password", "s3cret",
+        ]);
+
+        let targets = cli.targets(&Config::default()).unwrap();
+        let jmx = targets[0].jmx.as_ref().unwrap();
+
+        assert!(jmx.jolokia);
+        assert_eq!(jmx.address, "http://orders:8778/jolokia");
+        assert_eq!(jmx.username.as_deref(), Some("monitor"));
+
+        let cli = parse(vec!["panopticon-tui", "--jmx", "orders:9010", "--jolokia", "http://orders:8778/jolokia"]);
+        assert!(cli.targets(&Config::default()).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use std::cmp::min;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::app::App;
+use crate::fetcher::FetcherResponse;
+use crate::target::TargetSettings;
+
+///
+/// First line of a session recording, followed by one `RecordedEvent` per line.
+///
+/// Every line is a compact json document, so a recording that was cut short
+/// (e.g. the app was killed) is still readable up to its last complete line.
+///
+#[derive(Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    /// Unix time (in ms) when the recording started.
+    pub started_at: i64,
+    pub targets: Vec<TargetSettings>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Time (in ms) since the start of the recording.
+    pub at: u64,
+    pub target: String,
+    pub response: FetcherResponse,
+}
+
+/// Writes fetcher responses to a session file as they arrive.
+pub struct Recorder {
+    file: Mutex<LineWriter<File>>,
+    started: Instant,
+}
+
+impl Recorder {
+    pub const VERSION: u32 = 2;
+
+    pub fn create(path: &Path, targets: &[TargetSettings]) -> Result<Recorder, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Couldn't create recording file {}: {}", path.display(), e))?;
+        let recorder = Recorder { file: Mutex::new(LineWriter::new(file)), started: Instant::now() };
+        let header = RecordingHeader {
+            version: Recorder::VERSION,
+            started_at: Local::now().timestamp_millis(),
+            targets: targets.to_vec(),
+        };
+        recorder.write_line(&header)?;
+        Ok(recorder)
+    }
+
+    pub fn record(&self, target: &str, response: &FetcherResponse) -> Result<(), String> {
+        let event = RecordedEvent {
+            at: self.started.elapsed().as_millis() as u64,
+            target: target.to_owned(),
+            response: response.clone(),
+        };
+        self.write_line(&event)
+    }
+
+    fn write_line<T: Serialize>(&self, value: &T) -> Result<(), String> {
+        let line = serde_json::to_string(value).map_err(|e| e.to_string())?;
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line).map_err(|e| format!("Couldn't write to recording file: {}", e))
+    }
+}
+
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Recording, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("Couldn't open recording file {}: {}", path.display(), e))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header: RecordingHeader = match lines.next() {
+            Some(line) => {
+                let line = line.map_err(|e| e.to_string())?;
+                serde_json::from_str(&line).map_err(|e| format!("Invalid recording header: {}", e))?
+            }
+            None => return Err(format!("Recording file {} is empty", path.display())),
+        };
+        if header.version != Recorder::VERSION {
+            return Err(format!("Unsupported recording version {}, expected {}", header.version, Recorder::VERSION));
+        }
+
+        let lines: Vec<String> = lines.collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+        let mut events = vec![];
+        for (n, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(event) => events.push(event),
+                // the last line may be incomplete if the recording app was killed
+                Err(_) if n == lines.len() - 1 => {}
+                Err(e) => return Err(format!("Invalid event at line {}: {}", n + 2, e)),
+            }
+        }
+        Ok(Recording { header, events })
+    }
+
+    /// Time of the last recorded event.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.events.last().map_or(0, |e| e.at))
+    }
+}
+
+/// Plays a recording back into an `App`, as if responses were coming from the fetcher.
+pub struct Player {
+    recording: Recording,
+    /// Current playback time.
+    position: Duration,
+    /// Index of the first event that hasn't been played yet.
+    next: usize,
+    pub speed: f64,
+    pub paused: bool,
+}
+
+impl Player {
+    pub const MIN_SPEED: f64 = 0.25;
+    pub const MAX_SPEED: f64 = 64.0;
+
+    pub fn new(recording: Recording) -> Player {
+        Player { recording, position: Duration::from_millis(0), next: 0, speed: 1.0, paused: false }
+    }
+
+    pub fn targets(&self) -> &[TargetSettings] {
+        &self.recording.header.targets
+    }
+
+    /// Moves playback forward by `elapsed` real time, scaled by the current speed.
+    pub fn advance(&mut self, app: &mut App, elapsed: Duration) {
+        if !self.paused {
+            let to = self.position + elapsed.mul_f64(self.speed);
+            self.play_until(app, to);
+        }
+    }
+
+    /// Jumps to the given playback time. Seeking backwards replays the recording from the start.
+    pub fn seek(&mut self, app: &mut App, to: Duration) {
+        if to < self.position {
+            app.reset(&self.recording.header.targets);
+            self.next = 0;
+        }
+        self.play_until(app, to);
+    }
+
+    pub fn seek_forward(&mut self, app: &mut App, by: Duration) {
+        self.seek(app, self.position + by)
+    }
+
+    pub fn seek_backward(&mut self, app: &mut App, by: Duration) {
+        self.seek(app, self.position.checked_sub(by).unwrap_or_default())
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(Player::MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(Player::MIN_SPEED);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.events.len()
+    }
+
+    pub fn status(&self) -> String {
+        let state = if self.is_finished() {
+            "end"
+        } else if self.paused {
+            "paused"
+        } else {
+            "playing"
+        };
+        format!(
+            "replay {} / {}, x{}, {} (<Space> pause, [ ] seek, - + speed)",
+            Player::format_time(self.position),
+            Player::format_time(self.recording.duration()),
+            self.speed,
+            state,
+        )
+    }
+
+    fn play_until(&mut self, app: &mut App, to: Duration) {
+        let to = min(to, self.recording.duration());
+        while let Some(event) = self.recording.events.get(self.next) {
+            if Duration::from_millis(event.at) > to {
+                break;
+            }
+            let at = Local.timestamp_millis(self.recording.header.started_at + event.at as i64);
+            app.on_fetcher_response(&event.target, event.response.clone(), at);
+            self.next += 1;
+        }
+        self.position = to;
+    }
+
+    fn format_time(d: Duration) -> String {
+        let secs = d.as_secs();
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::prelude::*;
+
+    use crate::alerts::{AlertMetric, AlertRule, Alerts, AlertsConfig};
+    use crate::app::App;
+    use crate::fetcher::FetcherResponse;
+    use crate::recording::{Player, Recorder, Recording, RecordedEvent, RecordingHeader};
+    use crate::target::TargetSettings;
+    use crate::zio::model::FiberCount;
+
+    fn target() -> TargetSettings {
+        TargetSettings {
+            name: "orders".to_owned(),
+            zio_zmx: Some("localhost:6789".to_owned()),
+            jmx: None,
+            akka: None,
+        }
+    }
+
+    fn fiber_count(running: i32) -> FetcherResponse {
+        FetcherResponse::FiberCount(Ok(FiberCount { done: 0, finishing: 0, running, suspended: 0 }))
+    }
+
+    #[test]
+    fn recorder_writes_readable_recording() {
+        let path = std::env::temp_dir().join("panopticon-recording-test.jsonl");
+        let recorder = Recorder::create(&path, &[target()]).unwrap();
+        recorder.record("orders", &fiber_count(3)).unwrap();
+        recorder.record("orders", &FetcherResponse::FiberCount(Err("connection refused".to_owned()))).unwrap();
+        drop(recorder);
+
+        let recording = Recording::load(&path).unwrap();
+
+        assert_eq!(recording.header.targets.len(), 1);
+        assert_eq!(recording.header.targets[0].zio_zmx, Some("localhost:6789".to_owned()));
+        assert_eq!(recording.events.len(), 2);
+        assert!(!recording.events[0].response.is_err());
+        assert_eq!(recording.events[1].response.error(), Some(&"connection refused".to_owned()));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn player_plays_and_seeks() {
+        let recording = Recording {
+            header: RecordingHeader { version: Recorder::VERSION, started_at: 0, targets: vec![target()] },
+            events: (1..=5)
+                .map(|n| RecordedEvent { at: n * 1000, target: "orders".to_owned(), response: fiber_count(n as i32) })
+                .collect(),
+        };
+        let mut player = Player::new(recording);
+        let mut app = App::new("test", player.targets());
+        let counts = |app: &App| app.target_states["orders"].zmx.as_ref().unwrap().fiber_counts.len();
+
+        player.advance(&mut app, Duration::from_millis(2500));
+        assert_eq!(counts(&app), 2);
+
+        player.toggle_pause();
+        player.advance(&mut app, Duration::from_secs(10));
+        assert_eq!(counts(&app), 2);
+
+        player.seek_backward(&mut app, Duration::from_secs(1));
+        assert_eq!(counts(&app), 1);
+
+        player.seek_forward(&mut app, Duration::from_secs(60));
+        assert_eq!(counts(&app), 5);
+        assert_eq!(player.position, Duration::from_secs(5));
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn replayed_alerts_fire_at_recorded_time() {
+        let recording = Recording {
+            header: RecordingHeader { version: Recorder::VERSION, started_at: 1_600_000_000_000, targets: vec![target()] },
+            events: (1..=5)
+                .map(|n| RecordedEvent { at: n * 1000, target: "orders".to_owned(), response: fiber_count(n as i32) })
+                .collect(),
+        };
+        let mut player = Player::new(recording);
+        let mut app = App::new("test", player.targets());
+        let rule = AlertRule {
+            name: "busy".to_owned(),
+            metric: AlertMetric::FibersRunning,
+            above: Some(2.0),
+            above_percent_of_max: None,
+            growing: false,
+            for_secs: 2,
+        };
+        app.alerts = Alerts::new(&AlertsConfig { rules: vec![rule], bell: false, command: None }).unwrap();
+
+        // the whole recording is played at once, but the rule still sees 2s of busy fibers
+        player.seek_forward(&mut app, Duration::from_secs(60));
+        assert_eq!(app.alerts.firing().len(), 1);
+        assert_eq!(app.alerts.history[0].time, Local.timestamp_millis(1_600_000_005_000));
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::Local;
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::akka::model::DeadLettersMetrics;
+use crate::fetcher::{Fetcher, FetcherRequest, FetcherResponse};
+use crate::jmx::model::MemoryUsage;
+use crate::target::TargetSettings;
+use crate::widgets::tree;
+
+/// The snapshot was fetched and printed.
+pub const EXIT_OK: i32 = 0;
+/// The data source couldn't be reached or returned an error.
+pub const EXIT_FETCH_FAILED: i32 = 1;
+/// The requested data can't be fetched with given options, e.g. `slick` snapshot without `--jmx` or `--db-pool-name`.
+pub const EXIT_MISCONFIGURED: i32 = 2;
+
+#[derive(Debug, StructOpt)]
+pub struct SnapshotOptions {
+    /// What to fetch
+    #[structopt(possible_values = &["fibers", "slick", "hikari", "jvm", "threads", "actors", "dead-letters"])]
+    pub kind: SnapshotKind,
+    /// Output format
+    #[structopt(long = "format", default_value = "text", possible_values = &["text", "json"])]
+    pub format: SnapshotFormat,
+    /// Name of the target to fetch from. Required when several targets are configured
+    #[structopt(long = "target")]
+    pub target: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SnapshotKind {
+    Fibers,
+    Slick,
+    Hikari,
+    Jvm,
+    Threads,
+    Actors,
+    DeadLetters,
+}
+
+impl FromStr for SnapshotKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SnapshotKind, String> {
+        match s {
+            "fibers" => Ok(SnapshotKind::Fibers),
+            "slick" => Ok(SnapshotKind::Slick),
+            "hikari" => Ok(SnapshotKind::Hikari),
+            "jvm" => Ok(SnapshotKind::Jvm),
+            "threads" => Ok(SnapshotKind::Threads),
+            "actors" => Ok(SnapshotKind::Actors),
+            "dead-letters" => Ok(SnapshotKind::DeadLetters),
+            _ => Err(format!("Unknown snapshot kind: {}", s)),
+        }
+    }
+}
+
+impl SnapshotKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SnapshotKind::Fibers => "fibers",
+            SnapshotKind::Slick => "slick",
+            SnapshotKind::Hikari => "hikari",
+            SnapshotKind::Jvm => "jvm",
+            SnapshotKind::Threads => "threads",
+            SnapshotKind::Actors => "actors",
+            SnapshotKind::DeadLetters => "dead-letters",
+        }
+    }
+
+    fn request(&self) -> FetcherRequest {
+        match self {
+            SnapshotKind::Fibers => FetcherRequest::FiberDump,
+            SnapshotKind::Slick => FetcherRequest::SlickMetrics,
+            SnapshotKind::Hikari => FetcherRequest::HikariMetrics,
+            SnapshotKind::Jvm => FetcherRequest::JvmMetrics,
+            SnapshotKind::Threads => FetcherRequest::ThreadDump,
+            SnapshotKind::Actors => FetcherRequest::ActorTree,
+            SnapshotKind::DeadLetters => FetcherRequest::DeadLetters,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SnapshotFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for SnapshotFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SnapshotFormat, String> {
+        match s {
+            "text" => Ok(SnapshotFormat::Text),
+            "json" => Ok(SnapshotFormat::Json),
+            _ => Err(format!("Unknown format: {}", s)),
+        }
+    }
+}
+
+///
+/// Fetches a single snapshot, prints it to stdout and returns the exit code.
+/// Errors are printed to stderr, so that stdout only ever contains the snapshot.
+///
+pub fn run(options: &SnapshotOptions, targets: Vec<TargetSettings>) -> i32 {
+    let target = match select_target(&targets, options.target.as_deref()) {
+        Ok(t) => t.to_owned(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_MISCONFIGURED;
+        }
+    };
+
+    let request = options.kind.request();
+    if !target.has_source(request.source()) {
+        eprintln!("Target {} has no {:?} connection options, can't fetch {:?} snapshot", target.name, request.source(), options.kind);
+        return EXIT_MISCONFIGURED;
+    }
+
+    let needs_db_pool = options.kind == SnapshotKind::Slick || options.kind == SnapshotKind::Hikari;
+    if needs_db_pool && target.jmx.as_ref().is_some_and(|j| j.db_pools.is_none()) {
+        eprintln!("Target {} has no --db-pool-name or --discover-db-pools, can't fetch {:?} snapshot", target.name, options.kind);
+        return EXIT_MISCONFIGURED;
+    }
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FETCH_FAILED;
+        }
+    };
+    let name = target.name.clone();
+    let fetcher = Fetcher::new(vec![target]);
+    let response = runtime.block_on(fetcher.fetch(&name, request));
+    runtime.shutdown_background();
+
+    match format_response(response, options.format) {
+        Ok(output) => {
+            println!("{}", output);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_FETCH_FAILED
+        }
+    }
+}
+
+///
+/// Writes data shown in the UI to a timestamped file in the given directory, e.g. `orders-fibers-20201020-154501.123.txt`,
+/// in the same format as the snapshot subcommand prints it. Returns the path of the file.
+///
+pub fn save(dir: &Path, target: &str, kind: SnapshotKind, response: FetcherResponse, format: SnapshotFormat) -> Result<PathBuf, String> {
+    let output = format_response(response, format)?;
+    let target: String = target.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let extension = match format {
+        SnapshotFormat::Text => "txt",
+        SnapshotFormat::Json => "json",
+    };
+    let file = format!("{}-{}-{}.{}", target, kind.name(), Local::now().format("%Y%m%d-%H%M%S%.3f"), extension);
+    let path = dir.join(file);
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&path, output))
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+fn select_target<'a>(targets: &'a [TargetSettings], name: Option<&str>) -> Result<&'a TargetSettings, String> {
+    match (name, targets) {
+        (Some(name), _) => targets.iter().find(|t| t.name == name)
+            .ok_or_else(|| format!("Target {} not found", name)),
+        (None, [target]) => Ok(target),
+        (None, []) => Err("Nothing to fetch from, please specify connection options".to_owned()),
+        (None, _) => Err("Several targets configured, please choose one with --target".to_owned()),
+    }
+}
+
+fn format_response(response: FetcherResponse, format: SnapshotFormat) -> Result<String, String> {
+    match (response, format) {
+        (FetcherResponse::FiberDump(r), SnapshotFormat::Json) => r.and_then(|x| to_json(&x)),
+        (FetcherResponse::FiberDump(r), SnapshotFormat::Text) => r.map(|fibers| {
+            let tree: Vec<String> = tree::tree_list_widget(fibers.clone(), true).into_iter().map(|x| x.0).collect();
+            let dumps: Vec<String> = fibers.into_iter().map(|f| f.dump).collect();
+            format!("{}\n\n{}", tree.join("\n"), dumps.join("\n\n"))
+        }),
+        (FetcherResponse::SlickMetrics(r), SnapshotFormat::Json) => r.and_then(|x| to_json(&x)),
+        (FetcherResponse::SlickMetrics(r), SnapshotFormat::Text) => r.map(|pools| {
+            by_pool(pools, |m| format!("active threads: {}\nqueue size: {}", m.active_threads, m.queue_size))
+        }),
+        (FetcherResponse::HikariMetrics(r), SnapshotFormat::Json) => r.and_then(|x| to_json(&x)),
+        (FetcherResponse::HikariMetrics(r), SnapshotFormat::Text) => r.map(|pools| {
+            by_pool(pools, |m| format!("total: {}\nactive: {}\nidle: {}\nwaiting: {}", m.total, m.active, m.idle, m.waiting))
+        }),
+        (FetcherResponse::JvmMetrics(r), SnapshotFormat::Json) => r.and_then(|x| to_json(&x)),
+        (FetcherResponse::JvmMetrics(r), SnapshotFormat::Text) => r.map(|m| {
+            let memory = |u: &MemoryUsage| format!("used {}, committed {}, max {}", u.used, u.committed, u.max);
+            let cpu = |x: Option<f64>| x.map_or("n/a".to_owned(), |x| format!("{:.1}%", x * 100.0));
+            let mut lines = vec![
+                format!("heap: {}", memory(&m.heap)),
+                format!("non-heap: {}", memory(&m.non_heap)),
+                format!("threads: {} live, {} daemon, {} peak", m.live_threads, m.daemon_threads, m.peak_threads),
+                format!("classes: {} loaded, {} unloaded", m.loaded_classes, m.unloaded_classes),
+                format!("cpu: process {}, system {}", cpu(m.process_cpu_load), cpu(m.system_cpu_load)),
+            ];
+            for gc in m.garbage_collectors {
+                lines.push(format!("gc {}: {} collections, {}ms", gc.name, gc.collection_count, gc.collection_time));
+            }
+            lines.join("\n")
+        }),
+        (FetcherResponse::ThreadDump(r), SnapshotFormat::Json) => r.and_then(|x| to_json(&x)),
+        (FetcherResponse::ThreadDump(r), SnapshotFormat::Text) => r.map(|d| {
+            d.threads.iter().map(|t| t.dump(d.is_deadlocked(t))).collect::<Vec<String>>().join("\n\n")
+        }),
+        (FetcherResponse::ActorTree(r), SnapshotFormat::Json) => r.and_then(|x| to_json(&x)),
+        (FetcherResponse::ActorTree(r), SnapshotFormat::Text) => r.map(|actors| {
+            tree::tree_list_widget(actors, false).into_iter().map(|x| x.0).collect::<Vec<String>>().join("\n")
+        }),
+        (FetcherResponse::DeadLetters(r), SnapshotFormat::Json) =>
+            r.and_then(|(snapshot, window)| to_json(&DeadLettersMetrics { snapshot, window })),
+        (FetcherResponse::DeadLetters(r), SnapshotFormat::Text) => r.map(|(snapshot, window)| {
+            let mut lines = vec![
+                format!("within last {}ms:", window.within_millis),
+                format!("  dead letters: {}", window.dead_letters.count),
+                format!("  unhandled: {}", window.unhandled.count),
+                format!("  dropped: {}", window.dropped.count),
+            ];
+            let messages = snapshot.dead_letters.iter().map(|x| x.value.to_ui(x.timestamp))
+                .chain(snapshot.unhandled.iter().map(|x| x.value.to_ui(x.timestamp)))
+                .chain(snapshot.dropped.iter().map(|x| x.value.to_ui(x.timestamp)));
+            for m in messages {
+                lines.push(format!("{} {} -> {}: {}", m.readable_timestamp(), m.sender, m.recipient, m.message));
+            }
+            lines.join("\n")
+        }),
+        (r, _) => Err(format!("Unexpected response to {:?} request", r.request())),
+    }
+}
+
+/// Metrics of every pool, under the pool name.
+fn by_pool<T, F>(pools: BTreeMap<String, T>, format: F) -> String
+    where F: Fn(&T) -> String, {
+    pools.iter()
+        .map(|(pool, m)| {
+            let lines: Vec<String> = format(m).lines().map(|l| format!("  {}", l)).collect();
+            format!("pool {}:\n{}", pool, lines.join("\n"))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetcher::FetcherResponse;
+    use crate::jmx::model::HikariMetrics;
+    use crate::snapshot::{format_response, select_target, SnapshotFormat};
+    use crate::target::TargetSettings;
+    use crate::zio::model::{Fiber, FiberStatus};
+
+    #[test]
+    fn snapshot_formats_responses() {
+        let hikari = vec![
+            ("orders-read".to_owned(), HikariMetrics { total: 10, active: 2, idle: 8, waiting: 0 }),
+            ("orders-write".to_owned(), HikariMetrics { total: 5, active: 5, idle: 0, waiting: 3 }),
+        ];
+        assert_eq!(
+            format_response(FetcherResponse::HikariMetrics(Ok(hikari.into_iter().collect())), SnapshotFormat::Text),
+            Ok("pool orders-read:\n  total: 10\n  active: 2\n  idle: 8\n  waiting: 0\n\
+                pool orders-write:\n  total: 5\n  active: 5\n  idle: 0\n  waiting: 3".to_owned())
+        );
+
+        let fiber = Fiber::new(1, None, FiberStatus::Running, "#1".to_owned());
+        let json = format_response(FetcherResponse::FiberDump(Ok(vec![fiber])), SnapshotFormat::Json).unwrap();
+        assert!(json.contains("\"status\": \"Running\""));
+
+        assert_eq!(
+            format_response(FetcherResponse::SlickMetrics(Err("connection refused".to_owned())), SnapshotFormat::Json),
+            Err("connection refused".to_owned())
+        );
+    }
+
+    #[test]
+    fn snapshot_requires_target_name_for_several_targets() {
+        let target = |name: &str| TargetSettings { name: name.to_owned(), zio_zmx: Some("localhost:6789".to_owned()), jmx: None, akka: None };
+
+        assert!(select_target(&[], None).is_err());
+        assert_eq!(select_target(&[target("orders")], None).map(|t| t.name.as_str()), Ok("orders"));
+        assert!(select_target(&[target("orders"), target("payments")], None).is_err());
+        assert_eq!(select_target(&[target("orders"), target("payments")], Some("payments")).map(|t| t.name.as_str()), Ok("payments"));
+        assert!(select_target(&[target("orders")], Some("payments")).is_err());
+    }
+}
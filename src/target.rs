@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+use crate::akka::model::AkkaSettings;
+use crate::health::Source;
+use crate::jmx::model::JMXConnectionSettings;
+
+/// Everything panopticon needs to know to monitor a single app instance.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TargetSettings {
+    pub name: String,
+    pub zio_zmx: Option<String>,
+    pub jmx: Option<JMXConnectionSettings>,
+    pub akka: Option<AkkaSettings>,
+}
+
+impl TargetSettings {
+    pub fn is_empty(&self) -> bool {
+        self.zio_zmx.is_none() && self.jmx.is_none() && self.akka.is_none()
+    }
+
+    pub fn has_source(&self, source: Source) -> bool {
+        match source {
+            Source::Zmx => self.zio_zmx.is_some(),
+            Source::Jmx => self.jmx.is_some(),
+            Source::Akka => self.akka.is_some(),
+        }
+    }
+}
@@ -9,43 +9,173 @@ use tui::{
     style::{Color, Modifier, Style},
     symbols::Marker,
     Terminal,
-    widgets::{Axis, BarChart, Block, Borders, Chart, Dataset, List, Paragraph, Tabs},
+    widgets::{Axis, BarChart, Block, Borders, Chart, Dataset, GraphType, List, Paragraph, Tabs},
 };
-use tui::text::Span;
+use tui::text::{Span, Spans};
 use tui::widgets::{ListItem, Wrap};
 
 use crate::akka::model::DeadLettersWindow;
-use crate::app::{AkkaTab, App, AppTabKind, SlickTab, ZMXTab};
-use crate::jmx::model::HikariMetrics;
+use crate::alerts::Alerts;
+use crate::app::{AkkaTab, App, AppTabKind, DbPool, JvmTab, MBeansTab, SlickTab, ThreadsTab, ZMXTab};
+use crate::health::{HealthStatus, SourceHealth};
+use crate::jmx::model::{HikariMetrics, HikariTimings, JvmMetrics, MemoryUsage, Percentiles};
 use crate::zio::model::FiberCount;
 
 pub fn draw<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(), io::Error> {
-    terminal.draw(|mut f| {
+    terminal.draw(|f| {
+        let multi_target = app.targets.tabs.len() > 1;
+        let mut constraints = vec![Constraint::Length(3), Constraint::Min(0)];
+        if multi_target {
+            constraints.insert(0, Constraint::Length(3));
+        }
+        if app.alerts.has_rules() {
+            constraints.push(Constraint::Length(1));
+        }
         let chunks = Layout::default()
-            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .constraints(constraints)
             .split(f.size());
-        let tabs = app.tabs.to_owned();
-        let titles = tabs.titles();
+
+        if app.alerts.has_rules() {
+            draw_alert_status(f, &app.alerts, chunks[chunks.len() - 1]);
+        }
+
+        if multi_target {
+            draw_target_selector(f, app, chunks[0]);
+        }
+        let (tabs_area, body_area) = if multi_target { (chunks[1], chunks[2]) } else { (chunks[0], chunks[1]) };
+        let show_alert_history = app.show_alert_history;
+        if show_alert_history {
+            draw_alert_history(f, &app.alerts, body_area);
+        }
+
+        let title = if multi_target { app.current_target().to_owned() } else { header_title(app) };
+        let target = app.current_mut();
+        let tabs = target.tabs.to_owned();
+        let titles: Vec<Spans> = tabs.tabs.iter()
+            .map(|t| {
+                let status = target.health(t.kind.source()).map_or(HealthStatus::Connecting, |h| h.status());
+                Spans(vec![Span::raw(&t.title), Span::styled(" ●", Style::default().fg(health_color(status)))])
+            })
+            .collect();
+        let health = target.health(tabs.current().kind.source()).cloned();
         let tabs_widget = Tabs::new(titles)
             .block(Block::default()
                 .borders(Borders::ALL)
-                .title(Span::styled(app.title, Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD))))
+                .title(Span::styled(title, Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD))))
             .style(Style::default().fg(Color::Green))
             .highlight_style(Style::default().fg(Color::Yellow))
             .select(tabs.index);
-        f.render_widget(tabs_widget, chunks[0]);
+        f.render_widget(tabs_widget, tabs_area);
+        let health = health.as_ref();
+        if show_alert_history {
+            return;
+        }
         match tabs.current().kind {
-            AppTabKind::ZMX => &app.zmx.as_mut().map(|mut t| draw_zio_tab(&mut f, &mut t, chunks[1])),
-            AppTabKind::Slick => &app.slick.as_ref().map(|t| draw_slick_tab(&mut f, t, chunks[1])),
-            AppTabKind::Akka => &app.akka.as_mut().map(|t| draw_akka_tab(&mut f, t, chunks[1])),
+            AppTabKind::Zmx => { if let Some(t) = target.zmx.as_mut() { draw_zio_tab(f, t, health, body_area) } }
+            AppTabKind::Slick => { if let Some(t) = target.slick.as_ref() { draw_slick_tab(f, t, health, body_area) } }
+            AppTabKind::Jvm => { if let Some(t) = target.jvm.as_ref() { draw_jvm_tab(f, t, health, body_area) } }
+            AppTabKind::Threads => { if let Some(t) = target.threads.as_mut() { draw_threads_tab(f, t, health, body_area) } }
+            AppTabKind::Akka => { if let Some(t) = target.akka.as_mut() { draw_akka_tab(f, t, health, body_area) } }
+            AppTabKind::MBeans => { if let Some(t) = target.mbeans.as_mut() { draw_mbeans_tab(f, t, health, body_area) } }
         };
     })
 }
 
-fn draw_text<B>(f: &mut Frame<B>, area: Rect)
+/// Single line below everything else, listing firing alerts.
+fn draw_alert_status<B>(f: &mut Frame<B>, alerts: &Alerts, area: Rect)
+    where B: Backend,
+{
+    let firing = alerts.firing();
+    let text = if firing.is_empty() {
+        Spans(vec![Span::styled("No alerts firing (<H> alert history)", Style::default().fg(Color::Green))])
+    } else {
+        let alerts: Vec<String> = firing.iter().map(|a| a.to_string()).collect();
+        Spans(vec![
+            Span::styled(format!(" {} FIRING ", firing.len()), Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD)),
+            Span::styled(format!(" {} (<H> alert history)", alerts.join(" | ")), Style::default().fg(Color::Red)),
+        ])
+    };
+    f.render_widget(Paragraph::new(text), area);
+}
+
+fn draw_alert_history<B>(f: &mut Frame<B>, alerts: &Alerts, area: Rect)
+    where B: Backend,
+{
+    let items: Vec<ListItem> = alerts.history.iter()
+        .map(|e| {
+            let (label, color) = if e.fired { ("FIRED   ", Color::Red) } else { ("RESOLVED", Color::Green) };
+            ListItem::new(Spans(vec![
+                Span::raw(format!("{} ", e.time.format("%Y-%m-%d %H:%M:%S"))),
+                Span::styled(label, Style::default().fg(color).add_modifier(Modifier::BOLD)),
+                Span::raw(format!(" {}", e.alert)),
+            ]))
+        })
+        .collect();
+    let history = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Alert history, most recent first (<H> to close)"));
+    f.render_widget(history, area);
+}
+
+fn draw_target_selector<B>(f: &mut Frame<B>, app: &App, area: Rect)
     where B: Backend,
 {
-    let p = Paragraph::new("")
+    let titles: Vec<Spans> = app.targets.tabs.iter()
+        .map(|t| {
+            let status = app.target_states[&t.kind].status();
+            Spans(vec![Span::raw(&t.title), Span::styled(" ●", Style::default().fg(health_color(status)))])
+        })
+        .collect();
+    let targets_widget = Tabs::new(titles)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(
+                format!("{} (<Tab>/<Shift+Tab> to switch target)", header_title(app)),
+                Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+            )))
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+        .select(app.targets.index);
+    f.render_widget(targets_widget, area);
+}
+
+fn header_title(app: &App) -> String {
+    let title = match &app.replay_status {
+        Some(status) => format!("{} [{}]", app.title, status),
+        None => app.title.to_owned(),
+    };
+    let title = if app.auto_refresh {
+        format!("{} (auto-refresh every {})", title, format_duration(app.refresh_interval))
+    } else {
+        title
+    };
+    match &app.notice {
+        Some(notice) => format!("{} | {}", title, notice),
+        None => title,
+    }
+}
+
+fn health_color(status: HealthStatus) -> Color {
+    match status {
+        HealthStatus::Connecting => Color::Gray,
+        HealthStatus::Connected => Color::Green,
+        HealthStatus::Degraded => Color::Yellow,
+        HealthStatus::Down => Color::Red,
+    }
+}
+
+/// Bottom bar of every tab, showing the health of its data source.
+fn draw_text<B>(f: &mut Frame<B>, health: Option<&SourceHealth>, area: Rect)
+    where B: Backend,
+{
+    let text = match health {
+        Some(h) if h.status() == HealthStatus::Degraded || h.status() == HealthStatus::Down => Spans(vec![
+            Span::styled(h.summary(), Style::default().fg(health_color(h.status())).add_modifier(Modifier::BOLD)),
+            Span::raw(format!(": {}", h.last_error.as_deref().unwrap_or("").replace("\r\n", " "))),
+        ]),
+        Some(h) => Spans(vec![Span::styled(h.summary(), Style::default().fg(health_color(h.status())))]),
+        None => Spans(vec![]),
+    };
+    let p = Paragraph::new(text)
         .block(
             Block::default()
                 .borders(Borders::ALL)
@@ -55,20 +185,42 @@ fn draw_text<B>(f: &mut Frame<B>, area: Rect)
     f.render_widget(p, area);
 }
 
-fn draw_slick_tab<B>(f: &mut Frame<B>, slick: &SlickTab, area: Rect)
+fn draw_slick_tab<B>(f: &mut Frame<B>, slick: &SlickTab, health: Option<&SourceHealth>, area: Rect)
     where B: Backend,
 {
     let chunks = Layout::default()
-        .constraints([Constraint::Min(7), Constraint::Length(3)].as_ref())
+        .constraints([Constraint::Length(3), Constraint::Min(7), Constraint::Length(3)].as_ref())
         .split(area);
 
-    draw_database_graphs(f, slick, chunks[0]);
-    draw_text(f, chunks[1]);
+    let pools = Tabs::new(slick.pools.titles())
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title("Pools (<A> left pool, <D> right pool)"))
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().fg(Color::Blue))
+        .select(slick.pools.index);
+    f.render_widget(pools, chunks[0]);
+    match slick.current_pool() {
+        Some(pool) => draw_database_graphs(f, pool, chunks[1]),
+        None => draw_database_graphs(f, &DbPool::new(), chunks[1]),
+    }
+    draw_text(f, health, chunks[2]);
 }
 
-fn draw_database_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
+fn draw_database_graphs<B>(f: &mut Frame<B>, db: &DbPool, area: Rect)
     where B: Backend,
 {
+    let area = match db.hikari_timings.as_ref().filter(|_| db.has_hikari) {
+        Some(timings) => {
+            let chunks = Layout::default()
+                .constraints([Constraint::Min(7), Constraint::Length(9)].as_ref())
+                .split(area);
+            draw_hikari_timings(f, db, timings, chunks[1]);
+            chunks[0]
+        }
+        None => area,
+    };
+
     let constraints: Vec<Constraint> = if db.has_hikari {
         vec![Constraint::Percentage(50), Constraint::Percentage(50)]
     } else {
@@ -76,7 +228,7 @@ fn draw_database_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
     };
 
     let chunks = Layout::default()
-        .constraints(constraints.as_ref())
+        .constraints(constraints)
         .direction(Direction::Horizontal)
         .split(area);
     {
@@ -87,7 +239,7 @@ fn draw_database_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
     }
 }
 
-fn draw_slick_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
+fn draw_slick_graphs<B>(f: &mut Frame<B>, db: &DbPool, area: Rect)
     where B: Backend,
 {
     let chunks = Layout::default()
@@ -137,14 +289,14 @@ fn draw_slick_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
     f.render_widget(slick_queue_bc, chunks[1]);
 }
 
-fn hikari_chart<F>(db: &SlickTab, f: F) -> Vec<(f64, f64)>
+fn hikari_chart<F>(db: &DbPool, f: F) -> Vec<(f64, f64)>
     where F: Fn(&HikariMetrics) -> i32, {
     db.hikari_metrics.iter().enumerate()
         .map(|(i, x)| (i as f64, f(x) as f64))
         .collect()
 }
 
-fn draw_hikari_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
+fn draw_hikari_graphs<B>(f: &mut Frame<B>, db: &DbPool, area: Rect)
     where B: Backend,
 {
     let total_chart: Vec<(f64, f64)> = hikari_chart(db, |x| x.total);
@@ -152,7 +304,18 @@ fn draw_hikari_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
     let idle_chart: Vec<(f64, f64)> = hikari_chart(db, |x| x.idle);
     let waiting_chart: Vec<(f64, f64)> = hikari_chart(db, |x| x.waiting);
 
+    let max_pool_size = db.hikari_config.as_ref().map(|c| c.maximum_pool_size);
+    let max_chart: Vec<(f64, f64)> = max_pool_size.iter()
+        .flat_map(|&m| vec![(0.0, m as f64), (SlickTab::MAX_HIKARI_MEASURES as f64, m as f64)])
+        .collect();
+
     let datasets = vec![
+        Dataset::default()
+            .name("max")
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::DarkGray))
+            .data(&max_chart),
         Dataset::default()
             .name("total")
             .marker(Marker::Braille)
@@ -175,18 +338,23 @@ fn draw_hikari_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
             .data(&idle_chart)
     ];
 
-    let max_connections = db.hikari_metrics.back().map_or(99, |x| x.total);
+    let max_connections = db.hikari_metrics.back().map_or(99, |x| x.total).max(max_pool_size.unwrap_or(0));
     let total_connections = db.hikari_metrics.back().map_or(0, |x| x.total);
     let active_connections = db.hikari_metrics.back().map_or(0, |x| x.active);
     let waiting_connections = db.hikari_metrics.back().map_or(0, |x| x.waiting);
     let idle_connections = db.hikari_metrics.back().map_or(0, |x| x.idle);
 
+    let config = match db.hikari_config.as_ref() {
+        Some(c) => format!(", max={}, min idle={}", c.maximum_pool_size, c.minimum_idle),
+        None => "".to_owned(),
+    };
     let title = format!(
-        "HikariCP (total={}, active={}, idle={}, waiting={})",
+        "HikariCP (total={}, active={}, idle={}, waiting={}{})",
         total_connections,
         active_connections,
         idle_connections,
-        waiting_connections
+        waiting_connections,
+        config
     );
     let label = vec!["0".to_owned(), ((max_connections as f64) / 2.0).to_string(), max_connections.to_string()];
     let c = Chart::new(datasets)
@@ -213,15 +381,290 @@ fn draw_hikari_graphs<B>(f: &mut Frame<B>, db: &SlickTab, area: Rect)
     f.render_widget(c, area);
 }
 
+fn draw_hikari_timings<B>(f: &mut Frame<B>, db: &DbPool, timings: &HikariTimings, area: Rect)
+    where B: Backend,
+{
+    let config = db.hikari_config.as_ref();
+    let timeout = config.map_or("".to_owned(), |c| format!(", timeout: {}", c.connection_timeout));
+    let leak_detection = config
+        .filter(|c| c.leak_detection_threshold > 0)
+        .map_or("".to_owned(), |c| format!(", leak detection: {}", c.leak_detection_threshold));
+    let panels: Vec<(&str, &str, &Percentiles)> = vec![
+        ("Connection wait", timeout.as_str(), timings.wait.as_ref()),
+        ("Connection usage", leak_detection.as_str(), timings.usage.as_ref()),
+        ("Connection creation", "", timings.creation.as_ref()),
+    ].into_iter()
+        .filter_map(|(title, limit, p)| p.map(|p| (title, limit, p)))
+        .collect();
+
+    let chunks = Layout::default()
+        .constraints(vec![Constraint::Ratio(1, panels.len() as u32); panels.len()])
+        .direction(Direction::Horizontal)
+        .split(area);
+    for ((title, limit, p), chunk) in panels.iter().zip(chunks) {
+        let title = format!("{} in ms (mean: {:.1}{})", title, p.mean, limit);
+        let data = [("p50", p.p50), ("p75", p.p75), ("p95", p.p95), ("p99", p.p99), ("max", p.max)];
+        let data: Vec<(&str, u64)> = data.iter().map(|(l, v)| (*l, v.round() as u64)).collect();
+        let bc = BarChart::default()
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .title(Span::styled(&title, Style::default().fg(Color::Cyan))))
+            .data(&data)
+            .bar_width(5)
+            .bar_gap(1)
+            .value_style(
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Magenta)
+            )
+            .style(Style::default().fg(Color::Magenta));
+        f.render_widget(bc, chunk);
+    }
+}
 
-fn draw_zio_tab<B>(f: &mut Frame<B>, zmx: &mut ZMXTab, area: Rect)
+fn draw_jvm_tab<B>(f: &mut Frame<B>, tab: &JvmTab, health: Option<&SourceHealth>, area: Rect)
+    where B: Backend,
+{
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(7), Constraint::Length(3)].as_ref())
+        .split(area);
+    {
+        let chunks = Layout::default()
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(chunks[0]);
+        {
+            let chunks = Layout::default()
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .direction(Direction::Horizontal)
+                .split(chunks[0]);
+            draw_memory_chart(f, tab, "Heap", |m| &m.heap, chunks[0]);
+            draw_memory_chart(f, tab, "Non-heap", |m| &m.non_heap, chunks[1]);
+        }
+        {
+            let chunks = Layout::default()
+                .constraints([Constraint::Percentage(35), Constraint::Percentage(30), Constraint::Percentage(35)].as_ref())
+                .direction(Direction::Horizontal)
+                .split(chunks[1]);
+            draw_threads_chart(f, tab, chunks[0]);
+            draw_cpu_chart(f, tab, chunks[1]);
+            draw_gc_and_classes(f, tab, chunks[2]);
+        }
+    }
+    draw_text(f, health, chunks[1]);
+}
+
+fn jvm_chart<F>(tab: &JvmTab, f: F) -> Vec<(f64, f64)>
+    where F: Fn(&JvmMetrics) -> Option<f64>, {
+    tab.jvm_metrics.iter().enumerate()
+        .filter_map(|(i, x)| f(x).map(|v| (i as f64, v)))
+        .collect()
+}
+
+/// A line chart of the last `JvmTab::MAX_JVM_MEASURES` measures, with y axis from 0 to `max`.
+fn jvm_history_chart<'a>(title: String, datasets: Vec<Dataset<'a>>, max: f64, label: fn(f64) -> String) -> Chart<'a> {
+    let labels = vec![label(0.0), label(max / 2.0), label(max)];
+    Chart::new(datasets)
+        .block(
+            Block::default()
+                .title(Span::styled(title, Style::default().fg(Color::Cyan)))
+                .borders(Borders::ALL)
+        )
+        .x_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(vec![
+                    Span::styled("older", Style::default().add_modifier(Modifier::ITALIC)),
+                    Span::styled("recent", Style::default().add_modifier(Modifier::ITALIC))
+                ])
+                .bounds([0.0, JvmTab::MAX_JVM_MEASURES as f64])
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(labels.into_iter().map(|l| Span::styled(l, Style::default().add_modifier(Modifier::ITALIC))).collect())
+                .bounds([0.0, max])
+        )
+}
+
+fn draw_memory_chart<B, F>(f: &mut Frame<B>, tab: &JvmTab, name: &str, usage: F, area: Rect)
+    where B: Backend,
+          F: Fn(&JvmMetrics) -> &MemoryUsage,
+{
+    let used_chart = jvm_chart(tab, |m| Some(usage(m).used as f64));
+    let committed_chart = jvm_chart(tab, |m| Some(usage(m).committed as f64));
+    let datasets = vec![
+        Dataset::default()
+            .name("used")
+            .marker(Marker::Braille)
+            .style(Style::default().fg(Color::Green))
+            .data(&used_chart),
+        Dataset::default()
+            .name("committed")
+            .marker(Marker::Braille)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&committed_chart),
+    ];
+
+    let max = committed_chart.iter().chain(used_chart.iter()).map(|(_, v)| *v).fold(1.0, f64::max);
+    let title = match tab.jvm_metrics.back().map(&usage) {
+        Some(u) => format!(
+            "{} (used={}, committed={}, max={})",
+            name,
+            format_bytes(u.used as f64),
+            format_bytes(u.committed as f64),
+            if u.max < 0 { "undefined".to_owned() } else { format_bytes(u.max as f64) }
+        ),
+        None => name.to_owned(),
+    };
+    f.render_widget(jvm_history_chart(title, datasets, max, format_bytes), area);
+}
+
+fn draw_threads_chart<B>(f: &mut Frame<B>, tab: &JvmTab, area: Rect)
+    where B: Backend,
+{
+    let live_chart = jvm_chart(tab, |m| Some(m.live_threads as f64));
+    let daemon_chart = jvm_chart(tab, |m| Some(m.daemon_threads as f64));
+    let datasets = vec![
+        Dataset::default()
+            .name("live")
+            .marker(Marker::Braille)
+            .style(Style::default().fg(Color::Green))
+            .data(&live_chart),
+        Dataset::default()
+            .name("daemon")
+            .marker(Marker::Braille)
+            .style(Style::default().fg(Color::LightBlue))
+            .data(&daemon_chart),
+    ];
+
+    let max = tab.jvm_metrics.iter().map(|m| m.peak_threads).max().unwrap_or(0) + 1;
+    let title = match tab.jvm_metrics.back() {
+        Some(m) => format!("Threads (live={}, daemon={}, peak={})", m.live_threads, m.daemon_threads, m.peak_threads),
+        None => "Threads".to_owned(),
+    };
+    f.render_widget(jvm_history_chart(title, datasets, max as f64, |v| format!("{}", v.round())), area);
+}
+
+fn draw_cpu_chart<B>(f: &mut Frame<B>, tab: &JvmTab, area: Rect)
+    where B: Backend,
+{
+    let process_chart = jvm_chart(tab, |m| m.process_cpu_load.map(|x| x * 100.0));
+    let system_chart = jvm_chart(tab, |m| m.system_cpu_load.map(|x| x * 100.0));
+    let datasets = vec![
+        Dataset::default()
+            .name("process")
+            .marker(Marker::Braille)
+            .style(Style::default().fg(Color::Green))
+            .data(&process_chart),
+        Dataset::default()
+            .name("system")
+            .marker(Marker::Braille)
+            .style(Style::default().fg(Color::Yellow))
+            .data(&system_chart),
+    ];
+
+    let percent = |x: Option<f64>| x.map_or("n/a".to_owned(), |x| format!("{:.1}%", x * 100.0));
+    let title = match tab.jvm_metrics.back() {
+        Some(m) => format!("CPU (process={}, system={})", percent(m.process_cpu_load), percent(m.system_cpu_load)),
+        None => "CPU".to_owned(),
+    };
+    f.render_widget(jvm_history_chart(title, datasets, 100.0, |v| format!("{}%", v)), area);
+}
+
+fn draw_gc_and_classes<B>(f: &mut Frame<B>, tab: &JvmTab, area: Rect)
+    where B: Backend,
+{
+    let mut lines: Vec<Spans> = vec![];
+    if let Some(m) = tab.jvm_metrics.back() {
+        let recent = tab.recent_gc_activity();
+        for gc in &m.garbage_collectors {
+            let (count, time) = recent.iter().find(|(name, _, _)| name == &gc.name).map_or((0, 0), |(_, c, t)| (*c, *t));
+            lines.push(Spans(vec![Span::styled(gc.name.to_owned(), Style::default().add_modifier(Modifier::BOLD))]));
+            lines.push(Spans(vec![Span::raw(format!(
+                "  {} collections, {} total; last tick: +{}, {}ms",
+                gc.collection_count,
+                format_duration(std::time::Duration::from_millis(gc.collection_time as u64)),
+                count,
+                time
+            ))]));
+        }
+        lines.push(Spans(vec![Span::raw("")]));
+        lines.push(Spans(vec![Span::raw(format!("Classes: {} loaded, {} unloaded", m.loaded_classes, m.unloaded_classes))]));
+    }
+
+    let p = Paragraph::new(lines)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled("Garbage collectors", Style::default().fg(Color::Cyan))))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, area);
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+fn draw_threads_tab<B>(f: &mut Frame<B>, tab: &mut ThreadsTab, health: Option<&SourceHealth>, area: Rect)
+    where B: Backend,
+{
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(7), Constraint::Length(3)].as_ref())
+        .split(area);
+    {
+        let chunks = Layout::default()
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+            .direction(Direction::Horizontal)
+            .split(chunks[0]);
+
+        let items: Vec<ListItem> = tab.threads.items.iter()
+            .map(|t| {
+                let style = if t.deadlocked { Style::default().fg(Color::Red) } else { Style::default() };
+                ListItem::new(t.label.to_owned()).style(style)
+            })
+            .collect();
+        let title = match &tab.thread_dump {
+            Some(d) if tab.deadlocked_count() > 0 =>
+                format!("Threads: {}, {} DEADLOCKED (<G> to toggle grouping)", d.threads.len(), tab.deadlocked_count()),
+            Some(d) => format!("Threads: {} (<G> to toggle grouping)", d.threads.len()),
+            None => "Threads (press <Enter> to take a thread dump)".to_owned(),
+        };
+        let title_color = if tab.deadlocked_count() > 0 { Color::Red } else { Color::Cyan };
+        let list = List::new(items)
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .title(Span::styled(title, Style::default().fg(title_color))))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            .highlight_symbol(">");
+        f.render_stateful_widget(list, chunks[0], &mut tab.threads.state);
+
+        let p = Paragraph::new(tab.selected_dump().to_owned())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(Span::styled("Stack trace (press <PageUp>/<PageDown> to scroll)", Style::default().fg(Color::Cyan)))
+            )
+            .wrap(Wrap { trim: false })
+            .scroll((tab.scroll, 0));
+        f.render_widget(p, chunks[1]);
+    }
+    draw_text(f, health, chunks[1]);
+}
+
+fn draw_zio_tab<B>(f: &mut Frame<B>, zmx: &mut ZMXTab, health: Option<&SourceHealth>, area: Rect)
     where B: Backend,
 {
     let chunks = Layout::default()
         .constraints([Constraint::Min(7), Constraint::Length(3)].as_ref())
         .split(area);
     draw_fiber_list(f, zmx, chunks[0]);
-    draw_text(f, chunks[1]);
+    draw_text(f, health, chunks[1]);
 }
 
 fn fiber_count_chart<F>(db: &ZMXTab, f: F) -> Vec<(f64, f64)>
@@ -265,7 +708,7 @@ fn draw_fiber_list<B>(f: &mut Frame<B>, zmx: &mut ZMXTab, area: Rect)
                 let list = List::new(items)
                     .block(Block::default()
                         .borders(Borders::ALL)
-                        .title(Span::styled("Fibers (press <Enter> to take a snapshot)", Style::default().fg(Color::Cyan))))
+                        .title(Span::styled(fiber_list_title(zmx), Style::default().fg(Color::Cyan))))
                     .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
                     .highlight_symbol(">");
                 f.render_stateful_widget(list, chunks[0], &mut zmx.fibers.state);
@@ -338,7 +781,8 @@ fn draw_fiber_list<B>(f: &mut Frame<B>, zmx: &mut ZMXTab, area: Rect)
                 f.render_widget(c, chunks[1]);
             }
 
-            let p = Paragraph::new(zmx.selected_fiber_dump.0.to_owned())
+            let highlight = Style::default().fg(Color::Black).bg(Color::Yellow);
+            let p = Paragraph::new(highlight_matches(&zmx.selected_fiber_dump.0, &zmx.filter.query, highlight))
                 .block(
                     Block::default()
                         .borders(Borders::ALL)
@@ -351,7 +795,66 @@ fn draw_fiber_list<B>(f: &mut Frame<B>, zmx: &mut ZMXTab, area: Rect)
     }
 }
 
-fn draw_akka_tab<B>(f: &mut Frame<B>, tab: &mut AkkaTab, area: Rect)
+fn fiber_list_title(zmx: &ZMXTab) -> String {
+    if zmx.searching {
+        return format!("Search: {}_ (<Enter> to apply, <Esc> to clear)", zmx.filter.query);
+    }
+    if zmx.show_diff && zmx.snapshots.len() < 2 {
+        return "Snapshot diff (take one more snapshot with <Enter>)".to_owned();
+    }
+    let mut applied: Vec<String> = vec![];
+    if !zmx.filter.query.trim().is_empty() {
+        applied.push(format!("/{}", zmx.filter.query.trim()));
+    }
+    if zmx.filter.hide_done {
+        applied.push("hide done".to_owned());
+    }
+    if zmx.filter.only_suspended {
+        applied.push("only suspended".to_owned());
+    }
+    if zmx.sort_by_age && !zmx.show_diff {
+        applied.push("oldest first".to_owned());
+    }
+    if zmx.show_diff {
+        applied.push(format!("diff, {} snapshots kept", zmx.snapshots.len()));
+    } else if zmx.group_by_site {
+        applied.push("by suspension site".to_owned());
+    }
+    if applied.is_empty() {
+        "Fibers (press <Enter> to take a snapshot)".to_owned()
+    } else if zmx.filter.is_empty() {
+        format!("Fibers ({})", applied.join(", "))
+    } else {
+        format!("Fibers {} of {} ({})", zmx.fiber_ids.iter().flatten().count(), zmx.latest_fibers().len(), applied.join(", "))
+    }
+}
+
+/// Splits text into lines, marking every occurrence of the query, ignoring case.
+fn highlight_matches<'a>(text: &'a str, query: &str, style: Style) -> Vec<Spans<'a>> {
+    let query = query.trim().to_ascii_lowercase();
+    text.lines().map(|line| {
+        if query.is_empty() {
+            return Spans::from(line);
+        }
+        let lower = line.to_ascii_lowercase();
+        let mut spans = vec![];
+        let mut from = 0;
+        while let Some(n) = lower[from..].find(&query) {
+            let (start, end) = (from + n, from + n + query.len());
+            if start > from {
+                spans.push(Span::raw(&line[from..start]));
+            }
+            spans.push(Span::styled(&line[start..end], style));
+            from = end;
+        }
+        if from < line.len() {
+            spans.push(Span::raw(&line[from..]));
+        }
+        Spans(spans)
+    }).collect()
+}
+
+fn draw_akka_tab<B>(f: &mut Frame<B>, tab: &mut AkkaTab, health: Option<&SourceHealth>, area: Rect)
     where B: Backend,
 {
     let chunks = Layout::default()
@@ -363,11 +866,12 @@ fn draw_akka_tab<B>(f: &mut Frame<B>, tab: &mut AkkaTab, area: Rect)
             .split(chunks[0]);
         {
             let chunks = Layout::default()
-                .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
+                .constraints([Constraint::Percentage(30), Constraint::Percentage(30), Constraint::Percentage(40)].as_ref())
                 .direction(Direction::Horizontal)
                 .split(chunks[0]);
             draw_actor_tree(f, tab, chunks[0]);
-            draw_actor_count_chart(f, tab, chunks[1]);
+            draw_actor_details(f, tab, chunks[1]);
+            draw_actor_count_chart(f, tab, chunks[2]);
         }
         {
             let chunks = Layout::default()
@@ -378,7 +882,7 @@ fn draw_akka_tab<B>(f: &mut Frame<B>, tab: &mut AkkaTab, area: Rect)
             draw_dead_letters_window_chart(f, tab, chunks[1]);
         }
     }
-    draw_text(f, chunks[1]);
+    draw_text(f, health, chunks[1]);
 }
 
 fn draw_dead_letters_logs<B>(f: &mut Frame<B>, tab: &mut AkkaTab, area: Rect)
@@ -502,18 +1006,30 @@ fn draw_dead_letters_window_chart<B>(f: &mut Frame<B>, tab: &mut AkkaTab, area:
 fn draw_actor_tree<B>(f: &mut Frame<B>, tab: &mut AkkaTab, area: Rect)
     where B: Backend,
 {
-    let items: Vec<ListItem<'_>> = tab.actors.items.iter().map(|i| ListItem::new(i.to_owned())).collect();
+    let items: Vec<ListItem<'_>> = tab.actors.rows().iter().map(|i| ListItem::new(i.0.to_owned())).collect();
 
     let list = List::new(items)
         .block(Block::default()
             .borders(Borders::ALL)
-            .title(Span::styled("Actors (<Enter> to reload, <PageUp>/<PageDown> to scroll)", Style::default().fg(Color::Cyan))))
+            .title(Span::styled("Actors (<Enter> to reload, <PageUp>/<PageDown> to scroll, <f> to fold)", Style::default().fg(Color::Cyan))))
         .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
         .highlight_symbol(">");
 
     f.render_stateful_widget(list, area, &mut tab.actors.state);
 }
 
+fn draw_actor_details<B>(f: &mut Frame<B>, tab: &AkkaTab, area: Rect)
+    where B: Backend,
+{
+    let text = tab.actor_details().unwrap_or_else(|| "Select an actor with <PageUp>/<PageDown>".to_owned());
+    let p = Paragraph::new(text)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled("Actor", Style::default().fg(Color::Cyan))))
+        .wrap(Wrap { trim: false });
+    f.render_widget(p, area);
+}
+
 fn draw_actor_count_chart<B>(f: &mut Frame<B>, tab: &AkkaTab, area: Rect)
     where B: Backend,
 {
@@ -542,3 +1058,104 @@ fn draw_actor_count_chart<B>(f: &mut Frame<B>, tab: &AkkaTab, area: Rect)
         .style(Style::default().fg(Color::Green));
     f.render_widget(count_bc, area);
 }
+
+fn draw_mbeans_tab<B>(f: &mut Frame<B>, tab: &mut MBeansTab, health: Option<&SourceHealth>, area: Rect)
+    where B: Backend,
+{
+    let chunks = Layout::default()
+        .constraints([Constraint::Min(7), Constraint::Length(3)].as_ref())
+        .split(area);
+    {
+        let chunks = Layout::default()
+            .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+            .direction(Direction::Horizontal)
+            .split(chunks[0]);
+        let items: Vec<ListItem> = tab.mbeans.items.iter().map(|i| ListItem::new(i.to_owned())).collect();
+        let list = List::new(items)
+            .block(Block::default()
+                .borders(Borders::ALL)
+                .title(Span::styled("MBeans (press <Enter> to load attributes)", Style::default().fg(Color::Cyan))))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            .highlight_symbol(">");
+        f.render_stateful_widget(list, chunks[0], &mut tab.mbeans.state);
+        {
+            let chunks = Layout::default()
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .split(chunks[1]);
+            draw_mbean_attributes(f, tab, chunks[0]);
+            draw_pinned_attributes_chart(f, tab, chunks[1]);
+        }
+    }
+    draw_text(f, health, chunks[1]);
+}
+
+fn draw_mbean_attributes<B>(f: &mut Frame<B>, tab: &mut MBeansTab, area: Rect)
+    where B: Backend,
+{
+    let items: Vec<ListItem> = tab.attributes.items.iter()
+        .map(|a| {
+            let pin = if tab.is_pinned(a) { "* " } else { "  " };
+            ListItem::new(format!("{}{} = {}", pin, a.name, a.value))
+        })
+        .collect();
+    let title = match &tab.selected_mbean {
+        Some(m) => format!("{} (<PageUp>/<PageDown> to select, <P> to pin to chart)", m.name),
+        None => "Attributes".to_owned(),
+    };
+    let list = List::new(items)
+        .block(Block::default()
+            .borders(Borders::ALL)
+            .title(Span::styled(title, Style::default().fg(Color::Cyan))))
+        .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+        .highlight_symbol(">");
+    f.render_stateful_widget(list, area, &mut tab.attributes.state);
+}
+
+fn draw_pinned_attributes_chart<B>(f: &mut Frame<B>, tab: &MBeansTab, area: Rect)
+    where B: Backend,
+{
+    const COLORS: [Color; 6] = [Color::Green, Color::Yellow, Color::LightBlue, Color::Red, Color::Magenta, Color::White];
+
+    let series: Vec<(String, Vec<(f64, f64)>)> = tab.pinned.iter()
+        .map(|p| {
+            let data = tab.pinned_values.get(p).map_or(vec![], |values| {
+                values.iter().enumerate().map(|(i, v)| (i as f64, *v)).collect()
+            });
+            (p.attribute.to_owned(), data)
+        })
+        .collect();
+    let datasets: Vec<Dataset> = series.iter().enumerate()
+        .map(|(i, (name, data))| Dataset::default()
+            .name(name.as_str())
+            .marker(Marker::Braille)
+            .style(Style::default().fg(COLORS[i % COLORS.len()]))
+            .data(data))
+        .collect();
+
+    let values = || series.iter().flat_map(|(_, data)| data.iter().map(|(_, v)| *v));
+    let min = values().fold(0.0, f64::min);
+    let max = values().fold(0.0, f64::max);
+    let label = vec![format!("{}", min), format!("{}", (min + max) / 2.0), format!("{}", max)];
+    let c = Chart::new(datasets)
+        .block(
+            Block::default()
+                .title(Span::styled("Pinned attributes", Style::default().fg(Color::Cyan)))
+                .borders(Borders::ALL)
+        )
+        .x_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(vec![
+                    Span::styled("older", Style::default().add_modifier(Modifier::ITALIC)),
+                    Span::styled("recent", Style::default().add_modifier(Modifier::ITALIC))
+                ])
+                .bounds([0.0, MBeansTab::MAX_PINNED_MEASURES as f64])
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(label.into_iter().map(|l| Span::styled(l, Style::default().add_modifier(Modifier::ITALIC))).collect())
+                .bounds([min, max + 1.0])
+        );
+    f.render_widget(c, area);
+}
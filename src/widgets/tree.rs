@@ -1,6 +1,11 @@
 use std::collections::hash_map::HashMap;
+use std::collections::HashSet;
+
+use tui::widgets::ListState;
+
 use crate::zio::model::Fiber;
 use crate::akka::model::ActorTreeNode;
+use crate::jmx::model::MBeanTreeNode;
 
 pub trait TreeWidgetNode {
     fn id(&self) -> usize;
@@ -18,7 +23,10 @@ impl TreeWidgetNode for Fiber {
     }
 
     fn label(&self) -> String {
-        format!("{:?}", self.status)
+        match self.age() {
+            Some(age) => format!("{:?} {}", self.status, age),
+            None => format!("{:?}", self.status),
+        }
     }
 }
 
@@ -36,6 +44,211 @@ impl TreeWidgetNode for ActorTreeNode {
     }
 }
 
+impl TreeWidgetNode for MBeanTreeNode {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn parent_id(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn label(&self) -> String {
+        self.label.to_owned()
+    }
+}
+
+/// Rows of a tree, with nodes that can be collapsed to hide their children.
+/// Collapsed nodes are kept by node id, so they survive `replace`. Selection is up to the list that shows the rows.
+pub struct Tree<T> {
+    nodes: Vec<T>,
+    /// Parent of every node, by node id.
+    parents: HashMap<usize, Option<usize>>,
+    /// Formatted rows of the nodes that aren't hidden under a collapsed one.
+    pub rows: Vec<(String, T)>,
+    collapsed: HashSet<usize>,
+    print_ids: bool,
+}
+
+impl<T: Clone + TreeWidgetNode> Tree<T> {
+    pub fn new(print_ids: bool) -> Tree<T> {
+        Tree {
+            nodes: vec![],
+            parents: HashMap::new(),
+            rows: vec![],
+            collapsed: HashSet::new(),
+            print_ids,
+        }
+    }
+
+    /// Replaces the nodes. Collapsed nodes stay collapsed, even if they go missing for a while.
+    pub fn replace(&mut self, nodes: Vec<T>) {
+        self.parents = nodes.iter().map(|n| (n.id(), n.parent_id())).collect();
+        self.nodes = nodes;
+        self.reload();
+    }
+
+    pub fn nodes(&self) -> &[T] {
+        &self.nodes
+    }
+
+    /// Collapses an expanded node or expands a collapsed one. Nodes without children are left as they are.
+    pub fn toggle(&mut self, id: usize) {
+        if !self.collapsed.remove(&id) && self.nodes.iter().any(|n| n.parent_id() == Some(id)) {
+            self.collapsed.insert(id);
+        }
+        self.reload();
+    }
+
+    pub fn expand_all(&mut self) {
+        self.collapsed.clear();
+        self.reload();
+    }
+
+    /// Collapses every node with children, so that only the roots are left.
+    pub fn collapse_all(&mut self) {
+        self.collapsed = self.nodes.iter().filter_map(|n| n.parent_id()).filter(|p| self.parents.contains_key(p)).collect();
+        self.reload();
+    }
+
+    /// The node itself if it's visible, or its topmost collapsed ancestor otherwise.
+    /// Parents that aren't among the nodes are ignored, as their children are listed as roots.
+    pub fn visible_ancestor(&self, id: usize) -> usize {
+        let mut visible = id;
+        let parent = |id: &usize| self.parents.get(id).copied().flatten().filter(|p| self.parents.contains_key(p));
+        let mut current = parent(&id);
+        // the length bound guards against cycles in malformed input
+        for _ in 0..self.nodes.len() {
+            match current {
+                Some(p) => {
+                    if self.collapsed.contains(&p) {
+                        visible = p;
+                    }
+                    current = parent(&p);
+                }
+                None => break,
+            }
+        }
+        visible
+    }
+
+    fn reload(&mut self) {
+        let mut child_counts: HashMap<usize, usize> = HashMap::new();
+        for p in self.nodes.iter().filter_map(|n| n.parent_id()) {
+            *child_counts.entry(p).or_insert(0) += 1;
+        }
+        let visible: Vec<T> = self.nodes.iter().filter(|n| self.visible_ancestor(n.id()) == n.id()).cloned().collect();
+        self.rows = tree_list_widget(visible, self.print_ids)
+            .into_iter()
+            .map(|(label, n)| match child_counts.get(&n.id()) {
+                Some(count) if self.collapsed.contains(&n.id()) => (format!("{} [+{}]", label, count), n),
+                _ => (label, n),
+            })
+            .collect();
+    }
+}
+
+/// A tree that is navigated row by row or by level. Selection is kept by node id, so it survives `replace`.
+pub struct StatefulTree<T> {
+    pub tree: Tree<T>,
+    pub state: ListState,
+}
+
+impl<T: Clone + TreeWidgetNode> StatefulTree<T> {
+    pub fn new(print_ids: bool) -> StatefulTree<T> {
+        StatefulTree { tree: Tree::new(print_ids), state: ListState::default() }
+    }
+
+    /// Replaces the nodes, keeping the selected node if it's still there.
+    pub fn replace(&mut self, nodes: Vec<T>) {
+        let selected = self.selected_id();
+        self.tree.replace(nodes);
+        self.reselect(selected);
+    }
+
+    pub fn nodes(&self) -> &[T] {
+        self.tree.nodes()
+    }
+
+    pub fn rows(&self) -> &[(String, T)] {
+        &self.tree.rows
+    }
+
+    pub fn selected(&self) -> Option<&T> {
+        self.state.selected().and_then(|n| self.tree.rows.get(n)).map(|r| &r.1)
+    }
+
+    pub fn selected_id(&self) -> Option<usize> {
+        self.selected().map(|n| n.id())
+    }
+
+    /// Selects a node, or its closest visible ancestor if it's hidden. Returns false if there is no such node.
+    pub fn select(&mut self, id: usize) -> bool {
+        let id = self.tree.visible_ancestor(id);
+        match self.tree.rows.iter().position(|r| r.1.id() == id) {
+            Some(n) => {
+                self.state.select(Some(n));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn next(&mut self) {
+        let rows = self.tree.rows.len();
+        if rows > 0 {
+            let n = self.state.selected().map_or(0, |n| (n + 1) % rows);
+            self.state.select(Some(n));
+        }
+    }
+
+    pub fn previous(&mut self) {
+        let rows = self.tree.rows.len();
+        if rows > 0 {
+            let n = self.state.selected().map_or(0, |n| if n == 0 { rows - 1 } else { n - 1 });
+            self.state.select(Some(n));
+        }
+    }
+
+    /// Selects the parent of the selected node, if it has one.
+    pub fn select_parent(&mut self) {
+        if let Some(parent) = self.selected().and_then(|n| n.parent_id()) {
+            self.select(parent);
+        }
+    }
+
+    pub fn toggle(&mut self, id: usize) {
+        let selected = self.selected_id();
+        self.tree.toggle(id);
+        self.reselect(selected);
+    }
+
+    pub fn toggle_selected(&mut self) {
+        if let Some(id) = self.selected_id() {
+            self.toggle(id);
+        }
+    }
+
+    pub fn expand_all(&mut self) {
+        let selected = self.selected_id();
+        self.tree.expand_all();
+        self.reselect(selected);
+    }
+
+    pub fn collapse_all(&mut self) {
+        let selected = self.selected_id();
+        self.tree.collapse_all();
+        self.reselect(selected);
+    }
+
+    fn reselect(&mut self, selected: Option<usize>) {
+        let selected = selected.is_some_and(|id| self.select(id));
+        if !selected {
+            self.state.select(if self.tree.rows.is_empty() { None } else { Some(0) });
+        }
+    }
+}
+
 ///
 /// Given a list of items returns a list of formatted labels ready to be printed as a tree.
 ///
@@ -145,6 +358,7 @@ fn list_tree_nodes<T: Clone + TreeWidgetNode>(
 ///
 /// Converts a list of items into a Map of (parent -> list of children).
 /// The parent can be None, which indicates that the children are root nodes.
+/// Items whose parent isn't in the list, e.g. in a partial fiber dump, are root nodes as well.
 ///
 /// eg. A list of items:
 ///       (id: 0, parent_id: None)
@@ -161,9 +375,119 @@ fn list_tree_nodes<T: Clone + TreeWidgetNode>(
 ///       _ -> (0,7,6)
 ///       4 -> 5
 fn make_tree<T: Clone + TreeWidgetNode>(items: Vec<T>) -> HashMap<Option<usize>, Vec<T>> {
+    let ids: HashSet<usize> = items.iter().map(|i| i.id()).collect();
     items.iter().fold(HashMap::new(), |mut acc, f| {
-        let v = acc.entry(f.parent_id()).or_insert(vec![]);
+        let v = acc.entry(f.parent_id().filter(|p| ids.contains(p))).or_insert(vec![]);
         v.push(f.to_owned());
         acc
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::akka::model::ActorTreeNode;
+    use crate::widgets::tree::StatefulTree;
+
+    fn node(id: usize, parent: Option<usize>, name: &str) -> ActorTreeNode {
+        ActorTreeNode { name: name.to_owned(), parent, id }
+    }
+
+    fn actors() -> Vec<ActorTreeNode> {
+        vec![
+            node(1, None, "user"),
+            node(2, Some(1), "orders"),
+            node(3, Some(2), "worker-1"),
+            node(4, Some(2), "worker-2"),
+            node(5, Some(1), "payments"),
+            node(6, None, "system"),
+        ]
+    }
+
+    fn labels(tree: &StatefulTree<ActorTreeNode>) -> Vec<String> {
+        tree.rows().iter().map(|r| r.0.split_whitespace().collect::<Vec<&str>>().join(" ")).collect()
+    }
+
+    #[test]
+    fn collapsed_nodes_hide_their_children_and_show_how_many_they_have() {
+        let mut tree = StatefulTree::new(false);
+        tree.replace(actors());
+        assert_eq!(tree.rows().len(), 6);
+
+        tree.select(2);
+        tree.toggle_selected();
+        assert_eq!(labels(&tree), vec!["├─# user", "│ ├─# orders [+2]", "│ └─# payments", "└─# system"]);
+        assert_eq!(tree.selected_id(), Some(2));
+
+        tree.toggle_selected();
+        assert_eq!(tree.rows().len(), 6);
+
+        // leaves can't be collapsed
+        tree.toggle(3);
+        assert_eq!(tree.rows().len(), 6);
+    }
+
+    #[test]
+    fn collapse_all_leaves_roots_and_selects_the_root_of_the_selected_node() {
+        let mut tree = StatefulTree::new(false);
+        tree.replace(actors());
+        tree.select(4);
+
+        tree.collapse_all();
+        assert_eq!(labels(&tree), vec!["├─# user [+2]", "└─# system"]);
+        assert_eq!(tree.selected_id(), Some(1));
+
+        tree.expand_all();
+        assert_eq!(tree.rows().len(), 6);
+        assert_eq!(tree.selected_id(), Some(1));
+    }
+
+    #[test]
+    fn nodes_with_missing_parents_are_listed_as_roots() {
+        let mut tree = StatefulTree::new(false);
+        tree.replace(vec![node(1, None, "user"), node(3, Some(2), "worker-1"), node(4, Some(3), "job")]);
+        assert_eq!(labels(&tree), vec!["├─# user", "└─# worker-1", "└─# job"]);
+
+        tree.collapse_all();
+        assert_eq!(labels(&tree), vec!["├─# user", "└─# worker-1 [+1]"]);
+
+        tree.select(4);
+        tree.select_parent();
+        assert_eq!(tree.selected_id(), Some(3));
+    }
+
+    #[test]
+    fn select_parent_goes_up_a_level() {
+        let mut tree = StatefulTree::new(false);
+        tree.replace(actors());
+        tree.select(4);
+
+        tree.select_parent();
+        assert_eq!(tree.selected_id(), Some(2));
+        tree.select_parent();
+        assert_eq!(tree.selected_id(), Some(1));
+        tree.select_parent();
+        assert_eq!(tree.selected_id(), Some(1));
+    }
+
+    #[test]
+    fn selection_and_collapsed_nodes_are_kept_by_id_across_refreshes() {
+        let mut tree = StatefulTree::new(false);
+        tree.replace(actors());
+        tree.toggle(2);
+        tree.select(5);
+
+        let mut refreshed = actors();
+        refreshed.insert(0, node(7, None, "remote"));
+        tree.replace(refreshed);
+        assert_eq!(tree.selected_id(), Some(5));
+        assert_eq!(tree.rows().len(), 5);
+
+        // the selected node is gone, so the first one is selected
+        tree.replace(vec![node(1, None, "user"), node(6, None, "system")]);
+        assert_eq!(tree.selected_id(), Some(1));
+
+        // nodes that come back are still collapsed
+        tree.replace(actors());
+        assert_eq!(tree.rows().len(), 4);
+    }
+}
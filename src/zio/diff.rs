@@ -0,0 +1,51 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::zio::model::{Fiber, FiberStatus};
+
+/// Changes between the last two fiber dumps, used to hunt for fiber leaks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FiberDiff {
+    pub new: Vec<Fiber>,
+    /// Fibers that are gone or done, as they were last seen.
+    pub finished: Vec<Fiber>,
+    /// Fibers that changed status or suspension site, as they were before and as they are now.
+    pub changed: Vec<(Fiber, Fiber)>,
+    /// Fibers that are in every snapshot and still aren't done, the oldest first. Changed fibers are left out,
+    /// so that every fiber is listed once.
+    pub long_lived: Vec<Fiber>,
+}
+
+impl FiberDiff {
+    /// Compares the last snapshot with the one before it. Nothing can be compared with fewer than two snapshots.
+    pub fn of(snapshots: &VecDeque<Vec<Fiber>>) -> Option<FiberDiff> {
+        let mut recent = snapshots.iter().rev();
+        let (current, previous) = match (recent.next(), recent.next()) {
+            (Some(c), Some(p)) => (c, p),
+            _ => return None,
+        };
+        let before: HashMap<usize, &Fiber> = previous.iter().map(|f| (f.id, f)).collect();
+        let now: HashSet<usize> = current.iter().map(|f| f.id).collect();
+
+        let mut diff = FiberDiff { new: vec![], finished: vec![], changed: vec![], long_lived: vec![] };
+        for f in previous.iter().filter(|f| f.status != FiberStatus::Done && !now.contains(&f.id)) {
+            diff.finished.push(f.clone());
+        }
+        for f in current {
+            match before.get(&f.id) {
+                None => diff.new.push(f.clone()),
+                Some(b) if f.status == FiberStatus::Done && b.status != FiberStatus::Done => diff.finished.push(f.clone()),
+                Some(b) if b.status != f.status || b.suspended_at != f.suspended_at => diff.changed.push(((*b).clone(), f.clone())),
+                Some(_) => {}
+            }
+        }
+
+        let ids: Vec<HashSet<usize>> = snapshots.iter().map(|s| s.iter().map(|f| f.id).collect()).collect();
+        let changed: HashSet<usize> = diff.changed.iter().map(|(_, f)| f.id).collect();
+        diff.long_lived = current.iter()
+            .filter(|f| f.status != FiberStatus::Done && !changed.contains(&f.id) && ids.iter().all(|s| s.contains(&f.id)))
+            .cloned()
+            .collect();
+        diff.long_lived.sort_by_key(|f| std::cmp::Reverse(f.lifetime_ms));
+        Some(diff)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::zio::model::{Fiber, FiberStatus};
+use crate::zio::model::{Fiber, FiberStatus, TraceElement};
 
 ///
 /// Takes a fiber dump string and parses it into Fiber model.
@@ -6,9 +6,11 @@ use crate::zio::model::{Fiber, FiberStatus};
 /// Expects a string where two first lines are of the following format:
 ///
 ///   ```
-///   #4 (7h432m25965s25965835ms)
-///   Status: Running()
+///   #4 (7h432m25965s25965835ms) waiting on #3
+///   Status: Suspended(interruptible, 12 asyncs, zio.Promise.await(Promise.scala:50))
 ///   ```
+///
+/// followed by the fiber's own stack and execution traces and then the traces of the fiber that spawned it.
 pub fn parse_fiber_dump(dump: String) -> Option<Fiber> {
     let fib_str: Vec<&str> = dump.trim().lines().take(2).collect();
 
@@ -48,7 +50,152 @@ pub fn parse_fiber_dump(dump: String) -> Option<Fiber> {
         };
 
     match (id, status) {
-        (Some(id), Some(status)) => Some(Fiber { id, parent_id, status, dump }),
+        (Some(id), Some(status)) => {
+            let (interruptible, asyncs, suspended_at) = parse_suspension(status_line);
+            let (execution_trace, stack_trace) = parse_traces(&dump);
+            Some(Fiber {
+                lifetime_ms: parse_lifetime(fib_str[0]),
+                waiting_on: parse_waiting_on(fib_str[0]),
+                interruptible,
+                asyncs,
+                suspended_at,
+                execution_trace,
+                stack_trace,
+                ..Fiber::new(id, parent_id, status, dump)
+            })
+        }
         _ => None
     }
 }
+
+/// Fibers read from a text with several fiber dumps, along with the blocks that couldn't be read.
+#[derive(Debug, PartialEq)]
+pub struct FiberDumps {
+    pub fibers: Vec<Fiber>,
+    /// First lines of the blocks that were skipped, e.g. `#broken` or a fiber with an unknown status.
+    pub skipped: Vec<String>,
+}
+
+impl FiberDumps {
+    /// Tells how many blocks were skipped, if any.
+    pub fn skipped_notice(&self) -> Option<String> {
+        match self.skipped.as_slice() {
+            [] => None,
+            [first, ..] => Some(format!("Skipped {} block(s) that aren't fiber dumps, the first one starts with: {}", self.skipped.len(), first)),
+        }
+    }
+}
+
+///
+/// Splits a text with several fiber dumps, e.g. the output of `Fiber.dumpAll` pasted from logs,
+/// into individual fibers. Every dump starts with a `#<id> (<lifetime>)` line, anything before the first one is ignored.
+/// Other lines starting with `#` start a block that is skipped, rather than being added to the previous dump.
+pub fn parse_fiber_dumps(text: &str) -> FiberDumps {
+    let mut dumps: Vec<Vec<&str>> = vec![];
+    for line in text.lines() {
+        if line.trim_start().starts_with('#') {
+            dumps.push(vec![line.trim_start()]);
+        } else if let Some(dump) = dumps.last_mut() {
+            dump.push(line);
+        }
+    }
+    let mut result = FiberDumps { fibers: vec![], skipped: vec![] };
+    for lines in dumps {
+        let fiber = if is_dump_header(lines[0]) { parse_fiber_dump(lines.join("\n").trim_end().to_owned()) } else { None };
+        match fiber {
+            Some(f) => result.fibers.push(f),
+            None => result.skipped.push(lines[0].to_owned()),
+        }
+    }
+    result
+}
+
+fn is_dump_header(line: &str) -> bool {
+    let rest = match line.trim_start().strip_prefix('#') {
+        Some(r) => r,
+        None => return false,
+    };
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    digits > 0 && rest[digits..].starts_with(" (")
+}
+
+/// Every unit of the lifetime counts the whole time, e.g. `(1m98s98260ms)`, so milliseconds are enough.
+fn parse_lifetime(header: &str) -> Option<u64> {
+    let lifetime = header.get(header.find('(')? + 1..header.find(')')?)?;
+    lifetime.strip_suffix("ms")?
+        .rsplit(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|ms| ms.parse().ok())
+}
+
+/// Ids listed after `waiting on`, e.g. `waiting on #3, #5`.
+fn parse_waiting_on(header: &str) -> Vec<usize> {
+    match header.find("waiting on") {
+        Some(n) => header[n + "waiting on".len()..].split(',')
+            .filter_map(|id| id.trim().trim_start_matches('#').parse().ok())
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Arguments of `Suspended(interruptible, 18 asyncs, zio.Promise.await(Promise.scala:50))`.
+fn parse_suspension(status_line: &str) -> (Option<bool>, Option<u64>, Option<TraceElement>) {
+    let args = status_line.find("Suspended(")
+        .and_then(|n| status_line[n + "Suspended(".len()..].trim_end().strip_suffix(')'));
+    let args: Vec<&str> = match args {
+        Some(a) => a.splitn(3, ',').map(|a| a.trim()).collect(),
+        None => return (None, None, None),
+    };
+    let interruptible = args.first().and_then(|a| match *a {
+        "interruptible" => Some(true),
+        "uninterruptible" => Some(false),
+        _ => None,
+    });
+    let asyncs = args.get(1)
+        .and_then(|a| a.strip_suffix("asyncs"))
+        .and_then(|a| a.trim().parse().ok());
+    let location = args.get(2).and_then(|a| parse_location(a));
+    (interruptible, asyncs, location)
+}
+
+/// Execution and stack traces of the fiber itself, leaving out the traces of its ancestors.
+fn parse_traces(dump: &str) -> (Vec<TraceElement>, Vec<TraceElement>) {
+    let mut execution = vec![];
+    let mut stack = vec![];
+    let mut section: Option<&mut Vec<TraceElement>> = None;
+    for line in dump.trim().lines().skip(2) {
+        if line.contains("was spawned by") {
+            break;
+        } else if line.contains("was supposed to continue to") {
+            section = Some(&mut stack);
+        } else if line.to_ascii_lowercase().contains("execution trace") {
+            section = Some(&mut execution);
+        } else if let Some(trace) = section.as_mut() {
+            let line = line.trim();
+            let entry = line.strip_prefix("a future continuation at ").or_else(|| line.strip_prefix("at "));
+            if let Some(e) = entry.and_then(parse_location) {
+                trace.push(e);
+            }
+        }
+    }
+    (execution, stack)
+}
+
+/// A location like `zio.ZIO.zipWith(ZIO.scala:1138)`.
+fn parse_location(s: &str) -> Option<TraceElement> {
+    let open = s.find('(')?;
+    let close = open + s[open..].find(')')?;
+    let name = s[..open].trim();
+    let dot = name.rfind('.')?;
+    let source = &s[open + 1..close];
+    let (file, line) = match source.rfind(':') {
+        Some(n) => (&source[..n], source[n + 1..].parse().ok()),
+        None => (source, None),
+    };
+    Some(TraceElement {
+        class_name: name[..dot].to_owned(),
+        method_name: name[dot + 1..].to_owned(),
+        file_name: if file.is_empty() || file == "Unknown Source" { None } else { Some(file.to_owned()) },
+        line_number: line,
+    })
+}
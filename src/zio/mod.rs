@@ -1,4 +1,5 @@
 pub mod model;
 pub mod zmx;
-mod dump_parser;
+pub mod diff;
+pub mod dump_parser;
 mod tests;
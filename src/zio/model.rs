@@ -1,20 +1,125 @@
 use std::fmt::{Display, Formatter, Result};
+use std::time::Duration;
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+use humantime::format_duration;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct Fiber {
     pub id: usize,
     pub parent_id: Option<usize>,
     pub status: FiberStatus,
+    /// Time since the fiber was started.
+    #[serde(default)]
+    pub lifetime_ms: Option<u64>,
+    /// Ids of the fibers this one is waiting on.
+    #[serde(default)]
+    pub waiting_on: Vec<usize>,
+    /// Only known for suspended fibers.
+    #[serde(default)]
+    pub interruptible: Option<bool>,
+    /// Number of asynchronous operations a suspended fiber went through.
+    #[serde(default)]
+    pub asyncs: Option<u64>,
+    /// Where a suspended fiber is waiting, e.g. `zio.Promise.await(Promise.scala:50)`.
+    #[serde(default)]
+    pub suspended_at: Option<TraceElement>,
+    /// Recently executed steps, the most recent first.
+    #[serde(default)]
+    pub execution_trace: Vec<TraceElement>,
+    /// Continuations the fiber is supposed to run next.
+    #[serde(default)]
+    pub stack_trace: Vec<TraceElement>,
     pub dump: String,
 }
 
+impl Fiber {
+    /// A fiber with nothing known besides its status and dump.
+    pub fn new(id: usize, parent_id: Option<usize>, status: FiberStatus, dump: String) -> Fiber {
+        Fiber {
+            id,
+            parent_id,
+            status,
+            lifetime_ms: None,
+            waiting_on: vec![],
+            interruptible: None,
+            asyncs: None,
+            suspended_at: None,
+            execution_trace: vec![],
+            stack_trace: vec![],
+            dump,
+        }
+    }
+
+    /// Lifetime in whole seconds, e.g. `1m 38s`.
+    pub fn age(&self) -> Option<String> {
+        self.lifetime_ms.map(|ms| format_duration(Duration::from_secs(ms / 1000)).to_string())
+    }
+
+    /// Status along with the suspension site, if known.
+    pub fn state(&self) -> String {
+        match &self.suspended_at {
+            Some(site) => format!("{} at {}", self.status, site),
+            None => self.status.to_string(),
+        }
+    }
+
+    /// The parsed parts of the dump, followed by the dump itself.
+    pub fn details(&self) -> String {
+        let mut s = format!("#{} {}", self.id, self.status);
+        if let Some(age) = self.age() {
+            s.push_str(&format!(", started {} ago", age));
+        }
+        if !self.waiting_on.is_empty() {
+            let ids: Vec<String> = self.waiting_on.iter().map(|id| format!("#{}", id)).collect();
+            s.push_str(&format!("\nWaiting on: {}", ids.join(", ")));
+        }
+        match (self.interruptible, self.asyncs) {
+            (Some(i), Some(a)) => s.push_str(&format!("\n{}, {} asyncs", if i { "Interruptible" } else { "Uninterruptible" }, a)),
+            (Some(i), None) => s.push_str(if i { "\nInterruptible" } else { "\nUninterruptible" }),
+            (None, Some(a)) => s.push_str(&format!("\n{} asyncs", a)),
+            (None, None) => {}
+        }
+        if let Some(location) = &self.suspended_at {
+            s.push_str(&format!("\nSuspended at: {}", location));
+        }
+        for (title, trace) in [("Stack trace", &self.stack_trace), ("Execution trace", &self.execution_trace)].iter() {
+            if !trace.is_empty() {
+                s.push_str(&format!("\n\n{}:", title));
+                trace.iter().for_each(|e| s.push_str(&format!("\n    at {}", e)));
+            }
+        }
+        format!("{}\n\nDump:\n{}", s, self.dump)
+    }
+}
+
 impl Display for Fiber {
     fn fmt(&self, f: &mut Formatter) -> Result {
         write!(f, "{} {:?} {}", self.id, self.parent_id, self.status)
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+/// A step of a fiber trace, e.g. `zio.ZIO.zipWith(ZIO.scala:1138)`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct TraceElement {
+    pub class_name: String,
+    pub method_name: String,
+    pub file_name: Option<String>,
+    pub line_number: Option<u32>,
+}
+
+impl Display for TraceElement {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{}.{}", self.class_name, self.method_name)?;
+        match (&self.file_name, self.line_number) {
+            (Some(file), Some(line)) => write!(f, "({}:{})", file, line),
+            (Some(file), None) => write!(f, "({})", file),
+            (None, _) => write!(f, "(Unknown Source)"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum FiberStatus {
     Done,
     Finishing,
@@ -28,7 +133,7 @@ impl Display for FiberStatus {
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub struct FiberCount {
     pub done: i32,
     pub finishing: i32,
@@ -37,6 +142,19 @@ pub struct FiberCount {
 }
 
 impl FiberCount {
+    pub fn from_fibers(fibers: &[Fiber]) -> FiberCount {
+        let mut count = FiberCount { done: 0, suspended: 0, running: 0, finishing: 0 };
+        for f in fibers.iter() {
+            match f.status {
+                FiberStatus::Done => { count.done += 1 }
+                FiberStatus::Finishing => { count.finishing += 1 }
+                FiberStatus::Running => { count.running += 1 }
+                FiberStatus::Suspended => { count.suspended += 1 }
+            }
+        }
+        count
+    }
+
     pub fn total(&self) -> i32 {
         self.done + self.finishing + self.running + self.suspended
     }
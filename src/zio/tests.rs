@@ -7,10 +7,8 @@ fn dump_parser_done() {
     Status: Done()";
 
     let result = Fiber {
-        id: 4,
-        parent_id: None,
-        status: FiberStatus::Done,
-        dump: dump.to_owned(),
+        lifetime_ms: Some(25965835),
+        ..Fiber::new(4, None, FiberStatus::Done, dump.to_owned())
     };
     assert_eq!(parse_fiber_dump(dump.to_owned()), Some(result));
 }
@@ -18,7 +16,7 @@ fn dump_parser_done() {
 #[test]
 fn dump_parser_suspended_with_parent() {
     use crate::zio::dump_parser::parse_fiber_dump;
-    use crate::zio::model::{Fiber, FiberStatus};
+    use crate::zio::model::{Fiber, FiberStatus, TraceElement};
 
     let dump = "#2 (1m98s98260ms) waiting on #2
     Status: Suspended(interruptible, 18 asyncs, zio.Promise.await(Promise.scala:50))
@@ -27,10 +25,17 @@ fn dump_parser_suspended_with_parent() {
     Fiber:Id(1588237280394,1) was supposed to continue to: ";
 
     let result = Fiber {
-        id: 2,
-        parent_id: Some(1),
-        status: FiberStatus::Suspended,
-        dump: dump.to_owned(),
+        lifetime_ms: Some(98260),
+        waiting_on: vec![2],
+        interruptible: Some(true),
+        asyncs: Some(18),
+        suspended_at: Some(TraceElement {
+            class_name: "zio.Promise".to_owned(),
+            method_name: "await".to_owned(),
+            file_name: Some("Promise.scala".to_owned()),
+            line_number: Some(50),
+        }),
+        ..Fiber::new(2, Some(1), FiberStatus::Suspended, dump.to_owned())
     };
     assert_eq!(parse_fiber_dump(dump.to_owned()), Some(result));
 }
@@ -44,10 +49,8 @@ fn dump_parser_running() {
     Status: Running()";
 
     let result = Fiber {
-        id: 3,
-        parent_id: None,
-        status: FiberStatus::Running,
-        dump: dump.to_owned(),
+        lifetime_ms: Some(96402),
+        ..Fiber::new(3, None, FiberStatus::Running, dump.to_owned())
     };
     assert_eq!(parse_fiber_dump(dump.to_owned()), Some(result));
 }
@@ -61,14 +64,104 @@ fn dump_parser_finishing() {
     Status: Finishing()";
 
     let result = Fiber {
-        id: 3,
-        parent_id: None,
-        status: FiberStatus::Finishing,
-        dump: dump.to_owned(),
+        lifetime_ms: Some(96402),
+        ..Fiber::new(3, None, FiberStatus::Finishing, dump.to_owned())
     };
     assert_eq!(parse_fiber_dump(dump.to_owned()), Some(result));
 }
 
+#[test]
+fn dump_parser_reads_own_traces_only() {
+    use crate::zio::dump_parser::parse_fiber_dump;
+    use crate::zio::model::TraceElement;
+
+    let dump = "#7 (2s2460ms) waiting on #3, #5
+   Status: Suspended(uninterruptible, 2 asyncs, zio.ZIO.zipWithPar(ZIO.scala:1820))
+Fiber:Id(1605261812384,7) was supposed to continue to:
+  a future continuation at zio.ZIO.zipWithPar(ZIO.scala:1820)
+  a future continuation at com.acme.OrderService.place(OrderService.scala:42)
+
+Fiber:Id(1605261812384,7) execution trace:
+  at zio.ZIO.fork(ZIO.scala:553)
+  at com.acme.Inventory.reserve(Inventory.scala:17)
+  at <couldn't get location, error: java.lang.ClassNotFoundException>
+
+Fiber:Id(1605261812384,7) was spawned by:
+
+Fiber:Id(1605261812111,1) was supposed to continue to:
+  a future continuation at zio.App.main(App.scala:57)
+
+Fiber:Id(1605261812111,1) execution trace:
+  at zio.App.run(App.scala:40)
+
+Fiber:Id(1605261812111,1) was spawned by: <empty trace>";
+
+    let element = |class_name: &str, method_name: &str, file_name: &str, line_number: u32| TraceElement {
+        class_name: class_name.to_owned(),
+        method_name: method_name.to_owned(),
+        file_name: Some(file_name.to_owned()),
+        line_number: Some(line_number),
+    };
+
+    let fiber = parse_fiber_dump(dump.to_owned()).unwrap();
+    assert_eq!(fiber.id, 7);
+    assert_eq!(fiber.parent_id, Some(1));
+    assert_eq!(fiber.lifetime_ms, Some(2460));
+    assert_eq!(fiber.waiting_on, vec![3, 5]);
+    assert_eq!(fiber.interruptible, Some(false));
+    assert_eq!(fiber.asyncs, Some(2));
+    assert_eq!(fiber.suspended_at, Some(element("zio.ZIO", "zipWithPar", "ZIO.scala", 1820)));
+    assert_eq!(fiber.stack_trace, vec![
+        element("zio.ZIO", "zipWithPar", "ZIO.scala", 1820),
+        element("com.acme.OrderService", "place", "OrderService.scala", 42),
+    ]);
+    assert_eq!(fiber.execution_trace, vec![
+        element("zio.ZIO", "fork", "ZIO.scala", 553),
+        element("com.acme.Inventory", "reserve", "Inventory.scala", 17),
+    ]);
+    assert!(fiber.details().starts_with("#7 Suspended, started 2s ago\n\
+        Waiting on: #3, #5\n\
+        Uninterruptible, 2 asyncs\n\
+        Suspended at: zio.ZIO.zipWithPar(ZIO.scala:1820)\n\n\
+        Stack trace:\n    at zio.ZIO.zipWithPar(ZIO.scala:1820)"));
+}
+
+#[test]
+fn dump_parser_splits_concatenated_dumps() {
+    use crate::zio::dump_parser::parse_fiber_dumps;
+
+    let text = "2020-10-20 15:45:01 INFO  Dumping fibers
+#1 (5s5120ms)
+   Status: Suspended(interruptible, 1 asyncs, zio.App.main(App.scala:57))
+Fiber:Id(1605261812111,1) was supposed to continue to: <empty trace>
+
+Fiber:Id(1605261812111,1) ZIO Execution trace: <empty trace>
+
+Fiber:Id(1605261812111,1) was spawned by: <empty trace>
+  #2 (1s1000ms) waiting on #1
+   Status: Running()
+Fiber:Id(1605261812384,2) was spawned by:
+
+Fiber:Id(1605261812111,1) was supposed to continue to: <empty trace>
+#broken
+#3 (12ms)
+   Status: Trolling()
+";
+
+    let dumps = parse_fiber_dumps(text);
+    let fibers = &dumps.fibers;
+    assert_eq!(fibers.iter().map(|f| (f.id, f.parent_id)).collect::<Vec<_>>(), vec![(1, None), (2, Some(1))]);
+    assert!(fibers[0].dump.starts_with("#1 (5s5120ms)") && fibers[0].dump.ends_with("was spawned by: <empty trace>"));
+    assert_eq!(fibers[1].waiting_on, vec![1]);
+    // stray lines and fibers with an unknown status are reported rather than mixed into other dumps or dropped
+    assert!(fibers[1].dump.ends_with("was supposed to continue to: <empty trace>"));
+    assert_eq!(dumps.skipped, vec!["#broken", "#3 (12ms)"]);
+    assert_eq!(
+        dumps.skipped_notice(),
+        Some("Skipped 2 block(s) that aren't fiber dumps, the first one starts with: #broken".to_owned())
+    );
+}
+
 #[test]
 fn dump_parser_unknown_status() {
     use crate::zio::dump_parser::parse_fiber_dump;
@@ -84,3 +177,102 @@ fn dump_parser_not_enough_lines() {
     assert_eq!(parse_fiber_dump("#3 (1m96s96402ms)".to_owned()), None);
     assert_eq!(parse_fiber_dump("".to_owned()), None);
 }
+
+#[test]
+fn fiber_diff_compares_last_two_snapshots() {
+    use std::collections::VecDeque;
+    use crate::zio::diff::FiberDiff;
+    use crate::zio::model::{Fiber, FiberStatus, TraceElement};
+
+    let fiber = |id: usize, status: FiberStatus, lifetime_ms: u64| Fiber {
+        lifetime_ms: Some(lifetime_ms),
+        ..Fiber::new(id, None, status, format!("#{}", id))
+    };
+    let moved = Fiber {
+        suspended_at: Some(TraceElement {
+            class_name: "zio.Promise".to_owned(),
+            method_name: "await".to_owned(),
+            file_name: Some("Promise.scala".to_owned()),
+            line_number: Some(50),
+        }),
+        ..fiber(4, FiberStatus::Suspended, 3000)
+    };
+
+    let mut snapshots = VecDeque::new();
+    snapshots.push_back(vec![fiber(1, FiberStatus::Suspended, 1000), fiber(2, FiberStatus::Running, 1000), fiber(4, FiberStatus::Suspended, 1000)]);
+    assert_eq!(FiberDiff::of(&snapshots), None);
+
+    snapshots.push_back(vec![
+        fiber(1, FiberStatus::Suspended, 2000),
+        fiber(2, FiberStatus::Suspended, 2000),
+        fiber(3, FiberStatus::Running, 500),
+        fiber(4, FiberStatus::Suspended, 2000),
+        fiber(5, FiberStatus::Running, 500),
+    ]);
+    snapshots.push_back(vec![
+        fiber(1, FiberStatus::Suspended, 9000),
+        fiber(2, FiberStatus::Done, 3000),
+        moved.clone(),
+        fiber(6, FiberStatus::Running, 10),
+    ]);
+
+    let diff = FiberDiff::of(&snapshots).unwrap();
+    assert_eq!(diff.new, vec![fiber(6, FiberStatus::Running, 10)]);
+    assert_eq!(diff.finished, vec![fiber(3, FiberStatus::Running, 500), fiber(5, FiberStatus::Running, 500), fiber(2, FiberStatus::Done, 3000)]);
+    assert_eq!(diff.changed, vec![(fiber(4, FiberStatus::Suspended, 2000), moved.clone())]);
+    assert_eq!(diff.long_lived, vec![fiber(1, FiberStatus::Suspended, 9000)]);
+}
+
+#[tokio::test]
+async fn network_client_reuses_connection() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::zio::model::FiberCount;
+    use crate::zio::zmx::{NetworkZMXClient, ZMXClient};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    // serves every command on a single connection
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            if n == 0 {
+                return;
+            }
+            let dump = "#1 (10ms)\n    Status: Running()";
+            let response = format!("*1\r\n${}\r\n{}\r\n", dump.len(), dump);
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+
+    let client = NetworkZMXClient::new(address, NetworkZMXClient::TIMEOUT);
+    let expected = FiberCount { done: 0, finishing: 0, running: 1, suspended: 0 };
+    assert_eq!(client.fiber_count().await, Ok(expected.clone()));
+    assert_eq!(client.fiber_count().await, Ok(expected));
+    assert_eq!(client.dump_fibers().await.map(|f| f.len()), Ok(1));
+
+    drop(client);
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn network_client_gives_up_on_hung_server() {
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use crate::zio::zmx::{NetworkZMXClient, ZMXClient};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    // accepts the connection, but never responds
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        drop(socket);
+    });
+
+    let client = NetworkZMXClient::new(address, Duration::from_millis(200));
+    assert_eq!(client.dump_fibers().await, Err("No response from zio-zmx within 200ms".to_owned()));
+    server.abort();
+}
@@ -1,100 +1,178 @@
+use async_trait::async_trait;
 use bytes::BytesMut;
+use humantime::format_duration;
 use redis_protocol::types::Frame;
-use std::error::Error;
+use std::time::Duration;
 use tokio::io::AsyncWriteExt;
 use tokio::io::AsyncReadExt;
 use tokio::net::TcpStream;
+use tokio::sync::Mutex;
 use crate::zio::dump_parser;
-use crate::zio::model::Fiber;
+use crate::zio::model::{Fiber, FiberCount};
 
-pub trait ZMXClient {
+#[async_trait]
+pub trait ZMXClient: Send + Sync {
     fn address(&self) -> String;
-    fn dump_fibers(&self) -> Result<Vec<Fiber>, String>;
-}
+    async fn dump_fibers(&self) -> Result<Vec<Fiber>, String>;
 
-pub struct NetworkZMXClient {
-    address: String
+    /// Fiber counts by status. zio-zmx has no command for them, so they are calculated from a full fiber dump.
+    /// Clients of servers that can report the counts directly should override this.
+    async fn fiber_count(&self) -> Result<FiberCount, String> {
+        self.dump_fibers().await.map(|fibers| FiberCount::from_fibers(&fibers))
+    }
 }
 
-impl NetworkZMXClient {
-    pub fn new(address: String) -> NetworkZMXClient { NetworkZMXClient { address } }
+///
+/// A command understood by zio-zmx server, along with the way to read its response.
+///
+/// Commands are sent as RESP arrays of bulk strings, the same way redis commands are.
+/// `dump` is the only command zio-zmx servers support, new ones go next to `DumpCommand`.
+///
+pub trait ZMXCommand {
+    type Output;
+
+    fn name(&self) -> &'static str;
+    fn parse(&self, frame: Frame) -> Result<Self::Output, String>;
 
-    #[tokio::main]
-    async fn get_dump(&self) -> Result<Vec<Fiber>, Box<dyn Error>> {
-        let frame = Frame::Array(vec![Frame::BulkString("dump".into())]);
+    fn encode(&self) -> Result<BytesMut, String> {
+        let frame = Frame::Array(vec![Frame::BulkString(self.name().into())]);
         let mut buf = BytesMut::new();
+        redis_protocol::prelude::encode_bytes(&mut buf, &frame)
+            .map_err(|e| format!("Error encoding frame: {:?}", e))?;
+        Ok(buf)
+    }
+}
 
-        let _ = match redis_protocol::prelude::encode_bytes(&mut buf, &frame) {
-            Ok(l) => l,
-            Err(e) => panic!("Error encoding frame: {:?}", e)
-        };
+/// Full fiber dump, supported by all zio-zmx versions. Responds with an array of fiber dump strings.
+pub struct DumpCommand;
 
-        let mut stream = TcpStream::connect(&self.address).await?;
+impl ZMXCommand for DumpCommand {
+    type Output = Vec<Fiber>;
 
-        let _ = stream.write(&buf).await;
+    fn name(&self) -> &'static str { "dump" }
 
-        let mut buffer = String::new();
-        stream.read_to_string(&mut buffer).await?;
+    fn parse(&self, frame: Frame) -> Result<Vec<Fiber>, String> {
+        match frame {
+            Frame::Array(frames) => frames.iter().map(|f| {
+                let dump = f.as_str()
+                    .ok_or(format!("Failed to parse dump - invalid frame: {:?}", f))?;
+
+                dump_parser::parse_fiber_dump(dump.to_string())
+                    .ok_or(format!("Unknown dump format, failed to parse: {}", dump))
+            }).collect(),
+            Frame::Error(e) => Err(format!("zio-zmx responded with error: {}", e)),
+            f => Err(format!("Unexpected response to dump command: {:?}", f)),
+        }
+    }
+}
 
-        let buf: BytesMut = buffer.into();
+///
+/// Client talking to zio-zmx over a long-lived TCP connection.
+///
+/// The connection is opened on first use and reopened if the server closes it,
+/// which older servers do after every response.
+///
+pub struct NetworkZMXClient {
+    address: String,
+    /// How long a single command may take, including reconnecting. The connection is dropped after that.
+    timeout: Duration,
+    connection: Mutex<Option<TcpStream>>,
+}
 
-        let fc = match redis_protocol::prelude::decode_bytes(&buf) {
-            Ok((f, c)) => Ok((f, c)),
-            Err(e) => Err(format!("Error parsing bytes: {:?}", e))
-        };
+impl NetworkZMXClient {
+    pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(address: String, timeout: Duration) -> NetworkZMXClient {
+        NetworkZMXClient {
+            address,
+            timeout,
+            connection: Mutex::new(None),
+        }
+    }
 
-        let (frame, consumed) = fc?;
+    pub async fn send<C: ZMXCommand>(&self, command: &C) -> Result<C::Output, String> {
+        let request = command.encode()?;
+        let mut connection = self.connection.lock().await;
 
-        let mut fibers: Vec<Fiber> = vec![];
+        let frame = tokio::time::timeout(self.timeout, NetworkZMXClient::request(&mut connection, &self.address, &request))
+            .await
+            .unwrap_or_else(|_| Err(format!("No response from zio-zmx within {}", format_duration(self.timeout))));
+        if frame.is_err() {
+            *connection = None;
+        }
 
-        let parsing_result: Result<(), Box<dyn Error>> =
-            if let Some(Frame::Array(frames)) = frame {
-                let v: Result<Vec<Fiber>, String> = frames.iter().map(|f| {
-                    let dump = f.as_str()
-                        .ok_or(format!("Failed to parse dump - invalid frame: {:?}", f))?;
+        command.parse(frame?)
+    }
 
-                    dump_parser::parse_fiber_dump(dump.to_string())
-                        .ok_or(format!("Unknown dump format, failed to parse: {}", dump))
-                }).collect();
+    async fn request(connection: &mut Option<TcpStream>, address: &str, request: &[u8]) -> Result<Frame, String> {
+        if let Some(stream) = connection.as_mut() {
+            if let Ok(frame) = NetworkZMXClient::exchange(stream, request).await {
+                return Ok(frame);
+            }
+            // the server has closed the connection, try again with a fresh one
+        }
+        NetworkZMXClient::reconnect_and_exchange(connection, address, request).await
+    }
 
-                match v {
-                    Ok(fbs) => Ok(fbs.iter().for_each(|f| { fibers.push(f.to_owned()) })),
-                    Err(err) => { Err(Box::from(err.clone())) }
-                }
-            } else {
-                Err(Box::from(format!("Incomplete frame, parsed {} bytes", consumed)))
-            };
+    async fn reconnect_and_exchange(connection: &mut Option<TcpStream>, address: &str, request: &[u8]) -> Result<Frame, String> {
+        *connection = None;
+        let mut stream = TcpStream::connect(address).await.map_err(|e| e.to_string())?;
+        let frame = NetworkZMXClient::exchange(&mut stream, request).await?;
+        *connection = Some(stream);
+        Ok(frame)
+    }
 
-        parsing_result?;
+    /// Writes a request and reads frames until a complete one is received.
+    async fn exchange(stream: &mut TcpStream, request: &[u8]) -> Result<Frame, String> {
+        stream.write_all(request).await.map_err(|e| e.to_string())?;
 
-        Ok(fibers)
+        let mut buf = BytesMut::new();
+        let mut chunk = [0u8; 8192];
+        loop {
+            let n = stream.read(&mut chunk).await.map_err(|e| e.to_string())?;
+            if n == 0 {
+                return Err(format!("Connection closed, incomplete frame of {} bytes received", buf.len()));
+            }
+            buf.extend_from_slice(&chunk[..n]);
+
+            match redis_protocol::prelude::decode_bytes(&buf) {
+                Ok((Some(frame), _)) => return Ok(frame),
+                Ok((None, _)) => {}
+                Err(e) => return Err(format!("Error parsing bytes: {:?}", e)),
+            }
+        }
     }
 }
 
+#[async_trait]
 impl ZMXClient for NetworkZMXClient {
     fn address(&self) -> String {
         self.address.clone()
     }
 
-    fn dump_fibers(&self) -> Result<Vec<Fiber>, String> {
-        self.get_dump().map_err(|e| e.to_string())
+    async fn dump_fibers(&self) -> Result<Vec<Fiber>, String> {
+        self.send(&DumpCommand).await
     }
 }
 
+#[cfg(test)]
 pub struct StubZMXClient {
     pub dump: Result<Vec<Fiber>, String>
 }
 
+#[cfg(test)]
 impl StubZMXClient {
     pub fn new(dump: Result<Vec<Fiber>, String>) -> StubZMXClient { StubZMXClient { dump } }
 }
 
+#[cfg(test)]
+#[async_trait]
 impl ZMXClient for StubZMXClient {
     fn address(&self) -> String {
         "<stub>".to_owned()
     }
 
-    fn dump_fibers(&self) -> Result<Vec<Fiber>, String> {
+    async fn dump_fibers(&self) -> Result<Vec<Fiber>, String> {
         self.dump.clone()
     }
 }